@@ -0,0 +1,115 @@
+// Copyright 2025 chenjjiaa
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use anyhow::{Context, Result};
+use ethabi::{Function, Param, ParamType, StateMutability, Token};
+use ethers::{
+    prelude::*,
+    types::{Address, BlockId, TransactionRequest},
+};
+
+use crate::rpc::RpcProvider;
+
+/// A view function with unnamed parameters of the given types
+pub fn view_function(name: &str, inputs: Vec<ParamType>, outputs: Vec<ParamType>) -> Function {
+    let params = |kinds: Vec<ParamType>| {
+        kinds
+            .into_iter()
+            .map(|kind| Param {
+                name: String::new(),
+                kind,
+                internal_type: None,
+            })
+            .collect()
+    };
+
+    Function {
+        name: name.to_string(),
+        inputs: params(inputs),
+        outputs: params(outputs),
+        #[allow(deprecated)]
+        constant: None,
+        state_mutability: StateMutability::View,
+    }
+}
+
+/// Uniswap V2 factory `getPair(tokenA, tokenB)`
+pub fn get_pair_function() -> Function {
+    view_function(
+        "getPair",
+        vec![ParamType::Address, ParamType::Address],
+        vec![ParamType::Address],
+    )
+}
+
+/// Uniswap V2 pair `getReserves()`
+pub fn get_reserves_function() -> Function {
+    view_function(
+        "getReserves",
+        vec![],
+        vec![
+            ParamType::Uint(112),
+            ParamType::Uint(112),
+            ParamType::Uint(32),
+        ],
+    )
+}
+
+/// Uniswap V3 factory `getPool(tokenA, tokenB, fee)`
+pub fn get_pool_function() -> Function {
+    view_function(
+        "getPool",
+        vec![ParamType::Address, ParamType::Address, ParamType::Uint(24)],
+        vec![ParamType::Address],
+    )
+}
+
+/// Call `function` on `to` at `block` (the latest block if `None`) and decode its result
+pub async fn call(
+    provider: &RpcProvider,
+    to: Address,
+    function: &Function,
+    args: &[Token],
+    block: Option<BlockId>,
+) -> Result<Vec<Token>> {
+    let input_data = function
+        .encode_input(args)
+        .with_context(|| format!("Failed to encode {} call", function.name))?;
+
+    let tx_request = TransactionRequest::new().to(to).data(input_data);
+    let result = provider
+        .call(&tx_request.into(), block)
+        .await
+        .with_context(|| format!("{} call to {to:?} failed", function.name))?;
+
+    function
+        .decode_output(&result)
+        .with_context(|| format!("Failed to decode {} result", function.name))
+}
+
+/// Factory address of a Uniswap router or quoter
+pub async fn get_factory(provider: &RpcProvider, periphery: Address) -> Result<Address> {
+    match call(
+        provider,
+        periphery,
+        &view_function("factory", vec![], vec![ParamType::Address]),
+        &[],
+        None,
+    )
+    .await?
+    .first()
+    {
+        Some(Token::Address(factory)) => Ok(*factory),
+        _ => anyhow::bail!("Unexpected factory result format"),
+    }
+}
@@ -0,0 +1,410 @@
+// Copyright 2025 chenjjiaa
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::{Context, Result};
+use ethabi::{Function, Param, ParamType, StateMutability, Token};
+use ethers::types::{Address, U256};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::abi;
+use crate::chains::Chain;
+use crate::ens::ResolvedName;
+use crate::erc20;
+use crate::multicall::Multicall;
+use crate::rpc::RpcProvider;
+
+/// Permit2, deployed at the same address on every supported chain
+pub const PERMIT2_ADDRESS: &str = "0x000000000022D473030F116dDEE9F6B43aC78BA3";
+
+/// Lifetime of Permit2 allowances set by the suggested `approve`, as in the Uniswap app
+const PERMIT2_EXPIRATION_SECS: u64 = 30 * 24 * 60 * 60;
+
+/// Tokens whose `approve` reverts when changing one non-zero allowance to another, so the
+/// allowance must be reset to zero first (mainnet USDT)
+const APPROVE_RESET_TOKENS: [&str; 1] = ["0xdAC17F958D2ee523a2206206994597C13D831ec7"];
+
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct GetAllowanceInput {
+    /// Token owner address or ENS name
+    pub owner: String,
+    /// ERC20 token address, ENS name or symbol (e.g. "USDC")
+    pub token: String,
+    /// Spender address or ENS name, or "uniswap_v2" / "uniswap_v3" for the chain's Uniswap
+    /// routers
+    pub spender: String,
+    /// Amount the spender needs to move (in human-readable format, e.g., "100.5"). If given,
+    /// the output says whether the allowances cover it and which approvals are missing
+    #[serde(default)]
+    pub amount: Option<String>,
+    /// Chain name (e.g. "mainnet", "arbitrum", "base"). Defaults to the server's default chain
+    #[serde(default)]
+    pub chain: Option<String>,
+    /// Chain ID (e.g. 1, 42161, 8453). Alternative to `chain`
+    #[serde(default)]
+    pub chain_id: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AllowanceOutput {
+    /// Token owner address
+    pub owner: String,
+    /// Token address
+    pub token: String,
+    /// Spender address
+    pub spender: String,
+    /// Number of decimals of the token
+    pub decimals: u8,
+    /// ERC20 allowance of the owner to the spender
+    pub direct: AllowanceCheck,
+    /// Allowance of the owner to the spender through Permit2, as used by the Uniswap
+    /// Universal Router. `None` if Permit2 couldn't be read
+    pub permit2: Option<Permit2Check>,
+    /// Chain the allowances were read on
+    pub chain: String,
+    /// ENS names in the input and the addresses they resolved to
+    pub ens_names: Vec<ResolvedName>,
+}
+
+/// An ERC20 allowance, and the approvals that raise it to a required amount
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AllowanceCheck {
+    /// Spender address
+    pub spender: String,
+    /// Current allowance (in human-readable format)
+    pub allowance: String,
+    /// Whether the allowance is the maximum uint256, which most tokens never decrease
+    pub unlimited: bool,
+    /// Amount the spender needs to move (in human-readable format)
+    pub required: Option<String>,
+    /// Whether the allowance covers `required`
+    pub sufficient: Option<bool>,
+    /// Transactions the owner must send, in order, before the spender can move `required`
+    pub approvals: Vec<ApprovalTransaction>,
+}
+
+/// A Permit2 allowance: the owner approves Permit2 on the token, then the spender on Permit2
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Permit2Check {
+    /// ERC20 allowance of the owner to Permit2 (in human-readable format)
+    pub token_allowance: String,
+    /// Permit2 allowance of the owner to the spender (in human-readable format)
+    pub allowance: String,
+    /// Unix time (seconds) the Permit2 allowance expires at
+    pub expiration: u64,
+    /// Whether the Permit2 allowance has expired
+    pub expired: bool,
+    /// Permit2 nonce of the owner, token and spender, used by signed permits
+    pub nonce: u64,
+    /// Whether both allowances cover the required amount and the Permit2 one hasn't expired
+    pub sufficient: Option<bool>,
+    /// Transactions the owner must send, in order, before the spender can move the required
+    /// amount through Permit2
+    pub approvals: Vec<ApprovalTransaction>,
+}
+
+/// An unsigned transaction granting an allowance
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApprovalTransaction {
+    /// Contract to call: the token, or Permit2
+    pub to: String,
+    /// ABI-encoded calldata
+    pub data: String,
+    /// ETH to send, always "0"
+    pub value: String,
+    /// What the transaction does
+    pub description: String,
+}
+
+/// Resolve a spender parameter that names a Uniswap router, `None` for anything else
+pub fn router_alias(spender: &str, chain: &Chain) -> Result<Option<Address>> {
+    let router = match spender.trim().to_lowercase().as_str() {
+        "uniswap_v2" => chain.contracts.uniswap_v2_router,
+        "uniswap_v3" => chain.contracts.uniswap_v3_router,
+        _ => return Ok(None),
+    };
+    Ok(Some(Address::from_str(router)?))
+}
+
+/// Read the ERC20 allowance of `owner` to `spender` over `token`. If `required` is given,
+/// the check lists the approvals needed to cover it
+pub async fn check_allowance(
+    provider: &RpcProvider,
+    chain: &Chain,
+    token: Address,
+    owner: Address,
+    spender: Address,
+    decimals: u8,
+    required: Option<U256>,
+) -> Result<AllowanceCheck> {
+    let mut multicall = Multicall::new(provider, chain)?;
+    multicall.add_call(
+        token,
+        &erc20::allowance_function(),
+        &[Token::Address(owner), Token::Address(spender)],
+    )?;
+    let allowance = erc20::decode_uint(
+        multicall
+            .call()
+            .await?
+            .pop()
+            .context("Missing allowance result")?
+            .context("Failed to read allowance")?,
+    )?;
+
+    let sufficient = required.map(|required| allowance >= required);
+    let approvals = match required {
+        Some(required) if allowance < required => {
+            token_approvals(token, spender, allowance, required, decimals)?
+        }
+        _ => Vec::new(),
+    };
+
+    Ok(AllowanceCheck {
+        spender: format!("{spender:?}"),
+        allowance: format_amount(allowance, decimals)?,
+        unlimited: allowance == U256::MAX,
+        required: required
+            .map(|required| format_amount(required, decimals))
+            .transpose()?,
+        sufficient,
+        approvals,
+    })
+}
+
+/// Read the Permit2 allowance of `owner` to `spender` over `token`, together with the
+/// token's allowance to Permit2, in a single Multicall3 batch
+pub async fn check_permit2_allowance(
+    provider: &RpcProvider,
+    chain: &Chain,
+    token: Address,
+    owner: Address,
+    spender: Address,
+    decimals: u8,
+    required: Option<U256>,
+) -> Result<Permit2Check> {
+    let permit2 = Address::from_str(PERMIT2_ADDRESS)?;
+
+    let mut multicall = Multicall::new(provider, chain)?;
+    multicall.add_call(
+        token,
+        &erc20::allowance_function(),
+        &[Token::Address(owner), Token::Address(permit2)],
+    )?;
+    multicall.add_call(
+        permit2,
+        &permit2_allowance_function(),
+        &[
+            Token::Address(owner),
+            Token::Address(token),
+            Token::Address(spender),
+        ],
+    )?;
+    let mut results = multicall.call().await?.into_iter();
+
+    let token_allowance = erc20::decode_uint(
+        results
+            .next()
+            .context("Missing allowance result")?
+            .context("Failed to read allowance to Permit2")?,
+    )?;
+    let (amount, expiration, nonce) = match results
+        .next()
+        .context("Missing Permit2 allowance result")?
+        .context("Failed to read Permit2 allowance")?
+        .as_slice()
+    {
+        [Token::Uint(amount), Token::Uint(expiration), Token::Uint(nonce)] => {
+            (*amount, expiration.low_u64(), nonce.low_u64())
+        }
+        _ => anyhow::bail!("Unexpected Permit2 allowance result format"),
+    };
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default();
+    let expired = expiration < now;
+
+    let mut approvals = Vec::new();
+    if let Some(required) = required {
+        if token_allowance < required {
+            approvals.extend(token_approvals(
+                token,
+                permit2,
+                token_allowance,
+                required,
+                decimals,
+            )?);
+        }
+        if amount < required || expired {
+            approvals.push(permit2_approval(
+                token,
+                spender,
+                required,
+                now + PERMIT2_EXPIRATION_SECS,
+                decimals,
+            )?);
+        }
+    }
+
+    Ok(Permit2Check {
+        token_allowance: format_amount(token_allowance, decimals)?,
+        allowance: format_amount(amount, decimals)?,
+        expiration,
+        expired,
+        nonce,
+        sufficient: required.map(|_| approvals.is_empty()),
+        approvals,
+    })
+}
+
+/// `approve(spender, required)` on `token`, preceded by a reset to zero for tokens that
+/// need it
+fn token_approvals(
+    token: Address,
+    spender: Address,
+    current: U256,
+    required: U256,
+    decimals: u8,
+) -> Result<Vec<ApprovalTransaction>> {
+    let needs_reset = !current.is_zero()
+        && APPROVE_RESET_TOKENS
+            .iter()
+            .any(|reset_token| Address::from_str(reset_token).ok() == Some(token));
+
+    let mut approvals = Vec::new();
+    if needs_reset {
+        approvals.push(approve(
+            token,
+            spender,
+            U256::zero(),
+            format!("Reset the allowance of {spender:?} to 0, as this token requires before changing it"),
+        )?);
+    }
+    approvals.push(approve(
+        token,
+        spender,
+        required,
+        format!(
+            "Approve {spender:?} to spend {} of {token:?}",
+            format_amount(required, decimals)?
+        ),
+    )?);
+    Ok(approvals)
+}
+
+fn approve(
+    token: Address,
+    spender: Address,
+    amount: U256,
+    description: String,
+) -> Result<ApprovalTransaction> {
+    let data = erc20::approve_function()
+        .encode_input(&[Token::Address(spender), Token::Uint(amount)])
+        .context("Failed to encode approve")?;
+    Ok(ApprovalTransaction {
+        to: format!("{token:?}"),
+        data: format!("0x{}", hex::encode(data)),
+        value: "0".to_string(),
+        description,
+    })
+}
+
+/// The Permit2 approval of `spender` for `amount` of `token` until `expiration`
+fn permit2_approval(
+    token: Address,
+    spender: Address,
+    amount: U256,
+    expiration: u64,
+    decimals: u8,
+) -> Result<ApprovalTransaction> {
+    if amount.bits() > 160 {
+        anyhow::bail!("Amount {amount} exceeds the Permit2 allowance limit of 2^160 - 1");
+    }
+
+    let data = permit2_approve_function()
+        .encode_input(&[
+            Token::Address(token),
+            Token::Address(spender),
+            Token::Uint(amount),
+            Token::Uint(U256::from(expiration)),
+        ])
+        .context("Failed to encode Permit2 approve")?;
+
+    Ok(ApprovalTransaction {
+        to: format!("{:?}", Address::from_str(PERMIT2_ADDRESS)?),
+        data: format!("0x{}", hex::encode(data)),
+        value: "0".to_string(),
+        description: format!(
+            "Approve {spender:?} on Permit2 to spend {} of {token:?} until {expiration}",
+            format_amount(amount, decimals)?
+        ),
+    })
+}
+
+/// Permit2 `approve(address token, address spender, uint160 amount, uint48 expiration)`
+pub fn permit2_approve_function() -> Function {
+    Function {
+        name: "approve".to_string(),
+        inputs: vec![
+            Param {
+                name: "token".to_string(),
+                kind: ParamType::Address,
+                internal_type: None,
+            },
+            Param {
+                name: "spender".to_string(),
+                kind: ParamType::Address,
+                internal_type: None,
+            },
+            Param {
+                name: "amount".to_string(),
+                kind: ParamType::Uint(160),
+                internal_type: None,
+            },
+            Param {
+                name: "expiration".to_string(),
+                kind: ParamType::Uint(48),
+                internal_type: None,
+            },
+        ],
+        outputs: vec![],
+        #[allow(deprecated)]
+        constant: None,
+        state_mutability: StateMutability::NonPayable,
+    }
+}
+
+/// Permit2 `allowance(address owner, address token, address spender)`, which returns
+/// `(uint160 amount, uint48 expiration, uint48 nonce)`
+fn permit2_allowance_function() -> Function {
+    abi::view_function(
+        "allowance",
+        vec![ParamType::Address, ParamType::Address, ParamType::Address],
+        vec![
+            ParamType::Uint(160),
+            ParamType::Uint(48),
+            ParamType::Uint(48),
+        ],
+    )
+}
+
+/// Format a raw amount in whole tokens. Unlike [`erc20::to_decimal_units`] this handles
+/// amounts beyond the `Decimal` range, such as unlimited allowances
+fn format_amount(amount: U256, decimals: u8) -> Result<String> {
+    ethers::utils::format_units(amount, u32::from(decimals))
+        .context("Failed to format token amount")
+}
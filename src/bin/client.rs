@@ -12,7 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use dotenv::dotenv;
 use rmcp::{model::CallToolRequestParam, service::ServiceExt, transport::TokioChildProcess};
 use serde_json::json;
@@ -26,10 +26,8 @@ async fn main() -> Result<()> {
     // Initialize tracing
     tracing_subscriber::fmt::init();
 
-    let wallet_address = env::var("WALLET_ADDRESS")
-        .unwrap_or_else(|_| "0xd8dA6BF26964aF9D7eEd9e03E53415D37aA96045".to_string());
-    let token_address = env::var("TOKEN_ADDRESS")
-        .unwrap_or_else(|_| "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48".to_string());
+    let wallet_address = env::var("WALLET_ADDRESS").context("WALLET_ADDRESS must be set")?;
+    let token_address = env::var("TOKEN_ADDRESS").context("TOKEN_ADDRESS must be set")?;
 
     // Get the path to the server binary
     let server_path = if PathBuf::from("target/debug/server").exists() {
@@ -0,0 +1,179 @@
+// Copyright 2025 chenjjiaa
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Sends real transactions, so run it against a local dev node such as a mainnet fork:
+//!
+//! ```bash
+//! anvil --fork-url https://eth.llamarpc.com
+//! ETH_RPC_URL=http://127.0.0.1:8545 cargo r --bin test_wallet
+//! ```
+
+use anyhow::Result;
+use rmcp::{
+    model::{CallToolRequestParam, CallToolResult},
+    service::ServiceExt,
+    transport::TokioChildProcess,
+};
+use serde_json::json;
+use std::path::PathBuf;
+use tokio::process::Command;
+use tracing::{error, info};
+
+const USDC_ADDRESS: &str = "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48";
+
+/// Mnemonic of the funded accounts of anvil and hardhat dev nodes
+const DEV_MNEMONIC: &str = "test test test test test test test test test test test junk";
+/// First and second accounts of `DEV_MNEMONIC`
+const DEV_ACCOUNT_0: &str = "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266";
+const DEV_ACCOUNT_1: &str = "0x70997970C51812dc3A010C7d01b50e0d17dc79C8";
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::DEBUG)
+        .init();
+
+    let server_path = if PathBuf::from("target/debug/server").exists() {
+        "target/debug/server"
+    } else if PathBuf::from("target/release/server").exists() {
+        "target/release/server"
+    } else {
+        "target/debug/server"
+    };
+    let rpc_url =
+        std::env::var("ETH_RPC_URL").unwrap_or_else(|_| "http://127.0.0.1:8545".to_string());
+    let mnemonic = std::env::var("WALLET_MNEMONIC").unwrap_or_else(|_| DEV_MNEMONIC.to_string());
+
+    println!("Testing Wallet Transactions");
+    println!("Starting server at: {server_path} against {rpc_url}\n");
+
+    let mut cmd = Command::new(server_path);
+    cmd.env("ETH_RPC_URL", &rpc_url)
+        .env("WALLET_ENABLED", "true")
+        .env("WALLET_MNEMONIC", mnemonic)
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped());
+
+    info!("Spawning server process...");
+    let service = match ().serve(TokioChildProcess::new(cmd)?).await {
+        Ok(s) => {
+            println!("Connected to server!\n");
+            s
+        }
+        Err(e) => {
+            error!("Failed to connect to server: {}", e);
+            return Err(e.into());
+        }
+    };
+
+    // Test 1: Send ETH between wallet accounts
+    println!("Test 1: Send 0.01 ETH from {DEV_ACCOUNT_0} to {DEV_ACCOUNT_1}\n");
+    let result1 = match service
+        .call_tool(CallToolRequestParam {
+            name: "send_transaction".into(),
+            arguments: json!({
+                "from": DEV_ACCOUNT_0,
+                "to": DEV_ACCOUNT_1,
+                "value": "0.01"
+            })
+            .as_object()
+            .cloned(),
+        })
+        .await
+    {
+        Ok(r) => r,
+        Err(e) => {
+            error!("Test 1 failed: {}", e);
+            service.cancel().await?;
+            return Err(e.into());
+        }
+    };
+    print_result(result1);
+
+    // Test 2: Swap ETH for USDC on V2
+    println!("Test 2: Execute V2 Swap ETH -> USDC");
+    println!("Swapping 0.1 ETH for USDC\n");
+    let result2 = match service
+        .call_tool(CallToolRequestParam {
+            name: "execute_swap".into(),
+            arguments: json!({
+                "from_token": "ETH",
+                "to_token": USDC_ADDRESS,
+                "amount": "0.1",
+                "slippage_tolerance": "0.5",
+                "sender": DEV_ACCOUNT_0,
+                "version": "v2"
+            })
+            .as_object()
+            .cloned(),
+        })
+        .await
+    {
+        Ok(r) => r,
+        Err(e) => {
+            error!("Test 2 failed: {}", e);
+            service.cancel().await?;
+            return Err(e.into());
+        }
+    };
+    print_result(result2);
+
+    // Test 3: Swap the USDC back on V3, approving the router first
+    println!("Test 3: Execute V3 Swap USDC -> ETH with approval");
+    println!("Swapping 100 USDC for ETH\n");
+    let result3 = match service
+        .call_tool(CallToolRequestParam {
+            name: "execute_swap".into(),
+            arguments: json!({
+                "from_token": USDC_ADDRESS,
+                "to_token": "ETH",
+                "amount": "100",
+                "slippage_tolerance": "0.5",
+                "sender": DEV_ACCOUNT_0,
+                "version": "v3",
+                "approve": true
+            })
+            .as_object()
+            .cloned(),
+        })
+        .await
+    {
+        Ok(r) => r,
+        Err(e) => {
+            error!("Test 3 failed: {}", e);
+            service.cancel().await?;
+            return Err(e.into());
+        }
+    };
+    print_result(result3);
+
+    service.cancel().await?;
+    println!("All tests completed!");
+
+    Ok(())
+}
+
+fn print_result(result: CallToolResult) {
+    println!("Result:");
+    for content in result.content {
+        match content.raw {
+            rmcp::model::RawContent::Text(text) => {
+                println!("{}", text.text);
+            }
+            _ => println!("{content:#?}"),
+        }
+    }
+    println!();
+}
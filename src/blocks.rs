@@ -0,0 +1,145 @@
+// Copyright 2025 chenjjiaa
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::{Context, Result};
+use ethers::{
+    prelude::*,
+    types::{BlockId, BlockNumber, H256},
+};
+use std::str::FromStr;
+use tracing::info;
+
+use crate::rpc::RpcProvider;
+
+/// A block resolved to its number and hash, so results computed at it can be reproduced
+#[derive(Debug, Clone, Copy)]
+pub struct ResolvedBlock {
+    pub number: u64,
+    pub hash: H256,
+    /// Block timestamp (Unix seconds)
+    pub timestamp: u64,
+}
+
+impl ResolvedBlock {
+    /// Block ID pinned to the block hash (EIP-1898), so reorgs can't change the answer
+    pub fn id(&self) -> BlockId {
+        BlockId::Hash(self.hash)
+    }
+}
+
+/// Parse a block selector: a decimal or 0x-prefixed block number, a 32-byte block hash,
+/// or one of `latest`, `safe`, `finalized`, `earliest`
+pub fn parse_block_id(block: &str) -> Result<BlockId> {
+    let block = block.trim().to_lowercase();
+
+    let id = match block.as_str() {
+        "latest" => BlockId::Number(BlockNumber::Latest),
+        "safe" => BlockId::Number(BlockNumber::Safe),
+        "finalized" => BlockId::Number(BlockNumber::Finalized),
+        "earliest" => BlockId::Number(BlockNumber::Earliest),
+        hash if hash.starts_with("0x") && hash.len() == 66 => {
+            BlockId::Hash(H256::from_str(hash).context("Invalid block hash")?)
+        }
+        hex if hex.starts_with("0x") => BlockId::Number(
+            u64::from_str_radix(&hex[2..], 16)
+                .context("Invalid block number")?
+                .into(),
+        ),
+        number => BlockId::Number(
+            number
+                .parse::<u64>()
+                .with_context(|| {
+                    format!(
+                        "Invalid block: {block} (expected a number, hash, latest, safe or finalized)"
+                    )
+                })?
+                .into(),
+        ),
+    };
+
+    Ok(id)
+}
+
+/// Fetch the header of `block` and return its number and hash
+pub async fn resolve_block(provider: &RpcProvider, block: BlockId) -> Result<ResolvedBlock> {
+    let header = provider
+        .get_block(block)
+        .await
+        .context("Failed to get block")?
+        .with_context(|| format!("Block not found: {block:?}"))?;
+
+    Ok(ResolvedBlock {
+        number: header
+            .number
+            .context("Block has no number (pending?)")?
+            .as_u64(),
+        hash: header.hash.context("Block has no hash (pending?)")?,
+        timestamp: header.timestamp.as_u64(),
+    })
+}
+
+/// Find the last block with a timestamp at or before `timestamp` (Unix seconds),
+/// by binary search over block headers
+pub async fn find_block_by_timestamp(
+    provider: &RpcProvider,
+    timestamp: u64,
+) -> Result<ResolvedBlock> {
+    let latest = provider
+        .get_block(BlockNumber::Latest)
+        .await
+        .context("Failed to get latest block")?
+        .context("Latest block not found")?;
+    let latest_number = latest
+        .number
+        .context("Latest block has no number")?
+        .as_u64();
+
+    if latest.timestamp <= timestamp.into() {
+        return Ok(ResolvedBlock {
+            number: latest_number,
+            hash: latest.hash.context("Latest block has no hash")?,
+            timestamp: latest.timestamp.as_u64(),
+        });
+    }
+
+    // Invariant: block `low` is at or before `timestamp`, block `high` is after it
+    let mut low = 0u64;
+    let mut high = latest_number;
+    let genesis_timestamp = block_timestamp(provider, low).await?;
+    if genesis_timestamp > timestamp {
+        anyhow::bail!("Timestamp {timestamp} is before the first block ({genesis_timestamp})");
+    }
+
+    while high - low > 1 {
+        let mid = low + (high - low) / 2;
+        if block_timestamp(provider, mid).await? <= timestamp {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+
+    let block = resolve_block(provider, BlockId::Number(low.into())).await?;
+    info!("Resolved timestamp {} to block {}", timestamp, block.number);
+    Ok(block)
+}
+
+async fn block_timestamp(provider: &RpcProvider, number: u64) -> Result<u64> {
+    let header = provider
+        .get_block(number)
+        .await
+        .with_context(|| format!("Failed to get block {number}"))?
+        .with_context(|| format!("Block {number} not found"))?;
+    Ok(header.timestamp.as_u64())
+}
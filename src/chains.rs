@@ -0,0 +1,532 @@
+// Copyright 2025 chenjjiaa
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::info;
+
+use crate::rpc::{RpcClient, RpcEndpoints, RpcProvider};
+
+/// Flavour of the Uniswap V3 swap router deployed on a chain.
+///
+/// The original `SwapRouter` takes a `deadline` inside the `exactInputSingle` params,
+/// while `SwapRouter02` dropped it. Some chains (e.g. Base, Sepolia) only have `SwapRouter02`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum V3RouterKind {
+    SwapRouter,
+    SwapRouter02,
+}
+
+/// Uniswap and wrapped-native contract addresses for a single chain
+#[derive(Debug, Clone, Copy)]
+pub struct ChainContracts {
+    /// Uniswap V2 Router02
+    pub uniswap_v2_router: &'static str,
+    /// Uniswap V3 swap router
+    pub uniswap_v3_router: &'static str,
+    /// Interface of the V3 swap router
+    pub uniswap_v3_router_kind: V3RouterKind,
+    /// Uniswap V3 QuoterV2
+    pub uniswap_v3_quoter_v2: &'static str,
+    /// Old Uniswap V3 Quoter, used as fallback where deployed
+    pub uniswap_v3_quoter: Option<&'static str>,
+    /// Wrapped native token (WETH, or WPOL on Polygon)
+    pub weth: &'static str,
+    /// Native USDC, the USD reference for on-chain prices
+    pub usdc: &'static str,
+    /// USDT, tried as an intermediate token in swap routes
+    pub usdt: Option<&'static str>,
+    /// DAI, tried as an intermediate token in swap routes
+    pub dai: Option<&'static str>,
+    /// Multicall3, used to batch read calls
+    pub multicall3: &'static str,
+}
+
+/// A Chainlink AggregatorV3 price feed
+#[derive(Debug, Clone, Copy)]
+pub struct ChainlinkFeed {
+    /// Pair as named by Chainlink, e.g. "ETH/USD"
+    pub pair: &'static str,
+    /// Feed proxy address
+    pub address: &'static str,
+    /// Maximum time between updates; older answers are reported as stale
+    pub heartbeat_secs: u64,
+}
+
+/// Static description of a supported chain
+#[derive(Debug)]
+pub struct Chain {
+    /// EIP-155 chain ID
+    pub id: u64,
+    /// Canonical chain name used in tool parameters and output
+    pub name: &'static str,
+    /// Alternative names accepted in tool parameters
+    pub aliases: &'static [&'static str],
+    /// Symbol of the native gas token
+    pub native_symbol: &'static str,
+    /// Environment variable holding the RPC URL for this chain
+    pub rpc_env_var: &'static str,
+    /// Public RPC endpoint used when no RPC URL is configured
+    pub default_rpc_url: &'static str,
+    /// CoinGecko asset platform ID, if CoinGecko indexes this chain
+    pub coingecko_platform: Option<&'static str>,
+    /// Contract addresses on this chain
+    pub contracts: ChainContracts,
+    /// Chainlink price feeds on this chain
+    pub chainlink_feeds: &'static [ChainlinkFeed],
+}
+
+pub const MAINNET_CHAIN_ID: u64 = 1;
+
+/// Multicall3 is deployed at the same address on every supported chain
+const MULTICALL3_ADDRESS: &str = "0xcA11bde05977b3631167028862bE2a173976CA11";
+
+const MAINNET_FEEDS: &[ChainlinkFeed] = &[
+    ChainlinkFeed {
+        pair: "ETH/USD",
+        address: "0x5f4eC3Df9cbd43714FE2740f5E3616155c5b8419",
+        heartbeat_secs: 3600,
+    },
+    ChainlinkFeed {
+        pair: "BTC/USD",
+        address: "0xF4030086522a5bEEa4988F8cA5B36dbC97BeE88c",
+        heartbeat_secs: 3600,
+    },
+    ChainlinkFeed {
+        pair: "USDC/USD",
+        address: "0x8fFfFfd4AfB6115b954Bd326cbe7B4BA576818f6",
+        heartbeat_secs: 86400,
+    },
+    ChainlinkFeed {
+        pair: "USDT/USD",
+        address: "0x3E7d1eAB13ad0104d2750B8863b489D65364e32D",
+        heartbeat_secs: 86400,
+    },
+    ChainlinkFeed {
+        pair: "DAI/USD",
+        address: "0xAed0c38402a5d19df6E4c03F4E2DceD6e29c1ee9",
+        heartbeat_secs: 3600,
+    },
+    ChainlinkFeed {
+        pair: "LINK/USD",
+        address: "0x2c1d072e956AFFC0D435Cb7AC38EF18d24d9127c",
+        heartbeat_secs: 3600,
+    },
+    ChainlinkFeed {
+        pair: "STETH/ETH",
+        address: "0x86392dC19c0b719886221c78AB11eb8Cf5c52812",
+        heartbeat_secs: 86400,
+    },
+];
+
+const SEPOLIA_FEEDS: &[ChainlinkFeed] = &[
+    ChainlinkFeed {
+        pair: "ETH/USD",
+        address: "0x694AA1769357215DE4FAC081bf1f309aDC325306",
+        heartbeat_secs: 3600,
+    },
+    ChainlinkFeed {
+        pair: "BTC/USD",
+        address: "0x1b44F3514812d835EB1BDB0acB33d3fA3351Ee43",
+        heartbeat_secs: 3600,
+    },
+    ChainlinkFeed {
+        pair: "USDC/USD",
+        address: "0xA2F78ab2355fe2f984D808B5CeE7FD0A93D5270E",
+        heartbeat_secs: 86400,
+    },
+    ChainlinkFeed {
+        pair: "LINK/USD",
+        address: "0xc59E3633BAAC79493d908e63626716e204A45EdF",
+        heartbeat_secs: 3600,
+    },
+];
+
+const ARBITRUM_FEEDS: &[ChainlinkFeed] = &[
+    ChainlinkFeed {
+        pair: "ETH/USD",
+        address: "0x639Fe6ab55C921f74e7fac1ee960C0B6293ba612",
+        heartbeat_secs: 86400,
+    },
+    ChainlinkFeed {
+        pair: "BTC/USD",
+        address: "0x6ce185860a4963106506C203335A2910413708e9",
+        heartbeat_secs: 86400,
+    },
+    ChainlinkFeed {
+        pair: "USDC/USD",
+        address: "0x50834F3163758fcC1Df9973b6e91f0F0F0434aD3",
+        heartbeat_secs: 86400,
+    },
+    ChainlinkFeed {
+        pair: "USDT/USD",
+        address: "0x3f3f5dF88dC9F13eac63DF89EC16ef6e7E25DdE7",
+        heartbeat_secs: 86400,
+    },
+    ChainlinkFeed {
+        pair: "DAI/USD",
+        address: "0xc5C8E77B397E531B8EC06BFb0048328B30E9eCfB",
+        heartbeat_secs: 86400,
+    },
+    ChainlinkFeed {
+        pair: "LINK/USD",
+        address: "0x86E53CF1B870786351Da77A57575e79CB55812CB",
+        heartbeat_secs: 3600,
+    },
+];
+
+const OPTIMISM_FEEDS: &[ChainlinkFeed] = &[
+    ChainlinkFeed {
+        pair: "ETH/USD",
+        address: "0x13e3Ee699D1909E989722E753853AE30b17e08c5",
+        heartbeat_secs: 1200,
+    },
+    ChainlinkFeed {
+        pair: "BTC/USD",
+        address: "0xD702DD976Fb76Fffc2D3963D037dfDae5b04E593",
+        heartbeat_secs: 1200,
+    },
+    ChainlinkFeed {
+        pair: "USDC/USD",
+        address: "0x16a9FA2FDa030272Ce99B29CF780dFA30361E0f3",
+        heartbeat_secs: 86400,
+    },
+    ChainlinkFeed {
+        pair: "USDT/USD",
+        address: "0xECef79E109e997bCA29c1c0897ec9d7b03647F5E",
+        heartbeat_secs: 86400,
+    },
+    ChainlinkFeed {
+        pair: "DAI/USD",
+        address: "0x8dBa75e83DA73cc766A7e5a0ee71F656BAb470d6",
+        heartbeat_secs: 86400,
+    },
+    ChainlinkFeed {
+        pair: "LINK/USD",
+        address: "0xCc232dcFAAE6354cE191Bd574108c1aD03f86450",
+        heartbeat_secs: 1200,
+    },
+];
+
+const BASE_FEEDS: &[ChainlinkFeed] = &[
+    ChainlinkFeed {
+        pair: "ETH/USD",
+        address: "0x71041dddad3595F9CEd3DcCFBe3D1F4b0a16Bb70",
+        heartbeat_secs: 1200,
+    },
+    ChainlinkFeed {
+        pair: "USDC/USD",
+        address: "0x7e860098F58bBFC8648a4311b374B1D669a2bc6B",
+        heartbeat_secs: 86400,
+    },
+    ChainlinkFeed {
+        pair: "LINK/USD",
+        address: "0x17CAb8FE31E32f08326e5E27412894e49B0f9D65",
+        heartbeat_secs: 86400,
+    },
+];
+
+const POLYGON_FEEDS: &[ChainlinkFeed] = &[
+    ChainlinkFeed {
+        pair: "ETH/USD",
+        address: "0xF9680D99D6C9589e2a93a78A04A279e509205945",
+        heartbeat_secs: 3600,
+    },
+    ChainlinkFeed {
+        pair: "BTC/USD",
+        address: "0xc907E116054Ad103354f2D350FD2514433D57F6f",
+        heartbeat_secs: 3600,
+    },
+    ChainlinkFeed {
+        pair: "POL/USD",
+        address: "0xAB594600376Ec9fD91F8e885dADF0CE036862dE0",
+        heartbeat_secs: 3600,
+    },
+    ChainlinkFeed {
+        pair: "USDC/USD",
+        address: "0xfE4A8cc5b5B2366C1B58Bea3858e81843581b2F7",
+        heartbeat_secs: 86400,
+    },
+    ChainlinkFeed {
+        pair: "USDT/USD",
+        address: "0x0A6513e40db6EB1b165753AD52E80663aeA50545",
+        heartbeat_secs: 86400,
+    },
+    ChainlinkFeed {
+        pair: "DAI/USD",
+        address: "0x4746DeC9e833A82EC7C2C1356372CcF2cfcD2F3D",
+        heartbeat_secs: 86400,
+    },
+    ChainlinkFeed {
+        pair: "LINK/USD",
+        address: "0xd9FFdb71EbE7496cC440152d43986Aae0AB76665",
+        heartbeat_secs: 3600,
+    },
+];
+
+pub static CHAINS: &[Chain] = &[
+    Chain {
+        id: MAINNET_CHAIN_ID,
+        name: "mainnet",
+        aliases: &["ethereum", "eth"],
+        native_symbol: "ETH",
+        rpc_env_var: "ETH_RPC_URL",
+        default_rpc_url: "https://eth.llamarpc.com",
+        coingecko_platform: Some("ethereum"),
+        contracts: ChainContracts {
+            uniswap_v2_router: "0x7a250d5630B4cF539739dF2C5dAcb4c659F2488D",
+            uniswap_v3_router: "0xE592427A0AEce92De3Edee1F18E0157C05861564",
+            uniswap_v3_router_kind: V3RouterKind::SwapRouter,
+            uniswap_v3_quoter_v2: "0x61fFE014bA17989E743c5F6cB21bF9697530B21e",
+            uniswap_v3_quoter: Some("0xb27308f9F90D607463bb33eA1BeBb41C27CE5AB6"),
+            weth: "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2",
+            usdc: "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48",
+            usdt: Some("0xdAC17F958D2ee523a2206206994597C13D831ec7"),
+            dai: Some("0x6B175474E89094C44Da98b954EedeAC495271d0F"),
+            multicall3: MULTICALL3_ADDRESS,
+        },
+        chainlink_feeds: MAINNET_FEEDS,
+    },
+    Chain {
+        id: 11155111,
+        name: "sepolia",
+        aliases: &[],
+        native_symbol: "ETH",
+        rpc_env_var: "SEPOLIA_RPC_URL",
+        default_rpc_url: "https://ethereum-sepolia-rpc.publicnode.com",
+        coingecko_platform: None,
+        contracts: ChainContracts {
+            uniswap_v2_router: "0xeE567Fe1712Faf6149d80dA1E6934E354124CfE3",
+            uniswap_v3_router: "0x3bFA4769FB09eefC5a80d6E87c3B9C650f7Ae48E",
+            uniswap_v3_router_kind: V3RouterKind::SwapRouter02,
+            uniswap_v3_quoter_v2: "0xEd1f6473345F45b75F8179591dd5bA1888cf2FB3",
+            uniswap_v3_quoter: None,
+            weth: "0xfFf9976782d46CC05630D1f6eBAb18b2324d6B14",
+            usdc: "0x1c7D4B196Cb0C7B01d743Fbc6116a902379C7238",
+            usdt: None,
+            dai: None,
+            multicall3: MULTICALL3_ADDRESS,
+        },
+        chainlink_feeds: SEPOLIA_FEEDS,
+    },
+    Chain {
+        id: 42161,
+        name: "arbitrum",
+        aliases: &["arbitrum-one", "arb"],
+        native_symbol: "ETH",
+        rpc_env_var: "ARBITRUM_RPC_URL",
+        default_rpc_url: "https://arb1.arbitrum.io/rpc",
+        coingecko_platform: Some("arbitrum-one"),
+        contracts: ChainContracts {
+            uniswap_v2_router: "0x4752ba5DBc23f44D87826276BF6Fd6b1C372aD24",
+            uniswap_v3_router: "0xE592427A0AEce92De3Edee1F18E0157C05861564",
+            uniswap_v3_router_kind: V3RouterKind::SwapRouter,
+            uniswap_v3_quoter_v2: "0x61fFE014bA17989E743c5F6cB21bF9697530B21e",
+            uniswap_v3_quoter: Some("0xb27308f9F90D607463bb33eA1BeBb41C27CE5AB6"),
+            weth: "0x82aF49447D8a07e3bd95BD0d56f35241523fBab1",
+            usdc: "0xaf88d065e77c8cC2239327C5EDb3A432268e5831",
+            usdt: Some("0xFd086bC7CD5C481DCC9C85ebE478A1C0b69FCbb9"),
+            dai: Some("0xDA10009cBd5D07dd0CeCc66161FC93D7c9000da1"),
+            multicall3: MULTICALL3_ADDRESS,
+        },
+        chainlink_feeds: ARBITRUM_FEEDS,
+    },
+    Chain {
+        id: 10,
+        name: "optimism",
+        aliases: &["op"],
+        native_symbol: "ETH",
+        rpc_env_var: "OPTIMISM_RPC_URL",
+        default_rpc_url: "https://mainnet.optimism.io",
+        coingecko_platform: Some("optimistic-ethereum"),
+        contracts: ChainContracts {
+            uniswap_v2_router: "0x4A7b5Da61326A6379179b40d00F57E5bbDC962c2",
+            uniswap_v3_router: "0xE592427A0AEce92De3Edee1F18E0157C05861564",
+            uniswap_v3_router_kind: V3RouterKind::SwapRouter,
+            uniswap_v3_quoter_v2: "0x61fFE014bA17989E743c5F6cB21bF9697530B21e",
+            uniswap_v3_quoter: Some("0xb27308f9F90D607463bb33eA1BeBb41C27CE5AB6"),
+            weth: "0x4200000000000000000000000000000000000006",
+            usdc: "0x0b2C639c533813f4Aa9D7837CAf62653d097Ff85",
+            usdt: Some("0x94b008aA00579c1307B0EF2c499aD98a8ce58e58"),
+            dai: Some("0xDA10009cBd5D07dd0CeCc66161FC93D7c9000da1"),
+            multicall3: MULTICALL3_ADDRESS,
+        },
+        chainlink_feeds: OPTIMISM_FEEDS,
+    },
+    Chain {
+        id: 8453,
+        name: "base",
+        aliases: &[],
+        native_symbol: "ETH",
+        rpc_env_var: "BASE_RPC_URL",
+        default_rpc_url: "https://mainnet.base.org",
+        coingecko_platform: Some("base"),
+        contracts: ChainContracts {
+            uniswap_v2_router: "0x4752ba5DBc23f44D87826276BF6Fd6b1C372aD24",
+            uniswap_v3_router: "0x2626664c2603336E57B271c5C0b26F421741e481",
+            uniswap_v3_router_kind: V3RouterKind::SwapRouter02,
+            uniswap_v3_quoter_v2: "0x3d4e44Eb1374240CE5F1B871ab261CD16335B76a",
+            uniswap_v3_quoter: None,
+            weth: "0x4200000000000000000000000000000000000006",
+            usdc: "0x833589fCD6eDb6E08f4c7C32D4f71b54bdA02913",
+            usdt: None,
+            dai: Some("0x50c5725949A6F0c72E6C4a641F24049A917DB0Cb"),
+            multicall3: MULTICALL3_ADDRESS,
+        },
+        chainlink_feeds: BASE_FEEDS,
+    },
+    Chain {
+        id: 137,
+        name: "polygon",
+        aliases: &["matic", "polygon-pos"],
+        native_symbol: "POL",
+        rpc_env_var: "POLYGON_RPC_URL",
+        default_rpc_url: "https://polygon-rpc.com",
+        coingecko_platform: Some("polygon-pos"),
+        contracts: ChainContracts {
+            uniswap_v2_router: "0xedf6066a2b290C185783862C7F4776A2C8077AD1",
+            uniswap_v3_router: "0xE592427A0AEce92De3Edee1F18E0157C05861564",
+            uniswap_v3_router_kind: V3RouterKind::SwapRouter,
+            uniswap_v3_quoter_v2: "0x61fFE014bA17989E743c5F6cB21bF9697530B21e",
+            uniswap_v3_quoter: Some("0xb27308f9F90D607463bb33eA1BeBb41C27CE5AB6"),
+            weth: "0x0d500B1d8E8eF31E21C99d1Db9A6444d3ADf1270",
+            usdc: "0x3c499c542cEF5E3811e1192ce70d8cC03d5c3359",
+            usdt: Some("0xc2132D05D31c914a87C6611C10748AEb04B58e8F"),
+            dai: Some("0x8f3Cf7ad23Cd3CaDbD9735AFf958023239c6A063"),
+            multicall3: MULTICALL3_ADDRESS,
+        },
+        chainlink_feeds: POLYGON_FEEDS,
+    },
+];
+
+impl Chain {
+    /// Look up a Chainlink feed by pair name (case-insensitive)
+    pub fn chainlink_feed(&self, pair: &str) -> Option<&'static ChainlinkFeed> {
+        self.chainlink_feeds
+            .iter()
+            .find(|feed| feed.pair.eq_ignore_ascii_case(pair))
+    }
+
+    /// Whether `token` is the symbol of the chain's native gas token ("ETH", or "POL" on
+    /// Polygon, where "ETH" is bridged WETH)
+    pub fn is_native_token(&self, token: &str) -> bool {
+        token.eq_ignore_ascii_case(self.native_symbol)
+    }
+}
+
+/// Look up a supported chain by name, alias or numeric chain ID
+pub fn find_chain(name_or_id: &str) -> Result<&'static Chain> {
+    let needle = name_or_id.trim().to_lowercase();
+
+    if let Ok(id) = needle.parse::<u64>() {
+        return find_chain_by_id(id);
+    }
+
+    CHAINS
+        .iter()
+        .find(|chain| chain.name == needle || chain.aliases.contains(&needle.as_str()))
+        .with_context(|| format!("Unsupported chain: {name_or_id}"))
+}
+
+/// Look up a supported chain by chain ID
+pub fn find_chain_by_id(id: u64) -> Result<&'static Chain> {
+    CHAINS
+        .iter()
+        .find(|chain| chain.id == id)
+        .with_context(|| format!("Unsupported chain ID: {id}"))
+}
+
+/// A supported chain together with the provider used to talk to it
+#[derive(Clone)]
+pub struct Network {
+    pub chain: &'static Chain,
+    pub provider: Arc<RpcProvider>,
+}
+
+/// Per-chain providers, keyed by chain ID
+pub struct ChainRegistry {
+    networks: HashMap<u64, Network>,
+    default_chain_id: u64,
+}
+
+impl ChainRegistry {
+    /// Create a registry from RPC endpoints keyed by chain ID.
+    ///
+    /// Chains without an entry in `rpc_endpoints` are left unavailable.
+    pub fn new(rpc_endpoints: HashMap<u64, RpcEndpoints>, default_chain_id: u64) -> Result<Self> {
+        let mut networks = HashMap::new();
+
+        for (chain_id, endpoints) in rpc_endpoints {
+            let chain = find_chain_by_id(chain_id)?;
+            let client = RpcClient::new(&endpoints)
+                .with_context(|| format!("Failed to create RPC client for {}", chain.name))?;
+            let provider = RpcProvider::new(client);
+
+            info!(
+                "Registered chain {} ({}) with RPC: {} (quorum: {})",
+                chain.name,
+                chain.id,
+                endpoints.urls.join(", "),
+                endpoints.settings.quorum
+            );
+            networks.insert(
+                chain_id,
+                Network {
+                    chain,
+                    provider: Arc::new(provider),
+                },
+            );
+        }
+
+        if !networks.contains_key(&default_chain_id) {
+            anyhow::bail!("No RPC URL configured for default chain ID {default_chain_id}");
+        }
+
+        Ok(Self {
+            networks,
+            default_chain_id,
+        })
+    }
+
+    /// Resolve the network selected by the optional `chain` / `chain_id` tool parameters.
+    ///
+    /// Falls back to the default chain when neither is given.
+    pub fn resolve(&self, chain: Option<&str>, chain_id: Option<u64>) -> Result<&Network> {
+        let id = match (chain, chain_id) {
+            (Some(name), Some(id)) => {
+                let named = find_chain(name)?;
+                if named.id != id {
+                    anyhow::bail!(
+                        "Chain {name} (ID {}) does not match chain_id {id}",
+                        named.id
+                    );
+                }
+                id
+            }
+            (Some(name), None) => find_chain(name)?.id,
+            (None, Some(id)) => find_chain_by_id(id)?.id,
+            (None, None) => self.default_chain_id,
+        };
+
+        self.networks
+            .get(&id)
+            .with_context(|| format!("No RPC URL configured for chain ID {id}"))
+    }
+
+    /// Ethereum mainnet, where ENS names are resolved regardless of the chain a tool targets
+    pub fn mainnet(&self) -> Result<&Network> {
+        self.resolve(None, Some(MAINNET_CHAIN_ID))
+    }
+}
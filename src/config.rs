@@ -0,0 +1,600 @@
+// Copyright 2025 chenjjiaa
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::{Context, Result};
+use ethers::types::Address;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+use tracing::warn;
+
+use crate::chains::{self, CHAINS};
+use crate::rpc::{RpcEndpoints, RpcSettings};
+
+/// Transport used to expose the MCP server
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TransportMode {
+    /// Standard input/output (default, for MCP standard)
+    Stdio,
+    /// Raw MCP frames over a bare TCP socket
+    Tcp,
+    /// MCP Streamable HTTP with SSE and session IDs
+    #[serde(alias = "streamable-http")]
+    Http,
+}
+
+impl FromStr for TransportMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "stdio" => Ok(Self::Stdio),
+            "tcp" => Ok(Self::Tcp),
+            "http" | "streamable-http" => Ok(Self::Http),
+            _ => anyhow::bail!("Invalid transport mode: {s} (expected stdio, tcp or http)"),
+        }
+    }
+}
+
+/// Source of token prices
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PriceSourceKind {
+    /// CoinGecko simple price API
+    Coingecko,
+    /// Uniswap V2 reserves and V3 quoters on the queried chain
+    Uniswap,
+}
+
+impl FromStr for PriceSourceKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "coingecko" => Ok(Self::Coingecko),
+            "uniswap" => Ok(Self::Uniswap),
+            _ => anyhow::bail!("Invalid price source: {s} (expected coingecko or uniswap)"),
+        }
+    }
+}
+
+/// Server configuration, loaded from an optional TOML file and overridden by environment variables
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Log level filter (overridden by `RUST_LOG`)
+    pub log_level: String,
+    /// Chain used when a tool call doesn't specify one (overridden by `DEFAULT_CHAIN`)
+    pub default_chain: String,
+    /// Transport settings
+    pub server: ServerConfig,
+    /// RPC retry, failover and quorum settings
+    pub rpc: RpcConfig,
+    /// Per-chain settings, keyed by chain name, alias or chain ID
+    pub chains: HashMap<String, ChainSettings>,
+    /// Price source settings
+    pub prices: PriceConfig,
+    /// Named lists of token contract addresses
+    pub token_lists: HashMap<String, Vec<String>>,
+    /// Token lists used to resolve token symbols to addresses
+    pub token_registry: TokenRegistryConfig,
+    /// Safety limits applied to tool inputs
+    pub limits: LimitsConfig,
+    /// Local wallet used to sign and broadcast transactions
+    pub wallet: WalletConfig,
+    /// Rules transactions must pass before the wallet signs them
+    pub policy: PolicyConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// Transport mode. Defaults to TCP if a port is set, otherwise stdio
+    /// (overridden by `SERVER_TRANSPORT`)
+    pub transport: Option<TransportMode>,
+    /// Bind address for TCP and HTTP transports (overridden by `SERVER_HOST`)
+    pub host: String,
+    /// Bind port for TCP and HTTP transports (overridden by `SERVER_PORT`)
+    pub port: u16,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RpcConfig {
+    /// Number of extra rounds over all endpoints after every endpoint failed
+    pub max_retries: u32,
+    /// Backoff before the first retry round in milliseconds, doubled on every further round
+    pub initial_backoff_ms: u64,
+    /// How long a failing endpoint is skipped before it's tried again, in seconds
+    pub unhealthy_cooldown_secs: u64,
+    /// Number of endpoints that must agree on read results (balances, quotes).
+    /// 0 or 1 disables quorum mode
+    pub quorum: usize,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChainSettings {
+    /// Primary RPC endpoint (overridden by the chain's `*_RPC_URL` environment variable,
+    /// which accepts a comma-separated list)
+    pub rpc_url: Option<String>,
+    /// Additional RPC endpoints, tried in order when the ones before them fail
+    pub rpc_urls: Vec<String>,
+    /// Per-chain override of `rpc.quorum`
+    pub quorum: Option<usize>,
+}
+
+impl ChainSettings {
+    /// All configured RPC endpoints in priority order
+    pub fn urls(&self) -> Vec<String> {
+        self.rpc_url
+            .iter()
+            .chain(self.rpc_urls.iter())
+            .cloned()
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PriceConfig {
+    /// Price sources in the order they are tried, falling back to the next one when a
+    /// source can't price a token (overridden by `PRICE_SOURCES`, comma-separated)
+    pub sources: Vec<PriceSourceKind>,
+    /// CoinGecko API base URL
+    pub coingecko_api_url: String,
+    /// CoinGecko API key, sent as `x-cg-demo-api-key` (overridden by `COINGECKO_API_KEY`)
+    pub coingecko_api_key: Option<String>,
+    /// Timeout for price API requests in seconds
+    pub timeout_secs: u64,
+    /// How long fetched prices are reused, in seconds. 0 disables the cache
+    /// (overridden by `PRICE_CACHE_TTL_SECS`)
+    pub cache_ttl_secs: u64,
+    /// Averaging window of on-chain Uniswap V3 TWAP prices, in seconds
+    pub twap_window_secs: u32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TokenRegistryConfig {
+    /// Load the token list bundled with the server
+    pub bundled: bool,
+    /// Extra token list files in the Uniswap token list format
+    /// (overridden by `TOKEN_LIST_FILES`, comma-separated)
+    pub files: Vec<PathBuf>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    /// Maximum slippage tolerance accepted by swap tools, as a percentage
+    pub max_slippage_tolerance: Decimal,
+    /// Price impact, as a percentage, above which swap estimates carry a warning
+    pub price_impact_warning: Decimal,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WalletConfig {
+    /// Load the wallet and expose the `send_transaction` and `execute_swap` tools, which sign
+    /// and broadcast transactions (overridden by `WALLET_ENABLED`)
+    pub enabled: bool,
+    /// Encrypted JSON keystores (Web3 Secret Storage), decrypted with the password in
+    /// `WALLET_KEYSTORE_PASSWORD` (overridden by `WALLET_KEYSTORES`, comma-separated)
+    pub keystores: Vec<PathBuf>,
+    /// BIP-32 derivation paths of the accounts derived from the BIP-39 mnemonic in
+    /// `WALLET_MNEMONIC`
+    pub derivation_paths: Vec<String>,
+}
+
+/// Spending policy of the wallet. Every rule is optional; an empty policy allows everything
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PolicyConfig {
+    /// Spend caps keyed by chain, then by token contract address or the chain's native token
+    /// symbol (e.g. "ETH", "POL")
+    pub spend_limits: HashMap<String, HashMap<String, SpendLimit>>,
+    /// Reject spending tokens that have no entry in `spend_limits`
+    pub deny_unlisted_tokens: bool,
+    /// Addresses that may receive funds from the wallet (swap and transfer recipients),
+    /// besides the wallet's own accounts. Empty allows any recipient
+    pub allowed_recipients: Vec<String>,
+    /// Contracts the wallet may call, and spenders it may approve. Empty allows any contract
+    pub allowed_contracts: Vec<String>,
+    /// Maximum slippage tolerance of executed swaps, as a percentage
+    pub max_slippage_tolerance: Option<Decimal>,
+    /// Maximum price impact of executed swaps, as a percentage. Swaps whose price impact
+    /// can't be calculated are rejected
+    pub max_price_impact: Option<Decimal>,
+    /// Maximum `maxFeePerGas` of signed transactions, in gwei
+    pub max_gas_price_gwei: Option<Decimal>,
+}
+
+/// Caps on spending one token on one chain, in human-readable units (e.g. 1.5 for 1.5 ETH)
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SpendLimit {
+    /// Maximum amount spent by one transaction
+    pub per_transaction: Option<Decimal>,
+    /// Maximum amount spent per UTC day
+    pub per_day: Option<Decimal>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            log_level: "info".to_string(),
+            default_chain: "mainnet".to_string(),
+            server: ServerConfig::default(),
+            rpc: RpcConfig::default(),
+            chains: HashMap::new(),
+            prices: PriceConfig::default(),
+            token_lists: HashMap::new(),
+            token_registry: TokenRegistryConfig::default(),
+            limits: LimitsConfig::default(),
+            wallet: WalletConfig::default(),
+            policy: PolicyConfig::default(),
+        }
+    }
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            transport: None,
+            host: "127.0.0.1".to_string(),
+            port: 0,
+        }
+    }
+}
+
+impl Default for RpcConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: 2,
+            initial_backoff_ms: 250,
+            unhealthy_cooldown_secs: 30,
+            quorum: 0,
+        }
+    }
+}
+
+impl Default for PriceConfig {
+    fn default() -> Self {
+        Self {
+            sources: vec![PriceSourceKind::Coingecko, PriceSourceKind::Uniswap],
+            coingecko_api_url: "https://api.coingecko.com/api/v3".to_string(),
+            coingecko_api_key: None,
+            timeout_secs: 10,
+            cache_ttl_secs: 60,
+            twap_window_secs: 1800,
+        }
+    }
+}
+
+impl Default for TokenRegistryConfig {
+    fn default() -> Self {
+        Self {
+            bundled: true,
+            files: Vec::new(),
+        }
+    }
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        Self {
+            max_slippage_tolerance: Decimal::from(5),
+            price_impact_warning: Decimal::from(3),
+        }
+    }
+}
+
+impl Default for WalletConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            keystores: Vec::new(),
+            derivation_paths: vec!["m/44'/60'/0'/0/0".to_string()],
+        }
+    }
+}
+
+impl Config {
+    /// Load configuration from `path` (if given), apply environment variable overrides and validate.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let mut config = match path {
+            Some(path) => {
+                let contents = std::fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read config file {}", path.display()))?;
+                toml::from_str::<Config>(&contents)
+                    .with_context(|| format!("Invalid config file {}", path.display()))?
+            }
+            None => Config::default(),
+        };
+
+        config.apply_env_overrides()?;
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    fn apply_env_overrides(&mut self) -> Result<()> {
+        if let Ok(level) = env::var("RUST_LOG") {
+            self.log_level = level;
+        }
+        if let Ok(chain) = env::var("DEFAULT_CHAIN") {
+            self.default_chain = chain;
+        }
+        if let Ok(transport) = env::var("SERVER_TRANSPORT") {
+            self.server.transport = Some(transport.parse().context("Invalid SERVER_TRANSPORT")?);
+        }
+        if let Ok(host) = env::var("SERVER_HOST") {
+            self.server.host = host;
+        }
+        if let Ok(port) = env::var("SERVER_PORT") {
+            self.server.port = port
+                .parse()
+                .with_context(|| format!("Invalid SERVER_PORT: {port}"))?;
+        }
+        if let Ok(api_key) = env::var("COINGECKO_API_KEY") {
+            self.prices.coingecko_api_key = Some(api_key);
+        }
+        if let Ok(ttl) = env::var("PRICE_CACHE_TTL_SECS") {
+            self.prices.cache_ttl_secs = ttl
+                .parse()
+                .with_context(|| format!("Invalid PRICE_CACHE_TTL_SECS: {ttl}"))?;
+        }
+        if let Ok(files) = env::var("TOKEN_LIST_FILES") {
+            self.token_registry.files = files
+                .split(',')
+                .map(str::trim)
+                .filter(|file| !file.is_empty())
+                .map(PathBuf::from)
+                .collect();
+        }
+        if let Ok(enabled) = env::var("WALLET_ENABLED") {
+            self.wallet.enabled = enabled
+                .parse()
+                .with_context(|| format!("Invalid WALLET_ENABLED: {enabled}"))?;
+        }
+        if let Ok(keystores) = env::var("WALLET_KEYSTORES") {
+            self.wallet.keystores = keystores
+                .split(',')
+                .map(str::trim)
+                .filter(|keystore| !keystore.is_empty())
+                .map(PathBuf::from)
+                .collect();
+        }
+        if let Ok(sources) = env::var("PRICE_SOURCES") {
+            self.prices.sources = sources
+                .split(',')
+                .filter(|source| !source.trim().is_empty())
+                .map(PriceSourceKind::from_str)
+                .collect::<Result<_>>()
+                .context("Invalid PRICE_SOURCES")?;
+        }
+
+        // Re-key chain settings by canonical chain name so env overrides land on the same entry
+        let mut chains_by_name = HashMap::new();
+        for (key, settings) in self.chains.drain() {
+            let chain = chains::find_chain(&key).context("Invalid [chains] entry")?;
+            if chains_by_name
+                .insert(chain.name.to_string(), settings)
+                .is_some()
+            {
+                anyhow::bail!("Chain {} is configured more than once", chain.name);
+            }
+        }
+        self.chains = chains_by_name;
+
+        for chain in CHAINS {
+            if let Ok(rpc_urls) = env::var(chain.rpc_env_var) {
+                let settings = self.chains.entry(chain.name.to_string()).or_default();
+                settings.rpc_url = None;
+                settings.rpc_urls = rpc_urls
+                    .split(',')
+                    .map(|url| url.trim().to_string())
+                    .filter(|url| !url.is_empty())
+                    .collect();
+            }
+        }
+
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<()> {
+        chains::find_chain(&self.default_chain).context("Invalid default_chain")?;
+
+        for (name, settings) in &self.chains {
+            let urls = settings.urls();
+            for rpc_url in &urls {
+                if !rpc_url.starts_with("http://") && !rpc_url.starts_with("https://") {
+                    anyhow::bail!("Invalid RPC URL for {name}: {rpc_url} (expected http(s)://)");
+                }
+            }
+
+            let quorum = settings.quorum.unwrap_or(self.rpc.quorum);
+            if quorum > 1 && quorum > urls.len() {
+                anyhow::bail!(
+                    "Quorum of {quorum} for {name} requires at least {quorum} RPC URLs, got {}",
+                    urls.len()
+                );
+            }
+        }
+
+        // Other chains without RPC URLs are left out of the registry under quorum, see
+        // `rpc_endpoints`
+        let default_chain = chains::find_chain(&self.default_chain)?;
+        if self.rpc.quorum > 1 && !self.chains.contains_key(default_chain.name) {
+            anyhow::bail!(
+                "rpc.quorum is {} but no RPC URLs are configured for the default chain {}",
+                self.rpc.quorum,
+                default_chain.name
+            );
+        }
+
+        let transport = self.transport();
+        if transport != TransportMode::Stdio && self.server.port == 0 {
+            anyhow::bail!(
+                "server.port (or SERVER_PORT) must be non-zero for {transport:?} transport"
+            );
+        }
+
+        if self.prices.timeout_secs == 0 {
+            anyhow::bail!("prices.timeout_secs must be greater than 0");
+        }
+        if self.prices.sources.is_empty() {
+            anyhow::bail!("prices.sources must list at least one price source");
+        }
+        if self.prices.twap_window_secs == 0 {
+            anyhow::bail!("prices.twap_window_secs must be greater than 0");
+        }
+        for (i, source) in self.prices.sources.iter().enumerate() {
+            if self.prices.sources[..i].contains(source) {
+                anyhow::bail!("Price source {source:?} is listed more than once");
+            }
+        }
+
+        for (list, tokens) in &self.token_lists {
+            for token in tokens {
+                Address::from_str(token)
+                    .with_context(|| format!("Invalid address {token} in token list {list}"))?;
+            }
+        }
+
+        let max_slippage = self.limits.max_slippage_tolerance;
+        if max_slippage < Decimal::ZERO || max_slippage > Decimal::from(100) {
+            anyhow::bail!("limits.max_slippage_tolerance must be between 0 and 100");
+        }
+        let impact_warning = self.limits.price_impact_warning;
+        if impact_warning < Decimal::ZERO || impact_warning > Decimal::from(100) {
+            anyhow::bail!("limits.price_impact_warning must be between 0 and 100");
+        }
+
+        self.validate_policy()
+    }
+
+    fn validate_policy(&self) -> Result<()> {
+        let policy = &self.policy;
+        let mut limited_chains = HashSet::new();
+        for (chain, limits) in &policy.spend_limits {
+            let chain = chains::find_chain(chain).context("Invalid policy.spend_limits entry")?;
+            if !limited_chains.insert(chain.id) {
+                anyhow::bail!(
+                    "Chain {} is configured more than once in policy.spend_limits",
+                    chain.name
+                );
+            }
+            for (token, limit) in limits {
+                if Address::from_str(token).is_err() && !chain.is_native_token(token) {
+                    anyhow::bail!(
+                        "Invalid token {token} in policy.spend_limits.{} (expected an address or {})",
+                        chain.name,
+                        chain.native_symbol
+                    );
+                }
+                for amount in [limit.per_transaction, limit.per_day].into_iter().flatten() {
+                    if amount < Decimal::ZERO {
+                        anyhow::bail!(
+                            "Spend limits of {token} in policy.spend_limits.{} must not be negative",
+                            chain.name
+                        );
+                    }
+                }
+            }
+        }
+        for (list, addresses) in [
+            ("allowed_recipients", &policy.allowed_recipients),
+            ("allowed_contracts", &policy.allowed_contracts),
+        ] {
+            for address in addresses {
+                Address::from_str(address)
+                    .with_context(|| format!("Invalid address {address} in policy.{list}"))?;
+            }
+        }
+        for (rule, percent) in [
+            ("max_slippage_tolerance", policy.max_slippage_tolerance),
+            ("max_price_impact", policy.max_price_impact),
+        ] {
+            if let Some(percent) = percent {
+                if percent < Decimal::ZERO || percent > Decimal::from(100) {
+                    anyhow::bail!("policy.{rule} must be between 0 and 100");
+                }
+            }
+        }
+        if policy
+            .max_gas_price_gwei
+            .is_some_and(|gwei| gwei < Decimal::ZERO)
+        {
+            anyhow::bail!("policy.max_gas_price_gwei must not be negative");
+        }
+
+        Ok(())
+    }
+
+    /// Transport mode, falling back to TCP if a port is set, otherwise stdio
+    pub fn transport(&self) -> TransportMode {
+        self.server.transport.unwrap_or(if self.server.port > 0 {
+            TransportMode::Tcp
+        } else {
+            TransportMode::Stdio
+        })
+    }
+
+    /// RPC endpoints for every supported chain, using the chain's public endpoint when none is
+    /// configured. Chains without configured RPC URLs are left out when a quorum is required,
+    /// as a single public endpoint can't meet it
+    pub fn rpc_endpoints(&self) -> HashMap<u64, RpcEndpoints> {
+        CHAINS
+            .iter()
+            .filter_map(|chain| {
+                let settings = self.chains.get(chain.name).cloned().unwrap_or_default();
+                let quorum = settings.quorum.unwrap_or(self.rpc.quorum);
+
+                let mut urls = settings.urls();
+                if urls.is_empty() {
+                    if quorum > 1 {
+                        warn!(
+                            "No RPC URLs configured for {} to meet a quorum of {}, the chain is disabled",
+                            chain.name, quorum
+                        );
+                        return None;
+                    }
+                    warn!(
+                        "No RPC URL configured for {}, using public endpoint {}",
+                        chain.name, chain.default_rpc_url
+                    );
+                    urls.push(chain.default_rpc_url.to_string());
+                }
+
+                let endpoints = RpcEndpoints {
+                    urls,
+                    settings: RpcSettings {
+                        max_retries: self.rpc.max_retries,
+                        initial_backoff: Duration::from_millis(self.rpc.initial_backoff_ms),
+                        unhealthy_cooldown: Duration::from_secs(self.rpc.unhealthy_cooldown_secs),
+                        quorum,
+                    },
+                };
+                Some((chain.id, endpoints))
+            })
+            .collect()
+    }
+}
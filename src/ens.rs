@@ -0,0 +1,150 @@
+// Copyright 2025 chenjjiaa
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::{Context, Result};
+use ethabi::{ParamType, Token};
+use ethers::{
+    providers::ens::{namehash, reverse_address, ENS_ADDRESS},
+    types::{Address, H256},
+};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::abi;
+use crate::rpc::RpcProvider;
+
+/// An ENS name resolved while handling a tool call, echoed back in the tool output
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResolvedName {
+    /// ENS name as given in the input
+    pub name: String,
+    /// Address the name resolved to
+    pub address: String,
+}
+
+/// Whether `input` should be treated as an ENS name rather than a hex address or token symbol
+pub fn is_ens_name(input: &str) -> bool {
+    let input = input.trim();
+    !input.starts_with("0x") && input.contains('.') && !input.contains(char::is_whitespace)
+}
+
+/// Resolve an ENS name to an address through the ENS registry and the name's resolver.
+///
+/// `provider` must be connected to Ethereum mainnet, where the ENS registry lives.
+pub async fn resolve_name(provider: &RpcProvider, name: &str) -> Result<Address> {
+    let name = name.trim().to_lowercase();
+    let node = namehash(&name);
+
+    let resolver = get_resolver(provider, node)
+        .await?
+        .with_context(|| format!("No ENS resolver set for {name}"))?;
+
+    let addr_fn = abi::view_function(
+        "addr",
+        vec![ParamType::FixedBytes(32)],
+        vec![ParamType::Address],
+    );
+
+    let address = match abi::call(provider, resolver, &addr_fn, &[node_token(node)], None)
+        .await?
+        .first()
+    {
+        Some(Token::Address(address)) => *address,
+        _ => anyhow::bail!("Unexpected ENS addr result format"),
+    };
+
+    if address.is_zero() {
+        anyhow::bail!("ENS name {name} does not resolve to an address");
+    }
+
+    info!("Resolved ENS name {} to {:?}", name, address);
+    Ok(address)
+}
+
+/// Look up the primary ENS name of `address`.
+///
+/// Returns `None` if no reverse record is set, or if the reverse record doesn't resolve
+/// back to `address` (anyone can claim any name in their reverse record).
+pub async fn lookup_address(provider: &RpcProvider, address: Address) -> Result<Option<String>> {
+    let node = namehash(&reverse_address(address));
+
+    let Some(resolver) = get_resolver(provider, node).await? else {
+        return Ok(None);
+    };
+
+    let name_fn = abi::view_function(
+        "name",
+        vec![ParamType::FixedBytes(32)],
+        vec![ParamType::String],
+    );
+
+    let name = match abi::call(provider, resolver, &name_fn, &[node_token(node)], None)
+        .await?
+        .first()
+    {
+        Some(Token::String(name)) => name.clone(),
+        _ => anyhow::bail!("Unexpected ENS name result format"),
+    };
+
+    if name.is_empty() {
+        return Ok(None);
+    }
+
+    match resolve_name(provider, &name).await {
+        Ok(forward) if forward == address => Ok(Some(name)),
+        Ok(forward) => {
+            warn!(
+                "Reverse record of {:?} is {}, which resolves to {:?}; ignoring it",
+                address, name, forward
+            );
+            Ok(None)
+        }
+        Err(e) => {
+            warn!(
+                "Reverse record of {:?} is {}, which doesn't resolve: {:#}",
+                address, name, e
+            );
+            Ok(None)
+        }
+    }
+}
+
+/// Resolver contract of `node` from the ENS registry, `None` if unset
+async fn get_resolver(provider: &RpcProvider, node: H256) -> Result<Option<Address>> {
+    let resolver_fn = abi::view_function(
+        "resolver",
+        vec![ParamType::FixedBytes(32)],
+        vec![ParamType::Address],
+    );
+
+    match abi::call(
+        provider,
+        ENS_ADDRESS,
+        &resolver_fn,
+        &[node_token(node)],
+        None,
+    )
+    .await?
+    .first()
+    {
+        Some(Token::Address(resolver)) if resolver.is_zero() => Ok(None),
+        Some(Token::Address(resolver)) => Ok(Some(*resolver)),
+        _ => anyhow::bail!("Unexpected ENS resolver result format"),
+    }
+}
+
+/// The `bytes32 node` argument of ENS registry and resolver calls
+fn node_token(node: H256) -> Token {
+    Token::FixedBytes(node.as_bytes().to_vec())
+}
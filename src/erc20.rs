@@ -0,0 +1,287 @@
+// Copyright 2025 chenjjiaa
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::{Context, Result};
+use ethabi::{Function, Param, ParamType, StateMutability, Token};
+use ethers::{
+    prelude::*,
+    types::{Address, U256},
+};
+use rust_decimal::Decimal;
+use std::str::FromStr;
+use tracing::info;
+
+use crate::abi;
+use crate::chains::Chain;
+use crate::multicall::Multicall;
+use crate::rpc::RpcProvider;
+
+/// ERC20 metadata of a contract
+#[derive(Debug, Clone)]
+pub struct TokenInfo {
+    /// Token name, if the contract exposes one
+    pub name: Option<String>,
+    /// Token symbol, if the contract exposes one
+    pub symbol: Option<String>,
+    pub decimals: u8,
+    pub total_supply: U256,
+}
+
+/// `balanceOf(address owner)`
+pub fn balance_of_function() -> Function {
+    abi::view_function(
+        "balanceOf",
+        vec![ParamType::Address],
+        vec![ParamType::Uint(256)],
+    )
+}
+
+/// `allowance(address owner, address spender)`
+pub fn allowance_function() -> Function {
+    abi::view_function(
+        "allowance",
+        vec![ParamType::Address, ParamType::Address],
+        vec![ParamType::Uint(256)],
+    )
+}
+
+/// `approve(address spender, uint256 amount)`
+pub fn approve_function() -> Function {
+    address_amount_function("approve", "spender")
+}
+
+/// `transfer(address to, uint256 amount)`
+pub fn transfer_function() -> Function {
+    address_amount_function("transfer", "to")
+}
+
+/// A state-changing function taking an address and an amount and returning a bool
+fn address_amount_function(name: &str, address: &str) -> Function {
+    Function {
+        name: name.to_string(),
+        inputs: vec![
+            Param {
+                name: address.to_string(),
+                kind: ParamType::Address,
+                internal_type: None,
+            },
+            Param {
+                name: "amount".to_string(),
+                kind: ParamType::Uint(256),
+                internal_type: None,
+            },
+        ],
+        outputs: vec![Param {
+            name: "".to_string(),
+            kind: ParamType::Bool,
+            internal_type: None,
+        }],
+        #[allow(deprecated)]
+        constant: None,
+        state_mutability: StateMutability::NonPayable,
+    }
+}
+
+pub fn decimals_function() -> Function {
+    abi::view_function("decimals", vec![], vec![ParamType::Uint(8)])
+}
+
+/// `symbol()` declared as returning `string`. Use [`decode_string_or_bytes32`] on the raw
+/// return data, as some tokens return `bytes32` instead
+pub fn symbol_function() -> Function {
+    abi::view_function("symbol", vec![], vec![ParamType::String])
+}
+
+/// `name()` declared as returning `string`. Use [`decode_string_or_bytes32`] on the raw
+/// return data, as some tokens return `bytes32` instead
+pub fn name_function() -> Function {
+    abi::view_function("name", vec![], vec![ParamType::String])
+}
+
+pub fn total_supply_function() -> Function {
+    abi::view_function("totalSupply", vec![], vec![ParamType::Uint(256)])
+}
+
+/// Extract a single `uint` return value
+pub fn decode_uint(tokens: Vec<Token>) -> Result<U256> {
+    match tokens.first() {
+        Some(Token::Uint(val)) => Ok(*val),
+        _ => anyhow::bail!("Unexpected uint result format"),
+    }
+}
+
+/// Extract a `decimals()` return value, rejecting values that don't fit in a `u8`
+pub fn decode_decimals(tokens: Vec<Token>) -> Result<u8> {
+    let decimals = decode_uint(tokens)?;
+    if decimals > U256::from(u8::MAX) {
+        anyhow::bail!("Invalid decimals value: {decimals}");
+    }
+    Ok(decimals.as_u32() as u8)
+}
+
+/// Decode `name()` / `symbol()` return data as an ABI `string`, falling back to a
+/// NUL-padded `bytes32` as returned by early tokens such as MKR and SAI
+pub fn decode_string_or_bytes32(data: &[u8]) -> Result<String> {
+    if let Ok(mut tokens) = ethabi::decode(&[ParamType::String], data) {
+        if let Some(Token::String(value)) = tokens.pop() {
+            return Ok(value);
+        }
+    }
+
+    if data.len() == 32 {
+        let end = data.iter().position(|b| *b == 0).unwrap_or(data.len());
+        return String::from_utf8(data[..end].to_vec()).context("bytes32 value is not UTF-8");
+    }
+
+    anyhow::bail!("Unexpected string result format ({} bytes)", data.len())
+}
+
+/// Read name, symbol, decimals and total supply of `token` in a single Multicall3 batch.
+///
+/// `name` and `symbol` are optional in ERC20 and left empty if the calls fail, while a
+/// failing `decimals` or `totalSupply` call is an error.
+pub async fn get_token_info(
+    provider: &RpcProvider,
+    chain: &Chain,
+    token: Address,
+) -> Result<TokenInfo> {
+    let decimals_fn = decimals_function();
+    let total_supply_fn = total_supply_function();
+
+    let mut multicall = Multicall::new(provider, chain)?;
+    multicall.add_call(token, &name_function(), &[])?;
+    multicall.add_call(token, &symbol_function(), &[])?;
+    multicall.add_call(token, &decimals_fn, &[])?;
+    multicall.add_call(token, &total_supply_fn, &[])?;
+
+    let mut results = multicall.call_raw().await?.into_iter();
+    let mut next = || results.next().context("Missing token metadata result");
+    let (name, symbol, decimals, total_supply) = (next()?, next()?, next()?, next()?);
+
+    let name = name.and_then(|data| decode_string_or_bytes32(&data)).ok();
+    let symbol = symbol.and_then(|data| decode_string_or_bytes32(&data)).ok();
+    let decimals = decimals
+        .and_then(|data| Ok(decimals_fn.decode_output(&data)?))
+        .and_then(decode_decimals)
+        .with_context(|| format!("Failed to get decimals of {token:?}"))?;
+    let total_supply = total_supply
+        .and_then(|data| Ok(total_supply_fn.decode_output(&data)?))
+        .and_then(decode_uint)
+        .with_context(|| format!("Failed to get total supply of {token:?}"))?;
+
+    info!(
+        "Token {:?}: {:?} ({:?}), {} decimals",
+        token, name, symbol, decimals
+    );
+
+    Ok(TokenInfo {
+        name,
+        symbol,
+        decimals,
+        total_supply,
+    })
+}
+
+/// Read `decimals()` of several tokens in a single Multicall3 batch.
+///
+/// Fails if any call fails, rather than assuming a default.
+pub async fn get_decimals(
+    provider: &RpcProvider,
+    chain: &Chain,
+    tokens: &[Address],
+) -> Result<Vec<u8>> {
+    let decimals_fn = decimals_function();
+
+    let mut multicall = Multicall::new(provider, chain)?;
+    for token in tokens {
+        multicall.add_call(*token, &decimals_fn, &[])?;
+    }
+
+    multicall
+        .call()
+        .await?
+        .into_iter()
+        .zip(tokens)
+        .map(|(result, token)| {
+            result
+                .and_then(decode_decimals)
+                .with_context(|| format!("Failed to get decimals of {token:?}"))
+        })
+        .collect()
+}
+
+/// Convert a raw token amount to a decimal amount
+pub fn to_decimal_units(raw: U256, decimals: u8) -> Result<Decimal> {
+    let formatted = ethers::utils::format_units(raw, u32::from(decimals))
+        .context("Failed to format token amount")?;
+    Decimal::from_str(&formatted).context("Failed to convert amount to Decimal")
+}
+
+/// Whether `address` has contract code
+pub async fn is_contract(provider: &RpcProvider, address: Address) -> Result<bool> {
+    let code = provider
+        .get_code(address, None)
+        .await
+        .context("Failed to get contract code")?;
+    Ok(!code.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bytes32(value: &str) -> Vec<u8> {
+        let mut data = value.as_bytes().to_vec();
+        data.resize(32, 0);
+        data
+    }
+
+    #[test]
+    fn decodes_string_symbols() {
+        let data = ethabi::encode(&[Token::String("USDC".to_string())]);
+        assert_eq!(decode_string_or_bytes32(&data).unwrap(), "USDC");
+    }
+
+    #[test]
+    fn decodes_bytes32_symbols() {
+        // MKR's symbol()
+        assert_eq!(decode_string_or_bytes32(&bytes32("MKR")).unwrap(), "MKR");
+        let full = "A".repeat(32);
+        assert_eq!(decode_string_or_bytes32(full.as_bytes()).unwrap(), full);
+    }
+
+    #[test]
+    fn rejects_other_symbol_data() {
+        assert!(decode_string_or_bytes32(&[]).is_err());
+        assert!(decode_string_or_bytes32(&[0xff; 32]).is_err());
+    }
+
+    #[test]
+    fn decodes_decimals() {
+        let data = ethabi::encode(&[Token::Uint(U256::from(6))]);
+        let tokens = decimals_function().decode_output(&data).unwrap();
+        assert_eq!(decode_decimals(tokens).unwrap(), 6);
+    }
+
+    #[test]
+    fn empty_decimals_are_an_error() {
+        assert!(decimals_function().decode_output(&[]).is_err());
+        assert!(decode_decimals(Vec::new()).is_err());
+    }
+
+    #[test]
+    fn decimals_must_fit_in_u8() {
+        assert!(decode_decimals(vec![Token::Uint(U256::from(256))]).is_err());
+    }
+}
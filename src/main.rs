@@ -12,71 +12,155 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+mod abi;
+mod allowance;
+mod blocks;
+mod chains;
+mod config;
+mod ens;
+mod erc20;
+mod multicall;
+mod policy;
+mod prices;
+mod rpc;
 mod server;
 mod swap;
+mod tokens;
+mod wallet;
 
 use anyhow::{Context, Result};
+use clap::Parser;
 use dotenv::dotenv;
-use rmcp::ServiceExt;
-use std::env;
+use rmcp::{
+    transport::{
+        streamable_http_server::session::local::LocalSessionManager, StreamableHttpServerConfig,
+        StreamableHttpService,
+    },
+    ServiceExt,
+};
 use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
 use tokio::net::TcpListener;
 use tracing::info;
 
+use chains::ChainRegistry;
+use config::{Config, TransportMode};
 use server::EthMcpServer;
 
-async fn run_server(server: EthMcpServer, server_host: String, server_port: u16) -> Result<()> {
-    // Determine transport mode: TCP if port is set, otherwise stdio
-    if server_port > 0 {
-        // TCP mode
-        let addr: SocketAddr = format!("{server_host}:{server_port}")
-            .parse()
-            .context("Invalid server address")?;
-
-        let listener = TcpListener::bind(&addr)
-            .await
-            .context("Failed to bind TCP listener")?;
-
-        let actual_addr = listener
-            .local_addr()
-            .context("Failed to get local address")?;
-
-        info!(
-            "MCP server listening on {}:{}",
-            actual_addr.ip(),
-            actual_addr.port()
-        );
-
-        // Accept connections and serve each one
-        loop {
-            match listener.accept().await {
-                Ok((stream, peer_addr)) => {
-                    info!("New connection from {}", peer_addr);
-                    let server_clone = server.clone();
-
-                    tokio::spawn(async move {
-                        let (read, write) = tokio::io::split(stream);
-                        if let Err(e) = server_clone.serve((read, write)).await {
-                            info!("Connection {} closed with error: {}", peer_addr, e);
-                        } else {
-                            info!("Connection {} closed gracefully", peer_addr);
-                        }
-                    });
-                }
-                Err(e) => {
-                    info!("Failed to accept connection: {}", e);
-                }
+/// Path the Streamable HTTP transport is mounted on
+const HTTP_MCP_PATH: &str = "/mcp";
+
+/// Ethereum MCP server
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Cli {
+    /// Path to a TOML configuration file. Environment variables override values from the file
+    #[arg(long, env = "ETH_MCP_CONFIG")]
+    config: Option<PathBuf>,
+}
+
+async fn bind_listener(server_host: &str, server_port: u16) -> Result<TcpListener> {
+    let addr: SocketAddr = format!("{server_host}:{server_port}")
+        .parse()
+        .context("Invalid server address")?;
+
+    TcpListener::bind(&addr)
+        .await
+        .context("Failed to bind TCP listener")
+}
+
+async fn run_server(
+    server: EthMcpServer,
+    transport: TransportMode,
+    server_host: String,
+    server_port: u16,
+) -> Result<()> {
+    match transport {
+        TransportMode::Tcp => run_tcp_server(server, &server_host, server_port).await,
+        TransportMode::Http => run_http_server(server, &server_host, server_port).await,
+        TransportMode::Stdio => run_stdio_server(server).await,
+    }
+}
+
+async fn run_tcp_server(server: EthMcpServer, server_host: &str, server_port: u16) -> Result<()> {
+    let listener = bind_listener(server_host, server_port).await?;
+
+    let actual_addr = listener
+        .local_addr()
+        .context("Failed to get local address")?;
+
+    info!(
+        "MCP server listening on {}:{}",
+        actual_addr.ip(),
+        actual_addr.port()
+    );
+
+    // Accept connections and serve each one
+    loop {
+        match listener.accept().await {
+            Ok((stream, peer_addr)) => {
+                info!("New connection from {}", peer_addr);
+                let server_clone = server.clone();
+
+                tokio::spawn(async move {
+                    let (read, write) = tokio::io::split(stream);
+                    if let Err(e) = server_clone.serve((read, write)).await {
+                        info!("Connection {} closed with error: {}", peer_addr, e);
+                    } else {
+                        info!("Connection {} closed gracefully", peer_addr);
+                    }
+                });
+            }
+            Err(e) => {
+                info!("Failed to accept connection: {}", e);
             }
         }
-    } else {
-        // Stdio mode (default, for MCP standard)
-        info!("Starting MCP server on stdio");
-        let running_service = server
-            .serve((tokio::io::stdin(), tokio::io::stdout()))
-            .await?;
-        // Wait for the service to finish (will wait for client requests)
-        running_service.waiting().await?;
     }
+}
+
+async fn run_http_server(server: EthMcpServer, server_host: &str, server_port: u16) -> Result<()> {
+    // Each MCP session gets its own clone of the server; sessions are tracked
+    // in memory and identified by the Mcp-Session-Id header
+    let service = StreamableHttpService::new(
+        move || Ok(server.clone()),
+        Arc::new(LocalSessionManager::default()),
+        StreamableHttpServerConfig::default(),
+    );
+
+    let router = axum::Router::new().nest_service(HTTP_MCP_PATH, service);
+
+    let listener = bind_listener(server_host, server_port).await?;
+
+    let actual_addr = listener
+        .local_addr()
+        .context("Failed to get local address")?;
+
+    info!(
+        "MCP Streamable HTTP server listening on http://{}:{}{}",
+        actual_addr.ip(),
+        actual_addr.port(),
+        HTTP_MCP_PATH
+    );
+
+    axum::serve(listener, router)
+        .with_graceful_shutdown(async {
+            tokio::signal::ctrl_c().await.ok();
+            info!("Shutting down MCP Streamable HTTP server");
+        })
+        .await
+        .context("MCP Streamable HTTP server failed")?;
+
+    Ok(())
+}
+
+async fn run_stdio_server(server: EthMcpServer) -> Result<()> {
+    info!("Starting MCP server on stdio");
+    let running_service = server
+        .serve((tokio::io::stdin(), tokio::io::stdout()))
+        .await?;
+    // Wait for the service to finish (will wait for client requests)
+    running_service.waiting().await?;
 
     Ok(())
 }
@@ -85,37 +169,40 @@ async fn run_server(server: EthMcpServer, server_host: String, server_port: u16)
 async fn main() -> Result<()> {
     dotenv().ok();
 
-    // Get log level from environment or use default
-    let log_level = env::var("RUST_LOG").unwrap_or_else(|_| "info".to_string());
+    let cli = Cli::parse();
+    let config = Config::load(cli.config.as_deref())?;
 
-    // Initialize tracing with log level from environment or default
+    // Initialize tracing with log level from environment or config
     // Use stderr for logs since stdout is used for MCP protocol communication
-    let filter = tracing_subscriber::EnvFilter::try_from_default_env()
-        .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new(&log_level));
+    let filter = tracing_subscriber::EnvFilter::try_new(&config.log_level)
+        .with_context(|| format!("Invalid log level: {}", config.log_level))?;
 
     tracing_subscriber::fmt()
         .with_env_filter(filter)
         .with_writer(std::io::stderr) // Use stderr for logs, stdout is for MCP
         .init();
-    info!("Log level: {}", log_level);
+    info!("Log level: {}", config.log_level);
+    if let Some(path) = &cli.config {
+        info!("Loaded configuration from {}", path.display());
+    }
 
-    // Get RPC URL from environment or use default public endpoint
-    let rpc_url =
-        env::var("ETH_RPC_URL").unwrap_or_else(|_| "https://eth.llamarpc.com".to_string());
-    info!("Starting Ethereum MCP Server with RPC: {}", rpc_url);
+    let default_chain = chains::find_chain(&config.default_chain)?;
+    info!(
+        "Starting Ethereum MCP Server with default chain: {}",
+        default_chain.name
+    );
 
-    // Get server host and port from environment
-    let server_host = env::var("SERVER_HOST").unwrap_or_else(|_| "127.0.0.1".to_string());
-    let server_port = env::var("SERVER_PORT")
-        .unwrap_or_else(|_| "0".to_string())
-        .parse::<u16>()
-        .unwrap_or(0);
+    let transport = config.transport();
+    let server_host = config.server.host.clone();
+    let server_port = config.server.port;
+    info!("Transport mode: {:?}", transport);
 
     // Create server instance
-    let server = EthMcpServer::new(rpc_url)?;
+    let chains = ChainRegistry::new(config.rpc_endpoints(), default_chain.id)?;
+    let server = EthMcpServer::new(chains, config)?;
 
     // Run the server with the specified transport mode
-    run_server(server, server_host, server_port).await?;
+    run_server(server, transport, server_host, server_port).await?;
 
     Ok(())
 }
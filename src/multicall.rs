@@ -0,0 +1,366 @@
+// Copyright 2025 chenjjiaa
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::{Context, Result};
+use ethabi::{Function, Param, ParamType, StateMutability, Token};
+use ethers::{
+    prelude::*,
+    types::{Address, BlockId, TransactionRequest},
+};
+use std::str::FromStr;
+use tracing::{debug, warn};
+
+use crate::chains::Chain;
+use crate::rpc::RpcProvider;
+
+/// Selector of the standard `Error(string)` revert payload
+const ERROR_STRING_SELECTOR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0];
+
+/// Outcome of a single call in a batch: the decoded return values, or why it failed
+pub type CallResult = Result<Vec<Token>>;
+
+/// Outcome of a single call in a batch: the undecoded return data, or why it failed
+pub type RawCallResult = Result<Vec<u8>>;
+
+struct Call {
+    target: Address,
+    function: Function,
+    call_data: Vec<u8>,
+}
+
+/// Batches read-only contract calls into a single `eth_call` to Multicall3 `aggregate3`.
+///
+/// Every sub-call is sent with `allowFailure = true`, so a revert in one call doesn't
+/// fail the others; failures are reported per call in the result of [`Multicall::call`].
+pub struct Multicall<'a> {
+    provider: &'a RpcProvider,
+    address: Address,
+    block: Option<BlockId>,
+    calls: Vec<Call>,
+}
+
+impl<'a> Multicall<'a> {
+    pub fn new(provider: &'a RpcProvider, chain: &Chain) -> Result<Self> {
+        Ok(Self {
+            provider,
+            address: Address::from_str(chain.contracts.multicall3)
+                .context("Invalid Multicall3 address")?,
+            block: None,
+            calls: Vec::new(),
+        })
+    }
+
+    /// Execute the batch at `block` instead of the latest block
+    pub fn at_block(mut self, block: BlockId) -> Self {
+        self.block = Some(block);
+        self
+    }
+
+    /// Queue a call to `function` on `target`. Returns the index of its result
+    pub fn add_call(
+        &mut self,
+        target: Address,
+        function: &Function,
+        args: &[Token],
+    ) -> Result<usize> {
+        let call_data = function
+            .encode_input(args)
+            .with_context(|| format!("Failed to encode {} call", function.name))?;
+
+        self.calls.push(Call {
+            target,
+            function: function.clone(),
+            call_data,
+        });
+        Ok(self.calls.len() - 1)
+    }
+
+    /// Send the batch and return one result per queued call, in the order they were added.
+    ///
+    /// The outer error is only returned if the batch itself couldn't be executed.
+    pub async fn call(self) -> Result<Vec<CallResult>> {
+        let results = self.execute().await?;
+
+        Ok(self
+            .calls
+            .iter()
+            .zip(results)
+            .map(|(call, result)| {
+                call.function
+                    .decode_output(&result?)
+                    .with_context(|| format!("Failed to decode {} result", call.function.name))
+            })
+            .collect())
+    }
+
+    /// Like [`Multicall::call`], but returns the undecoded return data of every call,
+    /// for results that don't follow the declared ABI (e.g. `bytes32` token symbols)
+    pub async fn call_raw(self) -> Result<Vec<RawCallResult>> {
+        self.execute().await
+    }
+
+    async fn execute(&self) -> Result<Vec<RawCallResult>> {
+        if self.calls.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let aggregate3 = aggregate3_function();
+        let calls = self
+            .calls
+            .iter()
+            .map(|call| {
+                Token::Tuple(vec![
+                    Token::Address(call.target),
+                    Token::Bool(true),
+                    Token::Bytes(call.call_data.clone()),
+                ])
+            })
+            .collect();
+
+        let input_data = aggregate3
+            .encode_input(&[Token::Array(calls)])
+            .context("Failed to encode aggregate3 call")?;
+
+        debug!("Sending Multicall3 batch with {} calls", self.calls.len());
+
+        let tx_request = TransactionRequest::new().to(self.address).data(input_data);
+        let result = self
+            .provider
+            .call(&tx_request.into(), self.block)
+            .await
+            .context("Failed to call Multicall3 aggregate3")?;
+
+        // No code at the Multicall3 address, e.g. a block before its deployment or a dev node
+        if result.is_empty() {
+            warn!(
+                "Multicall3 is not deployed at {:?}, sending {} calls individually",
+                self.address,
+                self.calls.len()
+            );
+            return Ok(self.call_individually().await);
+        }
+
+        self.decode_results(&result)
+    }
+
+    /// Split the return data of `aggregate3` into the results of the queued calls
+    fn decode_results(&self, result: &[u8]) -> Result<Vec<RawCallResult>> {
+        let decoded = aggregate3_function()
+            .decode_output(result)
+            .context("Failed to decode aggregate3 result")?;
+
+        let results = match decoded.into_iter().next() {
+            Some(Token::Array(results)) => results,
+            _ => anyhow::bail!("Unexpected aggregate3 result format"),
+        };
+
+        if results.len() != self.calls.len() {
+            anyhow::bail!(
+                "Multicall3 returned {} results for {} calls",
+                results.len(),
+                self.calls.len()
+            );
+        }
+
+        Ok(self
+            .calls
+            .iter()
+            .zip(results)
+            .map(|(call, result)| match result {
+                Token::Tuple(fields) => match fields.as_slice() {
+                    [Token::Bool(success), Token::Bytes(data)] => {
+                        check_call_result(call, *success, data)
+                    }
+                    _ => Err(anyhow::anyhow!("Unexpected aggregate3 call result format")),
+                },
+                _ => Err(anyhow::anyhow!("Unexpected aggregate3 call result format")),
+            })
+            .collect())
+    }
+
+    /// Send every queued call as its own `eth_call`, concurrently
+    async fn call_individually(&self) -> Vec<RawCallResult> {
+        futures::future::join_all(self.calls.iter().map(|call| async move {
+            let tx_request = TransactionRequest::new()
+                .to(call.target)
+                .data(call.call_data.clone());
+            let result = self
+                .provider
+                .call(&tx_request.into(), self.block)
+                .await
+                .with_context(|| {
+                    format!("{} call to {:?} failed", call.function.name, call.target)
+                })?;
+            check_call_result(call, true, &result)
+        }))
+        .await
+    }
+}
+
+fn check_call_result(call: &Call, success: bool, return_data: &[u8]) -> RawCallResult {
+    if !success {
+        anyhow::bail!(
+            "{} call to {:?} reverted{}",
+            call.function.name,
+            call.target,
+            revert_reason(return_data)
+                .map(|reason| format!(": {reason}"))
+                .unwrap_or_default()
+        );
+    }
+
+    // Calls to addresses without code succeed with empty return data
+    if return_data.is_empty() {
+        anyhow::bail!(
+            "{} call to {:?} returned no data (not a contract?)",
+            call.function.name,
+            call.target
+        );
+    }
+
+    Ok(return_data.to_vec())
+}
+
+/// Decode the message of an `Error(string)` revert, if that's what `data` contains
+fn revert_reason(data: &[u8]) -> Option<String> {
+    let payload = data.strip_prefix(&ERROR_STRING_SELECTOR)?;
+    match ethabi::decode(&[ParamType::String], payload).ok()?.pop()? {
+        Token::String(reason) => Some(reason),
+        _ => None,
+    }
+}
+
+fn aggregate3_function() -> Function {
+    let call3 = ParamType::Tuple(vec![ParamType::Address, ParamType::Bool, ParamType::Bytes]);
+    let result = ParamType::Tuple(vec![ParamType::Bool, ParamType::Bytes]);
+
+    Function {
+        name: "aggregate3".to_string(),
+        inputs: vec![Param {
+            name: "calls".to_string(),
+            kind: ParamType::Array(Box::new(call3)),
+            internal_type: None,
+        }],
+        outputs: vec![Param {
+            name: "returnData".to_string(),
+            kind: ParamType::Array(Box::new(result)),
+            internal_type: None,
+        }],
+        #[allow(deprecated)]
+        constant: None,
+        state_mutability: StateMutability::Payable,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chains;
+    use crate::erc20;
+    use crate::rpc::{RpcClient, RpcEndpoints, RpcSettings};
+    use std::time::Duration;
+
+    const TOKEN: &str = "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48";
+    const OWNER: &str = "0x70997970C51812dc3A010C7d01b50e0d17dc79C8";
+
+    /// A provider for batches that are only decoded, never sent
+    fn provider() -> RpcProvider {
+        let client = RpcClient::new(&RpcEndpoints {
+            urls: vec!["http://127.0.0.1:1".to_string()],
+            settings: RpcSettings {
+                max_retries: 0,
+                initial_backoff: Duration::ZERO,
+                unhealthy_cooldown: Duration::ZERO,
+                quorum: 1,
+            },
+        })
+        .unwrap();
+        RpcProvider::new(client)
+    }
+
+    fn aggregate3_result(results: &[(bool, Vec<u8>)]) -> Vec<u8> {
+        let results = results
+            .iter()
+            .map(|(success, data)| {
+                Token::Tuple(vec![Token::Bool(*success), Token::Bytes(data.clone())])
+            })
+            .collect();
+        ethabi::encode(&[Token::Array(results)])
+    }
+
+    fn error_string(reason: &str) -> Vec<u8> {
+        let mut data = ERROR_STRING_SELECTOR.to_vec();
+        data.extend(ethabi::encode(&[Token::String(reason.to_string())]));
+        data
+    }
+
+    #[test]
+    fn failed_calls_do_not_fail_the_batch() {
+        let provider = provider();
+        let token = Address::from_str(TOKEN).unwrap();
+        let mut multicall =
+            Multicall::new(&provider, chains::find_chain("mainnet").unwrap()).unwrap();
+        let owner = Token::Address(Address::from_str(OWNER).unwrap());
+        multicall
+            .add_call(token, &erc20::balance_of_function(), &[owner])
+            .unwrap();
+        multicall
+            .add_call(token, &erc20::decimals_function(), &[])
+            .unwrap();
+        multicall
+            .add_call(token, &erc20::symbol_function(), &[])
+            .unwrap();
+
+        let results = multicall
+            .decode_results(&aggregate3_result(&[
+                (true, ethabi::encode(&[Token::Uint(U256::from(1234))])),
+                (false, error_string("not supported")),
+                (true, Vec::new()),
+            ]))
+            .unwrap();
+
+        assert_eq!(
+            results[0].as_ref().unwrap(),
+            &ethabi::encode(&[Token::Uint(U256::from(1234))])
+        );
+        let reverted = format!("{:#}", results[1].as_ref().unwrap_err());
+        assert!(reverted.ends_with("reverted: not supported"), "{reverted}");
+        let empty = format!("{:#}", results[2].as_ref().unwrap_err());
+        assert!(empty.contains("returned no data"), "{empty}");
+    }
+
+    #[test]
+    fn result_count_must_match_the_calls() {
+        let provider = provider();
+        let mut multicall =
+            Multicall::new(&provider, chains::find_chain("mainnet").unwrap()).unwrap();
+        multicall
+            .add_call(
+                Address::from_str(TOKEN).unwrap(),
+                &erc20::decimals_function(),
+                &[],
+            )
+            .unwrap();
+
+        assert!(multicall.decode_results(&aggregate3_result(&[])).is_err());
+    }
+
+    #[test]
+    fn decodes_error_string_reverts() {
+        assert_eq!(revert_reason(&error_string("STF")).as_deref(), Some("STF"));
+        // Custom errors and bare reverts have no message
+        assert_eq!(revert_reason(&[0x12, 0x34, 0x56, 0x78]), None);
+        assert_eq!(revert_reason(&[]), None);
+    }
+}
@@ -0,0 +1,1000 @@
+// Copyright 2025 chenjjiaa
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::{Context, Result};
+use ethers::{
+    types::{Address, Bytes, U256},
+    utils::parse_units,
+};
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
+
+use crate::allowance;
+use crate::chains;
+use crate::config::{PolicyConfig, SpendLimit};
+use crate::erc20;
+use crate::swap::{SwapTransactionOutput, UnsignedTransaction};
+
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// A policy rule, named after its key in the `[policy]` configuration section
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rule {
+    SpendPerTransaction,
+    SpendPerDay,
+    UnlistedToken,
+    AllowedRecipients,
+    AllowedContracts,
+    MaxSlippageTolerance,
+    MaxPriceImpact,
+    MaxGasPrice,
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::SpendPerTransaction => "spend_limits.per_transaction",
+            Self::SpendPerDay => "spend_limits.per_day",
+            Self::UnlistedToken => "deny_unlisted_tokens",
+            Self::AllowedRecipients => "allowed_recipients",
+            Self::AllowedContracts => "allowed_contracts",
+            Self::MaxSlippageTolerance => "max_slippage_tolerance",
+            Self::MaxPriceImpact => "max_price_impact",
+            Self::MaxGasPrice => "max_gas_price_gwei",
+        };
+        write!(f, "policy.{name}")
+    }
+}
+
+/// A transaction rejected by the policy, with the rule it broke
+#[derive(Debug, Clone)]
+pub struct PolicyViolation {
+    pub rule: Rule,
+    pub reason: String,
+}
+
+impl fmt::Display for PolicyViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (rule {})", self.reason, self.rule)
+    }
+}
+
+impl std::error::Error for PolicyViolation {}
+
+/// An amount of a token leaving the wallet
+#[derive(Debug, Clone, Copy)]
+pub struct Spend {
+    /// Token contract, `None` for the native token
+    pub token: Option<Address>,
+    /// Amount in human-readable units
+    pub amount: Decimal,
+}
+
+/// Terms of a swap that the policy limits
+#[derive(Debug, Clone, Copy)]
+pub struct SwapTerms {
+    /// Slippage tolerance percentage
+    pub slippage_tolerance: Decimal,
+    /// Price impact percentage, `None` if it couldn't be calculated
+    pub price_impact: Option<Decimal>,
+}
+
+/// What a transaction does, as far as the policy is concerned
+#[derive(Debug, Clone)]
+pub struct PolicyCheck {
+    /// Tool the transaction comes from, for the log
+    pub action: &'static str,
+    pub chain_id: u64,
+    pub from: Address,
+    /// Contracts called and spenders approved, none for plain transfers
+    pub contracts: Vec<Address>,
+    /// Contract called with calldata the policy can't decode, so whatever it spends is unknown
+    pub opaque_call: Option<Address>,
+    /// Addresses receiving funds
+    pub recipients: Vec<Address>,
+    pub spends: Vec<Spend>,
+    pub max_fee_per_gas: U256,
+    pub swap: Option<SwapTerms>,
+}
+
+impl PolicyCheck {
+    /// The policy check of a transaction from `send_transaction`. `token_call` is its decoded
+    /// calldata with the decimals of [`TokenCall::token`]: transfers spend the amount sent and
+    /// approvals the amount approved, while unlimited approvals count as the largest possible
+    /// spend. Other calldata is an opaque call to `transaction.to`
+    pub fn for_transaction(
+        chain_id: u64,
+        transaction: &UnsignedTransaction,
+        token_call: Option<(TokenCall, u8)>,
+    ) -> Result<Self> {
+        let mut check = Self {
+            action: "send_transaction",
+            chain_id,
+            from: transaction.from,
+            contracts: Vec::new(),
+            opaque_call: None,
+            recipients: Vec::new(),
+            spends: Vec::new(),
+            max_fee_per_gas: transaction.max_fee_per_gas,
+            swap: None,
+        };
+        if !transaction.value.is_zero() {
+            check.spends.push(Spend {
+                token: None,
+                amount: erc20::to_decimal_units(transaction.value, 18)?.normalize(),
+            });
+        }
+
+        if transaction.data.is_empty() {
+            check.recipients.push(transaction.to);
+            return Ok(check);
+        }
+        let Some((call, decimals)) = token_call else {
+            check.contracts.push(transaction.to);
+            check.opaque_call = Some(transaction.to);
+            return Ok(check);
+        };
+        let spend = |amount| Spend {
+            token: Some(call.token(transaction.to)),
+            amount: erc20::to_decimal_units(amount, decimals)
+                .map_or(Decimal::MAX, |amount| amount.normalize()),
+        };
+        match call {
+            TokenCall::Transfer { to, amount } => {
+                check.spends.push(spend(amount));
+                check.recipients.push(to);
+            }
+            TokenCall::Approve { spender, amount } => {
+                check.spends.push(spend(amount));
+                check.contracts.push(spender);
+            }
+            TokenCall::Permit2Approve {
+                spender, amount, ..
+            } => {
+                check.spends.push(spend(amount));
+                check.contracts.extend([transaction.to, spender]);
+            }
+        }
+        Ok(check)
+    }
+
+    /// The policy check of a built swap transaction. The spend is the input amount, or the
+    /// maximum input of exact-output swaps
+    pub fn for_swap(built: &SwapTransactionOutput) -> Result<Self> {
+        let swap = &built.swap;
+        let amount = swap.maximum_input.as_deref().unwrap_or(&swap.input_amount);
+        let recipient = Address::from_str(&built.recipient).context("Invalid swap recipient")?;
+
+        Ok(Self {
+            action: "execute_swap",
+            chain_id: built.chain_id,
+            from: built.transaction.from,
+            contracts: vec![built.transaction.to],
+            opaque_call: None,
+            recipients: vec![recipient],
+            spends: vec![Spend {
+                // Anything but an address is the native token
+                token: Address::from_str(&swap.from_token).ok(),
+                amount: Decimal::from_str(amount)
+                    .with_context(|| format!("Invalid swap input amount: {amount}"))?,
+            }],
+            max_fee_per_gas: built.transaction.max_fee_per_gas,
+            swap: Some(SwapTerms {
+                slippage_tolerance: Decimal::from_str(&swap.slippage_tolerance)
+                    .context("Invalid slippage tolerance")?,
+                price_impact: swap
+                    .price_impact
+                    .as_deref()
+                    .map(Decimal::from_str)
+                    .transpose()
+                    .context("Invalid price impact")?,
+            }),
+        })
+    }
+
+    /// The policy checks of the approvals sent before a built swap, one per approval. The
+    /// spender of a token approval must be an allowed contract, and so must both Permit2 and
+    /// the spender of a Permit2 approval. Approvals spend nothing, as the swap's check counts
+    /// its input
+    pub fn for_swap_approvals(built: &SwapTransactionOutput) -> Result<Vec<Self>> {
+        let Some(allowance) = &built.swap.allowance else {
+            return Ok(Vec::new());
+        };
+
+        allowance
+            .approvals
+            .iter()
+            .map(|approval| {
+                let to = Address::from_str(&approval.to).context("Invalid approval target")?;
+                let data = Bytes::from_str(&approval.data).context("Invalid approval calldata")?;
+                let contracts = match decode_token_call(&data) {
+                    Some(TokenCall::Approve { spender, .. }) => vec![spender],
+                    Some(TokenCall::Permit2Approve { spender, .. }) => vec![to, spender],
+                    _ => vec![to],
+                };
+                Ok(Self {
+                    action: "execute_swap approval",
+                    chain_id: built.chain_id,
+                    from: built.transaction.from,
+                    contracts,
+                    opaque_call: None,
+                    recipients: Vec::new(),
+                    spends: Vec::new(),
+                    max_fee_per_gas: built.transaction.max_fee_per_gas,
+                    swap: None,
+                })
+            })
+            .collect()
+    }
+}
+
+/// ERC20 and Permit2 calls the policy looks into
+#[derive(Debug, Clone, Copy)]
+pub enum TokenCall {
+    /// `transfer(to, amount)`, with the raw amount
+    Transfer { to: Address, amount: U256 },
+    /// `approve(spender, amount)`, with the raw amount
+    Approve { spender: Address, amount: U256 },
+    /// Permit2 `approve(token, spender, amount, expiration)`, with the raw amount
+    Permit2Approve {
+        token: Address,
+        spender: Address,
+        amount: U256,
+    },
+}
+
+impl TokenCall {
+    /// Token the call moves or approves, given the contract it is sent to
+    pub fn token(&self, to: Address) -> Address {
+        match self {
+            Self::Transfer { .. } | Self::Approve { .. } => to,
+            Self::Permit2Approve { token, .. } => *token,
+        }
+    }
+}
+
+/// Decode `data` if it is an ERC20 `transfer` or `approve` call, or a Permit2 `approve`
+pub fn decode_token_call(data: &[u8]) -> Option<TokenCall> {
+    let (selector, args) = (data.get(..4)?, data.get(4..)?);
+    let transfer = erc20::transfer_function();
+    let approve = erc20::approve_function();
+    let permit2_approve = allowance::permit2_approve_function();
+
+    let decode_args =
+        |function: &ethabi::Function| match function.decode_input(args).ok()?.as_slice() {
+            [ethabi::Token::Address(address), ethabi::Token::Uint(amount)] => {
+                Some((*address, *amount))
+            }
+            _ => None,
+        };
+    if selector == transfer.short_signature() {
+        let (to, amount) = decode_args(&transfer)?;
+        Some(TokenCall::Transfer { to, amount })
+    } else if selector == approve.short_signature() {
+        let (spender, amount) = decode_args(&approve)?;
+        Some(TokenCall::Approve { spender, amount })
+    } else if selector == permit2_approve.short_signature() {
+        match permit2_approve.decode_input(args).ok()?.as_slice() {
+            [ethabi::Token::Address(token), ethabi::Token::Address(spender), ethabi::Token::Uint(amount), _] => {
+                Some(TokenCall::Permit2Approve {
+                    token: *token,
+                    spender: *spender,
+                    amount: *amount,
+                })
+            }
+            _ => None,
+        }
+    } else {
+        None
+    }
+}
+
+/// Amount of a token spent on one chain during a UTC day
+#[derive(Debug, Clone, Copy)]
+struct DailySpend {
+    day: u64,
+    amount: Decimal,
+}
+
+/// Spends counted against the daily caps, to give back with [`Policy::refund`] if the
+/// transaction isn't sent
+#[derive(Debug)]
+#[must_use]
+pub struct Authorization {
+    chain_id: u64,
+    day: u64,
+    spends: Vec<Spend>,
+}
+
+/// Spending policy checked before the wallet signs a transaction
+pub struct Policy {
+    /// Spend caps keyed by chain ID and token, `None` for the native token
+    spend_limits: HashMap<(u64, Option<Address>), SpendLimit>,
+    deny_unlisted_tokens: bool,
+    allowed_recipients: Vec<Address>,
+    allowed_contracts: Vec<Address>,
+    max_slippage_tolerance: Option<Decimal>,
+    max_price_impact: Option<Decimal>,
+    max_fee_per_gas: Option<U256>,
+    /// The wallet's accounts, which are always allowed recipients
+    own_accounts: Vec<Address>,
+    /// Spends of tokens with a daily cap, keyed like `spend_limits`. Kept in memory, so
+    /// they start over when the server restarts
+    spent: Mutex<HashMap<(u64, Option<Address>), DailySpend>>,
+}
+
+impl Policy {
+    /// Build the policy from a validated configuration
+    pub fn from_config(config: &PolicyConfig, own_accounts: Vec<Address>) -> Result<Self> {
+        let mut spend_limits = HashMap::new();
+        for (chain, limits) in &config.spend_limits {
+            let chain = chains::find_chain(chain).context("Invalid policy.spend_limits entry")?;
+            for (token, limit) in limits {
+                // Anything but an address is the chain's native token
+                spend_limits.insert((chain.id, Address::from_str(token).ok()), limit.clone());
+            }
+        }
+        let parse_addresses = |addresses: &[String]| {
+            addresses
+                .iter()
+                .map(|address| Address::from_str(address).context("Invalid address"))
+                .collect::<Result<Vec<_>>>()
+        };
+        let max_fee_per_gas = config
+            .max_gas_price_gwei
+            .map(|gwei| parse_units(gwei.to_string(), "gwei").map(U256::from))
+            .transpose()
+            .context("Invalid policy.max_gas_price_gwei")?;
+
+        Ok(Self {
+            spend_limits,
+            deny_unlisted_tokens: config.deny_unlisted_tokens,
+            allowed_recipients: parse_addresses(&config.allowed_recipients)?,
+            allowed_contracts: parse_addresses(&config.allowed_contracts)?,
+            max_slippage_tolerance: config.max_slippage_tolerance,
+            max_price_impact: config.max_price_impact,
+            max_fee_per_gas,
+            own_accounts,
+            spent: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Check `check` against every rule and log the decision. Allowed spends are counted
+    /// against the daily caps right away, so concurrent transactions can't both fit under
+    /// a cap; pass the authorization to [`Policy::refund`] if the transaction isn't sent
+    pub fn authorize(&self, check: &PolicyCheck) -> Result<Authorization, PolicyViolation> {
+        let mut applied = Vec::new();
+        let result = self.evaluate(check, &mut applied);
+        match &result {
+            Ok(_) if applied.is_empty() => info!(
+                "Policy allowed {} from {:?} on chain {}: no rule applies",
+                check.action, check.from, check.chain_id
+            ),
+            Ok(_) => info!(
+                "Policy allowed {} from {:?} on chain {}, passing {}",
+                check.action,
+                check.from,
+                check.chain_id,
+                applied
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Err(violation) => warn!(
+                "Policy rejected {} from {:?} on chain {} by {}: {}",
+                check.action, check.from, check.chain_id, violation.rule, violation.reason
+            ),
+        }
+        result
+    }
+
+    /// Authorize every check in `checks`, or none of them: if one is rejected, the spends
+    /// of the checks before it are given back
+    pub fn authorize_all(
+        &self,
+        checks: &[PolicyCheck],
+    ) -> Result<Vec<Authorization>, PolicyViolation> {
+        let mut authorizations = Vec::with_capacity(checks.len());
+        for check in checks {
+            match self.authorize(check) {
+                Ok(authorization) => authorizations.push(authorization),
+                Err(violation) => {
+                    for authorization in authorizations {
+                        self.refund(authorization);
+                    }
+                    return Err(violation);
+                }
+            }
+        }
+        Ok(authorizations)
+    }
+
+    /// Give back the spends of a transaction that wasn't sent
+    pub fn refund(&self, authorization: Authorization) {
+        let mut spent = self.spent.lock().unwrap_or_else(|e| e.into_inner());
+        for spend in &authorization.spends {
+            if let Some(daily) = spent.get_mut(&(authorization.chain_id, spend.token)) {
+                if daily.day == authorization.day {
+                    daily.amount = (daily.amount - spend.amount).max(Decimal::ZERO);
+                    info!(
+                        "Refunded {} {} to today's spend on chain {}",
+                        spend.amount,
+                        token_name(authorization.chain_id, spend.token),
+                        authorization.chain_id
+                    );
+                }
+            }
+        }
+    }
+
+    fn evaluate(
+        &self,
+        check: &PolicyCheck,
+        applied: &mut Vec<Rule>,
+    ) -> Result<Authorization, PolicyViolation> {
+        if let Some(max_fee_per_gas) = self.max_fee_per_gas {
+            applied.push(Rule::MaxGasPrice);
+            if check.max_fee_per_gas > max_fee_per_gas {
+                return Err(violation(
+                    Rule::MaxGasPrice,
+                    format!(
+                        "Max fee per gas of {} is above the limit of {}",
+                        gwei(check.max_fee_per_gas),
+                        gwei(max_fee_per_gas)
+                    ),
+                ));
+            }
+        }
+
+        if !self.allowed_contracts.is_empty() && !check.contracts.is_empty() {
+            applied.push(Rule::AllowedContracts);
+            for contract in &check.contracts {
+                if !self.allowed_contracts.contains(contract) {
+                    return Err(violation(
+                        Rule::AllowedContracts,
+                        format!("{contract:?} is not an allowed contract"),
+                    ));
+                }
+            }
+        }
+
+        // Spend rules can't see what an undecoded call spends, so with spend rules set it must
+        // go to a contract listed above
+        if let Some(contract) = check.opaque_call {
+            let spend_rules = !self.spend_limits.is_empty() || self.deny_unlisted_tokens;
+            if spend_rules && self.allowed_contracts.is_empty() {
+                applied.push(Rule::AllowedContracts);
+                return Err(violation(
+                    Rule::AllowedContracts,
+                    format!(
+                        "The calldata sent to {contract:?} can't be checked against the spend limits, so the contract must be allowed"
+                    ),
+                ));
+            }
+        }
+
+        if !self.allowed_recipients.is_empty() && !check.recipients.is_empty() {
+            applied.push(Rule::AllowedRecipients);
+            for recipient in &check.recipients {
+                if !self.allowed_recipients.contains(recipient)
+                    && !self.own_accounts.contains(recipient)
+                {
+                    return Err(violation(
+                        Rule::AllowedRecipients,
+                        format!("{recipient:?} is not an allowed recipient"),
+                    ));
+                }
+            }
+        }
+
+        if let Some(swap) = check.swap {
+            if let Some(max_slippage) = self.max_slippage_tolerance {
+                applied.push(Rule::MaxSlippageTolerance);
+                if swap.slippage_tolerance > max_slippage {
+                    return Err(violation(
+                        Rule::MaxSlippageTolerance,
+                        format!(
+                            "Slippage tolerance of {}% is above the limit of {}%",
+                            swap.slippage_tolerance, max_slippage
+                        ),
+                    ));
+                }
+            }
+            if let Some(max_impact) = self.max_price_impact {
+                applied.push(Rule::MaxPriceImpact);
+                match swap.price_impact {
+                    Some(impact) if impact > max_impact => {
+                        return Err(violation(
+                            Rule::MaxPriceImpact,
+                            format!(
+                                "Price impact of {impact}% is above the limit of {max_impact}%"
+                            ),
+                        ));
+                    }
+                    Some(_) => {}
+                    None => {
+                        return Err(violation(
+                            Rule::MaxPriceImpact,
+                            "Price impact couldn't be calculated, so it can't be checked"
+                                .to_string(),
+                        ));
+                    }
+                }
+            }
+        }
+
+        let mut daily_spends = Vec::new();
+        for spend in &check.spends {
+            if spend.amount.is_zero() {
+                continue;
+            }
+            let Some(limit) = self.spend_limits.get(&(check.chain_id, spend.token)) else {
+                if self.deny_unlisted_tokens {
+                    applied.push(Rule::UnlistedToken);
+                    return Err(violation(
+                        Rule::UnlistedToken,
+                        format!(
+                            "{} has no spend limit",
+                            token_name(check.chain_id, spend.token)
+                        ),
+                    ));
+                }
+                continue;
+            };
+            if let Some(per_transaction) = limit.per_transaction {
+                applied.push(Rule::SpendPerTransaction);
+                if spend.amount > per_transaction {
+                    return Err(violation(
+                        Rule::SpendPerTransaction,
+                        format!(
+                            "Spending {} {} is above the limit of {} per transaction",
+                            spend.amount,
+                            token_name(check.chain_id, spend.token),
+                            per_transaction
+                        ),
+                    ));
+                }
+            }
+            if let Some(per_day) = limit.per_day {
+                applied.push(Rule::SpendPerDay);
+                daily_spends.push((*spend, per_day));
+            }
+        }
+
+        self.reserve(check.chain_id, &daily_spends)
+    }
+
+    /// Count `spends` against their daily caps, all or none of them
+    fn reserve(
+        &self,
+        chain_id: u64,
+        spends: &[(Spend, Decimal)],
+    ) -> Result<Authorization, PolicyViolation> {
+        let day = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|now| now.as_secs() / SECS_PER_DAY)
+            .unwrap_or_default();
+        let mut spent = self.spent.lock().unwrap_or_else(|e| e.into_inner());
+        let spent_today = |spent: &HashMap<_, DailySpend>, token| {
+            spent
+                .get(&(chain_id, token))
+                .filter(|daily| daily.day == day)
+                .map_or(Decimal::ZERO, |daily| daily.amount)
+        };
+
+        for (spend, per_day) in spends {
+            let total = spent_today(&spent, spend.token) + spend.amount;
+            if total > *per_day {
+                return Err(violation(
+                    Rule::SpendPerDay,
+                    format!(
+                        "Spending {} {} would bring today's total to {}, above the limit of {} per day",
+                        spend.amount,
+                        token_name(chain_id, spend.token),
+                        total,
+                        per_day
+                    ),
+                ));
+            }
+        }
+        for (spend, _) in spends {
+            let amount = spent_today(&spent, spend.token) + spend.amount;
+            spent.insert((chain_id, spend.token), DailySpend { day, amount });
+        }
+
+        Ok(Authorization {
+            chain_id,
+            day,
+            spends: spends.iter().map(|(spend, _)| *spend).collect(),
+        })
+    }
+}
+
+fn violation(rule: Rule, reason: String) -> PolicyViolation {
+    PolicyViolation { rule, reason }
+}
+
+fn gwei(wei: U256) -> String {
+    match erc20::to_decimal_units(wei, 9) {
+        Ok(gwei) => format!("{} gwei", gwei.normalize()),
+        Err(_) => format!("{wei} wei"),
+    }
+}
+
+fn token_name(chain_id: u64, token: Option<Address>) -> String {
+    match token {
+        Some(token) => format!("{token:?}"),
+        None => chains::find_chain_by_id(chain_id)
+            .map_or("the native token", |chain| chain.native_symbol)
+            .to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAINNET: u64 = 1;
+    const BASE: u64 = 8453;
+    const SENDER: &str = "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266";
+    const USDC: &str = "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48";
+    const ROUTER: &str = "0x7a250d5630B4cF539739dF2C5dAcb4c659F2488D";
+
+    fn address(address: &str) -> Address {
+        Address::from_str(address).unwrap()
+    }
+
+    fn policy(config: &str) -> Policy {
+        let config: PolicyConfig = toml::from_str(config).unwrap();
+        Policy::from_config(&config, vec![address(SENDER)]).unwrap()
+    }
+
+    fn spend(token: Option<&str>, amount: &str) -> Spend {
+        Spend {
+            token: token.map(address),
+            amount: Decimal::from_str(amount).unwrap(),
+        }
+    }
+
+    fn check(chain_id: u64, spends: Vec<Spend>) -> PolicyCheck {
+        PolicyCheck {
+            action: "test",
+            chain_id,
+            from: address(SENDER),
+            contracts: Vec::new(),
+            opaque_call: None,
+            recipients: Vec::new(),
+            spends,
+            max_fee_per_gas: U256::zero(),
+            swap: None,
+        }
+    }
+
+    fn transaction(to: &str, value: U256, data: Vec<u8>) -> UnsignedTransaction {
+        UnsignedTransaction {
+            tx_type: 2.into(),
+            chain_id: MAINNET.into(),
+            from: address(SENDER),
+            to: address(to),
+            nonce: U256::zero(),
+            gas: U256::from(100_000),
+            max_fee_per_gas: U256::zero(),
+            max_priority_fee_per_gas: U256::zero(),
+            value,
+            data: data.into(),
+        }
+    }
+
+    fn approve(spender: &str, amount: U256) -> Vec<u8> {
+        erc20::approve_function()
+            .encode_input(&[
+                ethabi::Token::Address(address(spender)),
+                ethabi::Token::Uint(amount),
+            ])
+            .unwrap()
+    }
+
+    fn permit2_approve(token: &str, spender: &str, amount: U256) -> Vec<u8> {
+        allowance::permit2_approve_function()
+            .encode_input(&[
+                ethabi::Token::Address(address(token)),
+                ethabi::Token::Address(address(spender)),
+                ethabi::Token::Uint(amount),
+                ethabi::Token::Uint(U256::from(1_700_000_000)),
+            ])
+            .unwrap()
+    }
+
+    /// The check of `transaction`, decoded as `send_transaction` does for a 6-decimal token
+    fn transaction_check(transaction: &UnsignedTransaction) -> PolicyCheck {
+        let token_call = decode_token_call(&transaction.data)
+            .filter(|_| transaction.value.is_zero())
+            .map(|call| (call, 6));
+        PolicyCheck::for_transaction(MAINNET, transaction, token_call).unwrap()
+    }
+
+    fn rejected_by(policy: &Policy, check: &PolicyCheck) -> Option<Rule> {
+        policy
+            .authorize(check)
+            .err()
+            .map(|violation| violation.rule)
+    }
+
+    #[test]
+    fn per_transaction_limit() {
+        let policy = policy("spend_limits.mainnet.ETH = { per_transaction = 1 }");
+
+        assert!(policy
+            .authorize(&check(MAINNET, vec![spend(None, "1")]))
+            .is_ok());
+        assert_eq!(
+            rejected_by(&policy, &check(MAINNET, vec![spend(None, "1.5")])),
+            Some(Rule::SpendPerTransaction)
+        );
+    }
+
+    #[test]
+    fn daily_limit_counts_earlier_spends() {
+        let policy = policy("spend_limits.mainnet.ETH = { per_day = 2 }");
+
+        assert!(policy
+            .authorize(&check(MAINNET, vec![spend(None, "1.5")]))
+            .is_ok());
+        assert_eq!(
+            rejected_by(&policy, &check(MAINNET, vec![spend(None, "1")])),
+            Some(Rule::SpendPerDay)
+        );
+        assert!(policy
+            .authorize(&check(MAINNET, vec![spend(None, "0.5")]))
+            .is_ok());
+    }
+
+    #[test]
+    fn refund_gives_back_the_daily_spend() {
+        let policy = policy("spend_limits.mainnet.ETH = { per_day = 2 }");
+
+        let authorization = policy
+            .authorize(&check(MAINNET, vec![spend(None, "1.5")]))
+            .unwrap();
+        policy.refund(authorization);
+        assert!(policy
+            .authorize(&check(MAINNET, vec![spend(None, "2")]))
+            .is_ok());
+    }
+
+    #[test]
+    fn reserve_counts_all_spends_or_none() {
+        let policy = policy(&format!(
+            r#"
+            [spend_limits.mainnet]
+            ETH = {{ per_day = 1 }}
+            "{USDC}" = {{ per_day = 100 }}
+            "#
+        ));
+
+        let both = check(MAINNET, vec![spend(None, "1"), spend(Some(USDC), "150")]);
+        assert_eq!(rejected_by(&policy, &both), Some(Rule::SpendPerDay));
+        // The ETH spend of the rejected transaction wasn't counted
+        assert!(policy
+            .authorize(&check(MAINNET, vec![spend(None, "1")]))
+            .is_ok());
+    }
+
+    #[test]
+    fn limits_apply_to_one_chain() {
+        let policy = policy(
+            r#"
+            spend_limits.mainnet.ETH = { per_day = 1 }
+            spend_limits.base.ETH = { per_day = 1 }
+            "#,
+        );
+
+        assert!(policy
+            .authorize(&check(MAINNET, vec![spend(None, "1")]))
+            .is_ok());
+        assert_eq!(
+            rejected_by(&policy, &check(MAINNET, vec![spend(None, "0.1")])),
+            Some(Rule::SpendPerDay)
+        );
+        // Base has its own cap and tally
+        assert!(policy
+            .authorize(&check(BASE, vec![spend(None, "1")]))
+            .is_ok());
+        assert_eq!(
+            rejected_by(&policy, &check(BASE, vec![spend(None, "0.1")])),
+            Some(Rule::SpendPerDay)
+        );
+    }
+
+    #[test]
+    fn unlisted_tokens_are_per_chain() {
+        let policy = policy(
+            r#"
+            deny_unlisted_tokens = true
+            spend_limits.mainnet.ETH = { per_transaction = 1 }
+            "#,
+        );
+
+        assert!(policy
+            .authorize(&check(MAINNET, vec![spend(None, "1")]))
+            .is_ok());
+        assert_eq!(
+            rejected_by(&policy, &check(BASE, vec![spend(None, "1")])),
+            Some(Rule::UnlistedToken)
+        );
+    }
+
+    #[test]
+    fn authorize_all_refunds_when_one_check_is_rejected() {
+        let policy = policy(&format!(
+            r#"
+            allowed_contracts = ["{ROUTER}"]
+            spend_limits.mainnet.ETH = {{ per_day = 1 }}
+            "#
+        ));
+
+        let mut approval = check(MAINNET, Vec::new());
+        approval.contracts = vec![address(ROUTER), address(allowance::PERMIT2_ADDRESS)];
+        let swap = check(MAINNET, vec![spend(None, "1")]);
+        let error = policy.authorize_all(&[swap.clone(), approval]).unwrap_err();
+        assert_eq!(error.rule, Rule::AllowedContracts);
+
+        // The swap's spend was given back
+        assert!(policy.authorize_all(&[swap]).is_ok());
+    }
+
+    #[test]
+    fn decodes_permit2_approvals() {
+        let data = permit2_approve(USDC, ROUTER, U256::from(1000));
+
+        match decode_token_call(&data) {
+            Some(
+                call @ TokenCall::Permit2Approve {
+                    spender, amount, ..
+                },
+            ) => {
+                assert_eq!(spender, address(ROUTER));
+                assert_eq!(amount, U256::from(1000));
+                assert_eq!(
+                    call.token(address(allowance::PERMIT2_ADDRESS)),
+                    address(USDC)
+                );
+            }
+            call => panic!("Unexpected decoded call: {call:?}"),
+        }
+    }
+
+    #[test]
+    fn approvals_count_as_spends() {
+        let policy = policy(&format!(
+            r#"spend_limits.mainnet."{USDC}" = {{ per_transaction = 1000 }}"#
+        ));
+
+        let within = transaction(USDC, U256::zero(), approve(ROUTER, U256::from(500_000_000)));
+        assert!(policy.authorize(&transaction_check(&within)).is_ok());
+        let above = transaction(
+            USDC,
+            U256::zero(),
+            approve(ROUTER, U256::from(2_000_000_000u64)),
+        );
+        assert_eq!(
+            rejected_by(&policy, &transaction_check(&above)),
+            Some(Rule::SpendPerTransaction)
+        );
+    }
+
+    #[test]
+    fn unlimited_approvals_are_above_any_limit() {
+        let policy = policy(&format!(
+            r#"spend_limits.mainnet."{USDC}" = {{ per_day = 1000000 }}"#
+        ));
+
+        let unlimited = transaction(USDC, U256::zero(), approve(ROUTER, U256::MAX));
+        assert_eq!(
+            rejected_by(&policy, &transaction_check(&unlimited)),
+            Some(Rule::SpendPerDay)
+        );
+    }
+
+    #[test]
+    fn approvals_of_unlisted_tokens_are_denied() {
+        let policy = policy(
+            r#"
+            deny_unlisted_tokens = true
+            spend_limits.mainnet.ETH = { per_transaction = 1 }
+            "#,
+        );
+
+        let approval = transaction(USDC, U256::zero(), approve(ROUTER, U256::from(1)));
+        assert_eq!(
+            rejected_by(&policy, &transaction_check(&approval)),
+            Some(Rule::UnlistedToken)
+        );
+    }
+
+    #[test]
+    fn permit2_approvals_spend_the_approved_token() {
+        let policy = policy(&format!(
+            r#"spend_limits.mainnet."{USDC}" = {{ per_transaction = 1000 }}"#
+        ));
+
+        let approval = transaction(
+            allowance::PERMIT2_ADDRESS,
+            U256::zero(),
+            permit2_approve(USDC, ROUTER, U256::from(2_000_000_000u64)),
+        );
+        let check = transaction_check(&approval);
+        assert_eq!(check.spends[0].token, Some(address(USDC)));
+        assert_eq!(
+            check.contracts,
+            vec![address(allowance::PERMIT2_ADDRESS), address(ROUTER)]
+        );
+        assert_eq!(
+            rejected_by(&policy, &check),
+            Some(Rule::SpendPerTransaction)
+        );
+    }
+
+    #[test]
+    fn opaque_calls_need_an_allowed_contract_under_spend_rules() {
+        // transferFrom(from, to, amount), which the policy doesn't decode
+        let mut data = ethers::utils::id("transferFrom(address,address,uint256)").to_vec();
+        data.extend([0; 96]);
+        let call = transaction(USDC, U256::zero(), data);
+        let check = transaction_check(&call);
+        assert_eq!(check.opaque_call, Some(address(USDC)));
+
+        assert!(policy("").authorize(&check).is_ok());
+        let limited = policy("spend_limits.mainnet.ETH = { per_day = 1 }");
+        assert_eq!(rejected_by(&limited, &check), Some(Rule::AllowedContracts));
+        let denying = policy("deny_unlisted_tokens = true");
+        assert_eq!(rejected_by(&denying, &check), Some(Rule::AllowedContracts));
+
+        let allowed = policy(&format!(
+            r#"
+            allowed_contracts = ["{USDC}"]
+            spend_limits.mainnet.ETH = {{ per_day = 1 }}
+            "#
+        ));
+        assert!(allowed.authorize(&check).is_ok());
+        let others_allowed = policy(&format!(
+            r#"
+            allowed_contracts = ["{ROUTER}"]
+            spend_limits.mainnet.ETH = {{ per_day = 1 }}
+            "#
+        ));
+        assert_eq!(
+            rejected_by(&others_allowed, &check),
+            Some(Rule::AllowedContracts)
+        );
+    }
+
+    #[test]
+    fn calldata_with_eth_is_an_opaque_call() {
+        let value = ethers::utils::parse_ether("0.5").unwrap();
+        let call = transaction(ROUTER, value, approve(ROUTER, U256::MAX));
+        let check = transaction_check(&call);
+
+        assert_eq!(check.opaque_call, Some(address(ROUTER)));
+        assert_eq!(check.spends.len(), 1);
+        assert_eq!(check.spends[0].token, None);
+        assert_eq!(check.spends[0].amount, Decimal::from_str("0.5").unwrap());
+    }
+}
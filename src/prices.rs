@@ -0,0 +1,382 @@
+// Copyright 2025 chenjjiaa
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+mod cache;
+pub mod chainlink;
+mod coingecko;
+mod uniswap;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use ethers::types::Address;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
+
+use crate::blocks::ResolvedBlock;
+use crate::chains::Network;
+use crate::config::{PriceConfig, PriceSourceKind};
+
+pub use cache::PriceCache;
+pub use coingecko::CoinGeckoPriceSource;
+pub use uniswap::UniswapPriceSource;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenPriceOutput {
+    /// Token identifier (address or symbol)
+    pub token: String,
+    /// Token address if available
+    pub token_address: Option<String>,
+    /// Price in USD
+    pub price_usd: Option<String>,
+    /// Price in ETH
+    pub price_eth: Option<String>,
+    /// Last updated timestamp
+    pub last_updated: Option<String>,
+    /// Price source that produced the price (e.g. "coingecko", "uniswap_v3")
+    pub source: String,
+}
+
+/// Format a Unix timestamp (seconds) as RFC 3339, as used in `last_updated`
+pub fn format_timestamp(secs: u64) -> Option<String> {
+    let secs = i64::try_from(secs).ok()?;
+    chrono::DateTime::from_timestamp(secs, 0).map(|time| time.to_rfc3339())
+}
+
+/// A historical USD price
+#[derive(Debug, Clone, Copy)]
+pub struct PricePoint {
+    /// Unix timestamp (seconds)
+    pub timestamp: u64,
+    pub price_usd: Decimal,
+}
+
+/// Open, high, low and close of the price points in one interval
+#[derive(Debug, Clone, Copy)]
+pub struct Candle {
+    /// Start of the interval (Unix seconds)
+    pub start: u64,
+    pub open: Decimal,
+    pub high: Decimal,
+    pub low: Decimal,
+    pub close: Decimal,
+    /// Number of price points in the interval
+    pub points: usize,
+}
+
+/// Group `points` (oldest first) into candles of `interval_secs`, aligned to multiples of
+/// the interval since the Unix epoch. Intervals without points are skipped
+pub fn ohlc(points: &[PricePoint], interval_secs: u64) -> Vec<Candle> {
+    let mut candles: Vec<Candle> = Vec::new();
+    for point in points {
+        let start = point.timestamp - point.timestamp % interval_secs;
+        match candles.last_mut() {
+            Some(candle) if candle.start == start => {
+                candle.high = candle.high.max(point.price_usd);
+                candle.low = candle.low.min(point.price_usd);
+                candle.close = point.price_usd;
+                candle.points += 1;
+            }
+            _ => candles.push(Candle {
+                start,
+                open: point.price_usd,
+                high: point.price_usd,
+                low: point.price_usd,
+                close: point.price_usd,
+                points: 1,
+            }),
+        }
+    }
+    candles
+}
+
+/// What to price, parsed from a `token` tool parameter
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PriceQuery {
+    /// Ether itself ("eth" / "ethereum")
+    Ether,
+    /// A token contract on the queried chain
+    Address(Address),
+    /// A lowercased symbol that isn't in the token registry, e.g. the native token's
+    /// symbol or a CoinGecko coin ID
+    Symbol(String),
+}
+
+impl PriceQuery {
+    pub fn parse(token: &str) -> Result<Self> {
+        let token = token.trim().to_lowercase();
+
+        if token.starts_with("0x") && token.len() == 42 {
+            let address = Address::from_str(&token).context("Invalid token address")?;
+            return Ok(Self::Address(address));
+        }
+
+        match token.as_str() {
+            "eth" | "ethereum" => Ok(Self::Ether),
+            "" => anyhow::bail!("Token must not be empty"),
+            _ => Ok(Self::Symbol(token)),
+        }
+    }
+}
+
+/// A provider of token prices in USD and ETH
+#[async_trait]
+pub trait PriceSource: Send + Sync {
+    /// Name used in configuration and logs
+    fn name(&self) -> &'static str;
+
+    /// Price `query` on `network`. Fails if this source can't price the token
+    async fn get_price(&self, query: &PriceQuery, network: &Network) -> Result<TokenPriceOutput>;
+
+    /// Price several tokens, returning one result per query in order. Sources whose API
+    /// takes lists should override this to batch upstream calls; by default every query
+    /// is priced concurrently with [`PriceSource::get_price`]
+    async fn get_prices(
+        &self,
+        queries: &[PriceQuery],
+        network: &Network,
+    ) -> Vec<Result<TokenPriceOutput>> {
+        futures::future::join_all(queries.iter().map(|query| self.get_price(query, network))).await
+    }
+
+    /// Price `query` as of `block`. Sources without historical data return an error
+    async fn get_historical_price(
+        &self,
+        _query: &PriceQuery,
+        _network: &Network,
+        _block: &ResolvedBlock,
+    ) -> Result<TokenPriceOutput> {
+        anyhow::bail!("{} has no historical prices", self.name())
+    }
+
+    /// USD prices of `query` between `from` and `to` (Unix seconds), oldest first.
+    /// Sources without historical data return an error
+    async fn get_price_history(
+        &self,
+        _query: &PriceQuery,
+        _network: &Network,
+        _from: u64,
+        _to: u64,
+    ) -> Result<Vec<PricePoint>> {
+        anyhow::bail!("{} has no price history", self.name())
+    }
+}
+
+/// The configured price sources, tried in order until one returns a price, behind a
+/// shared price cache
+pub struct PriceSources {
+    sources: Vec<Box<dyn PriceSource>>,
+    cache: PriceCache,
+}
+
+impl PriceSources {
+    pub fn from_config(config: &PriceConfig) -> Result<Self> {
+        // One HTTP client for every price API, so connections are pooled across requests
+        let http_client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .build()
+            .context("Failed to create HTTP client")?;
+
+        let sources = config
+            .sources
+            .iter()
+            .map(|kind| -> Result<Box<dyn PriceSource>> {
+                Ok(match kind {
+                    PriceSourceKind::Coingecko => {
+                        Box::new(CoinGeckoPriceSource::new(http_client.clone(), config))
+                    }
+                    PriceSourceKind::Uniswap => Box::new(UniswapPriceSource::new(config)),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        info!(
+            "Price sources: {}",
+            sources
+                .iter()
+                .map(|source| source.name())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        Ok(Self {
+            sources,
+            cache: PriceCache::new(Duration::from_secs(config.cache_ttl_secs)),
+        })
+    }
+
+    /// Names of the configured sources, in the order they are tried
+    pub fn source_names(&self) -> Vec<&'static str> {
+        self.sources.iter().map(|source| source.name()).collect()
+    }
+
+    /// Price `query` as of `block` with the first source that has historical data, or
+    /// only with the source named `source`. Historical prices are not cached
+    pub async fn get_historical_price(
+        &self,
+        query: &PriceQuery,
+        network: &Network,
+        block: &ResolvedBlock,
+        source: Option<&str>,
+    ) -> Result<TokenPriceOutput> {
+        let (price, _) = self
+            .first_success(source, |source| {
+                source.get_historical_price(query, network, block)
+            })
+            .await
+            .context("No price source could price the token at that block")?;
+        Ok(price)
+    }
+
+    /// USD prices of `query` between `from` and `to` (Unix seconds), oldest first, from the
+    /// first source that has price history. Returns the name of that source too
+    pub async fn get_price_history(
+        &self,
+        query: &PriceQuery,
+        network: &Network,
+        from: u64,
+        to: u64,
+    ) -> Result<(Vec<PricePoint>, &'static str)> {
+        self.first_success(None, |source| {
+            source.get_price_history(query, network, from, to)
+        })
+        .await
+        .context("No price source has price history for the token")
+    }
+
+    /// Run `f` on each source (or only the one named `only`) until it succeeds. The error
+    /// lists why each source failed
+    async fn first_success<'a, T, F, Fut>(
+        &'a self,
+        only: Option<&str>,
+        mut f: F,
+    ) -> Result<(T, &'static str)>
+    where
+        F: FnMut(&'a dyn PriceSource) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut errors = Vec::new();
+        for source in &self.sources {
+            if only.is_some_and(|only| only != source.name()) {
+                continue;
+            }
+            match f(source.as_ref()).await {
+                Ok(value) => return Ok((value, source.name())),
+                Err(e) => {
+                    warn!("Price source {} failed: {:#}", source.name(), e);
+                    errors.push(format!("{}: {e:#}", source.name()));
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            anyhow::bail!(
+                "Price source {} is not configured",
+                only.unwrap_or_default()
+            );
+        }
+        anyhow::bail!("{}", errors.join("; "))
+    }
+
+    /// Price `query`, from the cache if a fresh entry exists, otherwise with the first
+    /// source that succeeds. If all fail, the error lists why each source failed
+    pub async fn get_price(
+        &self,
+        query: &PriceQuery,
+        network: &Network,
+    ) -> Result<TokenPriceOutput> {
+        self.get_prices(std::slice::from_ref(query), network)
+            .await
+            .pop()
+            .context("No price returned")?
+    }
+
+    /// Price several tokens at once. Cache misses are passed to every source as one batch,
+    /// and each source only gets the tokens the sources before it couldn't price.
+    /// Returns one result per query, in order
+    pub async fn get_prices(
+        &self,
+        queries: &[PriceQuery],
+        network: &Network,
+    ) -> Vec<Result<TokenPriceOutput>> {
+        self.cache
+            .get_or_fetch(network.chain.id, queries, |missing| {
+                self.fetch_prices(missing, network)
+            })
+            .await
+    }
+
+    async fn fetch_prices(
+        &self,
+        queries: Vec<PriceQuery>,
+        network: &Network,
+    ) -> Vec<Result<TokenPriceOutput>> {
+        let mut prices: Vec<Option<TokenPriceOutput>> = vec![None; queries.len()];
+        let mut errors: Vec<Vec<String>> = vec![Vec::new(); queries.len()];
+        let mut pending: Vec<usize> = (0..queries.len()).collect();
+
+        for source in &self.sources {
+            if pending.is_empty() {
+                break;
+            }
+
+            let batch: Vec<PriceQuery> = pending.iter().map(|&i| queries[i].clone()).collect();
+            let results = source.get_prices(&batch, network).await;
+
+            let mut still_pending = Vec::new();
+            for (i, result) in pending.into_iter().zip(results) {
+                match result {
+                    Ok(price) => prices[i] = Some(price),
+                    Err(e) => {
+                        warn!(
+                            "Price source {} failed for {:?} on {}: {:#}",
+                            source.name(),
+                            queries[i],
+                            network.chain.name,
+                            e
+                        );
+                        errors[i].push(format!("{}: {e:#}", source.name()));
+                        still_pending.push(i);
+                    }
+                }
+            }
+            pending = still_pending;
+        }
+
+        // Sources without an upstream timestamp are as fresh as this fetch
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or_default();
+
+        prices
+            .into_iter()
+            .zip(errors)
+            .map(|(price, errors)| match price {
+                Some(mut price) => {
+                    if price.last_updated.is_none() {
+                        price.last_updated = format_timestamp(now);
+                    }
+                    Ok(price)
+                }
+                None => Err(anyhow::anyhow!(
+                    "No price source could price the token ({})",
+                    errors.join("; ")
+                )),
+            })
+            .collect()
+    }
+}
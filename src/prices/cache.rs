@@ -0,0 +1,158 @@
+// Copyright 2025 chenjjiaa
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::Result;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+use super::{PriceQuery, TokenPriceOutput};
+
+/// Number of cache slots above which expired entries are dropped
+const PRUNE_THRESHOLD: usize = 1024;
+
+/// Cache key: chain ID and query
+type CacheKey = (u64, PriceQuery);
+
+/// A cache slot. Its async lock is held while the price is fetched, so concurrent
+/// requests for the same key wait for that fetch instead of starting their own
+type Slot = Arc<tokio::sync::Mutex<Option<CachedPrice>>>;
+
+struct CachedPrice {
+    price: TokenPriceOutput,
+    fetched_at: Instant,
+}
+
+/// In-memory price cache with a fixed TTL and at most one upstream fetch in flight per key.
+///
+/// Failed fetches are not cached.
+pub struct PriceCache {
+    ttl: Duration,
+    slots: Mutex<HashMap<CacheKey, Slot>>,
+}
+
+impl PriceCache {
+    /// A TTL of zero disables caching, but concurrent requests are still coalesced
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            slots: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Return the cached prices of `queries` on `chain_id` that are younger than the TTL,
+    /// and call `fetch` once with all the others. Returns one result per query, in order.
+    ///
+    /// `fetch` must return one result per query it is given, in order.
+    pub async fn get_or_fetch<F, Fut>(
+        &self,
+        chain_id: u64,
+        queries: &[PriceQuery],
+        fetch: F,
+    ) -> Vec<Result<TokenPriceOutput>>
+    where
+        F: FnOnce(Vec<PriceQuery>) -> Fut,
+        Fut: Future<Output = Vec<Result<TokenPriceOutput>>>,
+    {
+        let mut unique = queries.to_vec();
+        unique.sort();
+        unique.dedup();
+
+        let slots: Vec<Slot> = {
+            let mut slots = self.slots.lock().unwrap_or_else(|e| e.into_inner());
+            if slots.len() >= PRUNE_THRESHOLD {
+                self.prune(&mut slots);
+            }
+            unique
+                .iter()
+                .map(|query| slots.entry((chain_id, query.clone())).or_default().clone())
+                .collect()
+        };
+
+        // Slots are locked in key order, so concurrent batches can't deadlock
+        let mut entries = Vec::with_capacity(slots.len());
+        for slot in &slots {
+            entries.push(slot.lock().await);
+        }
+
+        let missing: Vec<usize> = entries
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| {
+                entry
+                    .as_ref()
+                    .is_none_or(|cached| cached.fetched_at.elapsed() >= self.ttl)
+            })
+            .map(|(i, _)| i)
+            .collect();
+        debug!(
+            "Price cache: {} of {} queries on chain {} need fetching",
+            missing.len(),
+            unique.len(),
+            chain_id
+        );
+
+        let mut errors = HashMap::new();
+        if !missing.is_empty() {
+            let mut fetched = fetch(missing.iter().map(|&i| unique[i].clone()).collect())
+                .await
+                .into_iter();
+            for &i in &missing {
+                match fetched
+                    .next()
+                    .unwrap_or_else(|| Err(anyhow::anyhow!("No price returned")))
+                {
+                    Ok(price) => {
+                        *entries[i] = Some(CachedPrice {
+                            price,
+                            fetched_at: Instant::now(),
+                        })
+                    }
+                    Err(e) => {
+                        errors.insert(i, e);
+                    }
+                }
+            }
+        }
+
+        queries
+            .iter()
+            .map(|query| {
+                let i = unique.partition_point(|other| other < query);
+                match (errors.get(&i), entries[i].as_ref()) {
+                    (Some(e), _) => Err(anyhow::anyhow!("{e:#}")),
+                    (None, Some(cached)) => Ok(cached.price.clone()),
+                    (None, None) => Err(anyhow::anyhow!("No price returned")),
+                }
+            })
+            .collect()
+    }
+
+    /// Drop slots that nobody is waiting on and whose price is missing or expired
+    fn prune(&self, slots: &mut HashMap<CacheKey, Slot>) {
+        slots.retain(|_, slot| {
+            if Arc::strong_count(slot) > 1 {
+                return true;
+            }
+            match slot.try_lock() {
+                Ok(entry) => entry
+                    .as_ref()
+                    .is_some_and(|cached| cached.fetched_at.elapsed() < self.ttl),
+                Err(_) => true,
+            }
+        });
+    }
+}
@@ -0,0 +1,108 @@
+// Copyright 2025 chenjjiaa
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::{Context, Result};
+use ethabi::{Function, ParamType, Token};
+use ethers::types::{Address, I256, U256};
+use tracing::info;
+
+use crate::abi;
+use crate::chains::Chain;
+use crate::erc20;
+use crate::multicall::Multicall;
+use crate::rpc::RpcProvider;
+
+/// Latest round of a Chainlink AggregatorV3 feed
+#[derive(Debug, Clone)]
+pub struct FeedRound {
+    /// Human-readable feed description, e.g. "ETH / USD"
+    pub description: String,
+    pub decimals: u8,
+    pub round_id: U256,
+    /// Raw answer, scaled by `10^decimals`
+    pub answer: I256,
+    /// Unix timestamp of the last answer update
+    pub updated_at: u64,
+}
+
+/// `latestRoundData()`, which returns `(roundId, answer, startedAt, updatedAt, answeredInRound)`
+fn latest_round_data_function() -> Function {
+    abi::view_function(
+        "latestRoundData",
+        vec![],
+        vec![
+            ParamType::Uint(80),
+            ParamType::Int(256),
+            ParamType::Uint(256),
+            ParamType::Uint(256),
+            ParamType::Uint(80),
+        ],
+    )
+}
+
+fn description_function() -> Function {
+    abi::view_function("description", vec![], vec![ParamType::String])
+}
+
+/// Read `latestRoundData`, `decimals` and `description` of `feed` in a single Multicall3 batch
+pub async fn read_latest_round(
+    provider: &RpcProvider,
+    chain: &Chain,
+    feed: Address,
+) -> Result<FeedRound> {
+    let latest_round_data_fn = latest_round_data_function();
+
+    let mut multicall = Multicall::new(provider, chain)?;
+    multicall.add_call(feed, &latest_round_data_fn, &[])?;
+    multicall.add_call(feed, &erc20::decimals_function(), &[])?;
+    multicall.add_call(feed, &description_function(), &[])?;
+
+    let mut results = multicall.call().await?.into_iter();
+    let mut next = || results.next().context("Missing feed result");
+    let (round, decimals, description) = (next()?, next()?, next()?);
+
+    let (round_id, answer, updated_at) =
+        match round.context("Failed to call latestRoundData")?.as_slice() {
+            [Token::Uint(round_id), Token::Int(answer), _, Token::Uint(updated_at), _] => {
+                (*round_id, I256::from_raw(*answer), *updated_at)
+            }
+            _ => anyhow::bail!("Unexpected latestRoundData result format"),
+        };
+    let decimals = decimals
+        .and_then(erc20::decode_decimals)
+        .context("Failed to get feed decimals")?;
+    let description = match description.context("Failed to get feed description")?.pop() {
+        Some(Token::String(description)) => description,
+        _ => anyhow::bail!("Unexpected description result format"),
+    };
+
+    if updated_at.is_zero() {
+        anyhow::bail!("Feed {feed:?} has no completed round");
+    }
+
+    info!(
+        "Chainlink feed {} ({:?}): round {}, answer {}, updated at {}",
+        description, feed, round_id, answer, updated_at
+    );
+
+    Ok(FeedRound {
+        description,
+        decimals,
+        round_id,
+        answer,
+        updated_at: updated_at
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("Invalid updatedAt {updated_at}"))?,
+    })
+}
@@ -0,0 +1,403 @@
+// Copyright 2025 chenjjiaa
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use reqwest::Url;
+use rust_decimal::Decimal;
+use tracing::info;
+
+use super::{format_timestamp, PricePoint, PriceQuery, PriceSource, TokenPriceOutput};
+use crate::blocks::ResolvedBlock;
+use crate::chains::Network;
+use crate::config::PriceConfig;
+
+/// Prices from the CoinGecko simple price API, and historical prices from its
+/// `market_chart/range` API
+pub struct CoinGeckoPriceSource {
+    client: reqwest::Client,
+    api_url: String,
+    api_key: Option<String>,
+}
+
+impl CoinGeckoPriceSource {
+    pub fn new(client: reqwest::Client, config: &PriceConfig) -> Self {
+        Self {
+            client,
+            api_url: config.coingecko_api_url.clone(),
+            api_key: config.coingecko_api_key.clone(),
+        }
+    }
+
+    /// API URL of `path`, with its segments and `query` percent-encoded
+    fn url(&self, path: &[impl AsRef<str>], query: &[(&str, &str)]) -> Result<Url> {
+        let mut url = Url::parse(&self.api_url).context("Invalid CoinGecko API URL")?;
+        url.path_segments_mut()
+            .map_err(|()| anyhow::anyhow!("Invalid CoinGecko API URL: {}", self.api_url))?
+            .pop_if_empty()
+            .extend(path);
+        url.query_pairs_mut().extend_pairs(query);
+        Ok(url)
+    }
+
+    async fn get_json(&self, url: Url) -> Result<serde_json::Value> {
+        let mut request = self.client.get(url);
+        if let Some(api_key) = &self.api_key {
+            request = request.header("x-cg-demo-api-key", api_key);
+        }
+
+        let response = request
+            .send()
+            .await
+            .context("Failed to send request to CoinGecko")?;
+
+        if !response.status().is_success() {
+            anyhow::bail!("CoinGecko API returned error: {}", response.status());
+        }
+
+        response
+            .json()
+            .await
+            .context("Failed to parse CoinGecko response")
+    }
+
+    /// Fetch a CoinGecko price endpoint that returns an object keyed by ID or address
+    async fn get_price_map(&self, url: Url) -> Result<PriceMap> {
+        match self.get_json(url).await? {
+            serde_json::Value::Object(prices) => Ok(prices),
+            _ => anyhow::bail!("Unexpected CoinGecko response format"),
+        }
+    }
+
+    /// Prices of token contracts on `platform`, keyed by lowercase address, with one
+    /// request per [`MAX_IDS_PER_REQUEST`] addresses
+    async fn fetch_prices_by_address(
+        &self,
+        platform: &str,
+        addresses: &[String],
+    ) -> Result<PriceMap> {
+        let mut prices = PriceMap::new();
+        for chunk in addresses.chunks(MAX_IDS_PER_REQUEST) {
+            let url = self.url(
+                &["simple", "token_price", platform],
+                &[
+                    ("contract_addresses", &chunk.join(",")),
+                    ("vs_currencies", "usd,eth"),
+                    ("include_last_updated_at", "true"),
+                ],
+            )?;
+
+            info!(
+                "Fetching {} prices by address from CoinGecko: {}",
+                chunk.len(),
+                url
+            );
+            prices.extend(self.get_price_map(url).await?);
+        }
+        Ok(prices)
+    }
+
+    /// Prices of CoinGecko coin IDs, keyed by ID, with one request per
+    /// [`MAX_IDS_PER_REQUEST`] IDs
+    async fn fetch_prices_by_id(&self, ids: &[&str]) -> Result<PriceMap> {
+        let mut prices = PriceMap::new();
+        for chunk in ids.chunks(MAX_IDS_PER_REQUEST) {
+            let url = self.url(
+                &["simple", "price"],
+                &[
+                    ("ids", &chunk.join(",")),
+                    ("vs_currencies", "usd,eth"),
+                    ("include_last_updated_at", "true"),
+                ],
+            )?;
+
+            info!(
+                "Fetching {} prices by ID from CoinGecko: {}",
+                chunk.len(),
+                url
+            );
+            prices.extend(self.get_price_map(url).await?);
+        }
+        Ok(prices)
+    }
+
+    /// CoinGecko API path segments of the coin `query` refers to
+    fn coin_path(query: &PriceQuery, network: &Network) -> Result<Vec<String>> {
+        Ok(match query {
+            PriceQuery::Address(address) => {
+                let chain = network.chain;
+                let platform = chain.coingecko_platform.with_context(|| {
+                    format!(
+                        "Token prices by address are not available on {}",
+                        chain.name
+                    )
+                })?;
+                vec![
+                    "coins".to_string(),
+                    platform.to_string(),
+                    "contract".to_string(),
+                    format!("{address:?}"),
+                ]
+            }
+            PriceQuery::Ether => vec!["coins".to_string(), "ethereum".to_string()],
+            PriceQuery::Symbol(symbol) => vec!["coins".to_string(), coin_id(symbol).to_string()],
+        })
+    }
+}
+
+/// CoinGecko price entries keyed by coin ID or contract address
+type PriceMap = serde_json::Map<String, serde_json::Value>;
+
+/// Maximum number of coin IDs or contract addresses per CoinGecko request
+const MAX_IDS_PER_REQUEST: usize = 100;
+
+/// How far before and after a block's timestamp historical prices are searched, in seconds.
+/// CoinGecko returns hourly points for ranges this short
+const HISTORICAL_SEARCH_SECS: u64 = 6 * 3600;
+
+/// CoinGecko coin ID of a symbol that isn't in the token registry. Unknown symbols are
+/// assumed to be coin IDs already, e.g. "bitcoin"
+fn coin_id(symbol: &str) -> &str {
+    match symbol {
+        "btc" => "bitcoin",
+        "wbtc" => "wrapped-bitcoin",
+        "weth" => "weth",
+        "usdc" => "usd-coin",
+        "usdt" => "tether",
+        "dai" => "dai",
+        "link" => "chainlink",
+        "uni" => "uniswap",
+        "aave" => "aave",
+        "mkr" => "maker",
+        "comp" => "compound-governance-token",
+        "matic" => "matic-network",
+        "pol" => "polygon-ecosystem-token",
+        "bnb" => "binancecoin",
+        "avax" => "avalanche-2",
+        "sol" => "solana",
+        _ => symbol,
+    }
+}
+
+/// Token label and address reported for `query`
+fn token_label(query: &PriceQuery) -> (String, Option<String>) {
+    match query {
+        PriceQuery::Address(address) => {
+            let address = format!("{address:?}");
+            (address.clone(), Some(address))
+        }
+        PriceQuery::Ether => ("ETH".to_string(), None),
+        PriceQuery::Symbol(symbol) => (symbol.clone(), None),
+    }
+}
+
+fn price_output(
+    token: String,
+    token_address: Option<String>,
+    token_data: &serde_json::Map<String, serde_json::Value>,
+) -> TokenPriceOutput {
+    let price_usd = token_data
+        .get("usd")
+        .and_then(|v| v.as_f64())
+        .map(|v| format!("{v:.6}"));
+
+    let price_eth = token_data
+        .get("eth")
+        .and_then(|v| v.as_f64())
+        .map(|v| format!("{v:.18}"));
+
+    let last_updated = token_data
+        .get("last_updated_at")
+        .and_then(|v| v.as_u64())
+        .and_then(format_timestamp);
+
+    TokenPriceOutput {
+        token,
+        token_address,
+        price_usd,
+        price_eth,
+        last_updated,
+        source: "coingecko".to_string(),
+    }
+}
+
+#[async_trait]
+impl PriceSource for CoinGeckoPriceSource {
+    fn name(&self) -> &'static str {
+        "coingecko"
+    }
+
+    async fn get_price(&self, query: &PriceQuery, network: &Network) -> Result<TokenPriceOutput> {
+        self.get_prices(std::slice::from_ref(query), network)
+            .await
+            .pop()
+            .context("No price returned")?
+    }
+
+    /// Price all address queries with one `simple/token_price` request and all symbol
+    /// queries with one `simple/price` request (per [`MAX_IDS_PER_REQUEST`] tokens)
+    async fn get_prices(
+        &self,
+        queries: &[PriceQuery],
+        network: &Network,
+    ) -> Vec<Result<TokenPriceOutput>> {
+        let chain = network.chain;
+
+        let mut addresses: Vec<String> = queries
+            .iter()
+            .filter_map(|query| match query {
+                PriceQuery::Address(address) => Some(format!("{address:?}")),
+                _ => None,
+            })
+            .collect();
+        addresses.sort();
+        addresses.dedup();
+
+        let mut ids: Vec<&str> = queries
+            .iter()
+            .filter_map(|query| match query {
+                PriceQuery::Address(_) => None,
+                PriceQuery::Ether => Some("ethereum"),
+                PriceQuery::Symbol(symbol) => Some(coin_id(symbol)),
+            })
+            .collect();
+        ids.sort();
+        ids.dedup();
+
+        let (address_prices, id_prices) = futures::join!(
+            async {
+                if addresses.is_empty() {
+                    return Ok(PriceMap::new());
+                }
+                let platform = chain.coingecko_platform.with_context(|| {
+                    format!(
+                        "Token prices by address are not available on {}",
+                        chain.name
+                    )
+                })?;
+                self.fetch_prices_by_address(platform, &addresses).await
+            },
+            async {
+                if ids.is_empty() {
+                    return Ok(PriceMap::new());
+                }
+                self.fetch_prices_by_id(&ids).await
+            }
+        );
+
+        queries
+            .iter()
+            .map(|query| {
+                let (token, token_address) = token_label(query);
+                let (prices, key) = match query {
+                    PriceQuery::Address(address) => (&address_prices, format!("{address:?}")),
+                    PriceQuery::Ether => (&id_prices, "ethereum".to_string()),
+                    PriceQuery::Symbol(symbol) => (&id_prices, coin_id(symbol).to_string()),
+                };
+
+                let prices = prices.as_ref().map_err(|e| anyhow::anyhow!("{e:#}"))?;
+                let token_data = prices
+                    .get(&key)
+                    .and_then(|v| v.as_object())
+                    .context("Token not found in CoinGecko response")?;
+                Ok(price_output(token, token_address, token_data))
+            })
+            .collect()
+    }
+
+    /// The price point closest to the block's timestamp, within
+    /// [`HISTORICAL_SEARCH_SECS`] of it
+    async fn get_historical_price(
+        &self,
+        query: &PriceQuery,
+        network: &Network,
+        block: &ResolvedBlock,
+    ) -> Result<TokenPriceOutput> {
+        let points = self
+            .get_price_history(
+                query,
+                network,
+                block.timestamp.saturating_sub(HISTORICAL_SEARCH_SECS),
+                block.timestamp + HISTORICAL_SEARCH_SECS,
+            )
+            .await?;
+        let point = points
+            .iter()
+            .min_by_key(|point| point.timestamp.abs_diff(block.timestamp))
+            .with_context(|| {
+                format!(
+                    "CoinGecko has no price within {}h of block {}",
+                    HISTORICAL_SEARCH_SECS / 3600,
+                    block.number
+                )
+            })?;
+
+        let (token, token_address) = token_label(query);
+        Ok(TokenPriceOutput {
+            token,
+            token_address,
+            price_usd: Some(format!("{:.6}", point.price_usd.round_dp(6))),
+            price_eth: None,
+            last_updated: format_timestamp(point.timestamp),
+            source: "coingecko".to_string(),
+        })
+    }
+
+    async fn get_price_history(
+        &self,
+        query: &PriceQuery,
+        network: &Network,
+        from: u64,
+        to: u64,
+    ) -> Result<Vec<PricePoint>> {
+        let mut path = Self::coin_path(query, network)?;
+        path.extend(["market_chart".to_string(), "range".to_string()]);
+        let url = self.url(
+            &path,
+            &[
+                ("vs_currency", "usd"),
+                ("from", &from.to_string()),
+                ("to", &to.to_string()),
+            ],
+        )?;
+        info!("Fetching price history from CoinGecko: {}", url);
+
+        let chart = self.get_json(url).await?;
+        let prices = chart
+            .get("prices")
+            .and_then(|v| v.as_array())
+            .context("Unexpected CoinGecko market chart format")?;
+
+        // Each point is [timestamp in milliseconds, price]
+        prices
+            .iter()
+            .map(|point| match point.as_array().map(Vec::as_slice) {
+                Some([millis, price]) => {
+                    let millis = millis
+                        .as_u64()
+                        .or_else(|| millis.as_f64().map(|v| v as u64));
+                    let price = price.as_f64().and_then(|v| Decimal::try_from(v).ok());
+                    match (millis, price) {
+                        (Some(millis), Some(price_usd)) => Ok(PricePoint {
+                            timestamp: millis / 1000,
+                            price_usd,
+                        }),
+                        _ => anyhow::bail!("Invalid CoinGecko price point: {point}"),
+                    }
+                }
+                _ => anyhow::bail!("Invalid CoinGecko price point: {point}"),
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,434 @@
+// Copyright 2025 chenjjiaa
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use ethabi::{ParamType, Token};
+use ethers::types::{Address, I256, U256};
+use rust_decimal::Decimal;
+use std::str::FromStr;
+use tracing::{debug, info, warn};
+
+use super::{format_timestamp, PriceQuery, PriceSource, TokenPriceOutput};
+use crate::abi;
+use crate::blocks::ResolvedBlock;
+use crate::chains::{Chain, Network};
+use crate::config::PriceConfig;
+use crate::erc20;
+use crate::multicall::Multicall;
+use crate::swap::{SwapProvider, V3_FEE_TIERS};
+
+/// Minimum wrapped-native reserve (in whole tokens) for a V2 pair to be trusted as a
+/// price reference. Thinner pairs are easy to move, so the V3 quoters are used instead
+const MIN_V2_NATIVE_RESERVE: u64 = 10;
+
+/// Each tick moves the V3 pool price by a factor of 1.0001
+const TICK_BASE: f64 = 1.0001;
+
+/// On-chain prices derived from Uniswap pools.
+///
+/// A token is priced against the wrapped native token, which is priced against USDC.
+/// Each leg uses the V2 pair mid price when the pair holds enough liquidity, otherwise
+/// a QuoterV2 quote for one whole token with the pool fee added back.
+///
+/// Historical prices use the same legs, each read as a V3 pool TWAP at the block.
+pub struct UniswapPriceSource {
+    twap_window_secs: u32,
+}
+
+impl UniswapPriceSource {
+    pub fn new(config: &PriceConfig) -> Self {
+        Self {
+            twap_window_secs: config.twap_window_secs,
+        }
+    }
+
+    /// Price of one `base` in `quote` units, and the Uniswap version it came from
+    async fn mid_price(
+        &self,
+        network: &Network,
+        base: (Address, u8),
+        quote: (Address, u8),
+    ) -> Result<(Decimal, &'static str)> {
+        match self.v2_mid_price(network, base, quote).await {
+            Ok(Some(price)) => return Ok((price, "uniswap_v2")),
+            Ok(None) => debug!(
+                "V2 pair {:?}/{:?} is missing or too thin, trying V3",
+                base.0, quote.0
+            ),
+            Err(e) => warn!("Failed to read V2 pair {:?}/{:?}: {:#}", base.0, quote.0, e),
+        }
+
+        let price = self.v3_price(network, base, quote).await?;
+        Ok((price, "uniswap_v3"))
+    }
+
+    /// Mid price from the reserves of the V2 pair, `None` if there is no pair or its
+    /// wrapped-native reserve is below [`MIN_V2_NATIVE_RESERVE`]
+    async fn v2_mid_price(
+        &self,
+        network: &Network,
+        (base, base_decimals): (Address, u8),
+        (quote, quote_decimals): (Address, u8),
+    ) -> Result<Option<Decimal>> {
+        let provider = &network.provider;
+        let contracts = &network.chain.contracts;
+        let weth = Address::from_str(contracts.weth)?;
+        let router = Address::from_str(contracts.uniswap_v2_router)?;
+
+        let factory = abi::get_factory(provider, router).await?;
+
+        let get_pair_fn = abi::get_pair_function();
+        let pair = match abi::call(
+            provider,
+            factory,
+            &get_pair_fn,
+            &[Token::Address(base), Token::Address(quote)],
+            None,
+        )
+        .await?
+        .first()
+        {
+            Some(Token::Address(pair)) if pair.is_zero() => return Ok(None),
+            Some(Token::Address(pair)) => *pair,
+            _ => anyhow::bail!("Unexpected getPair result format"),
+        };
+
+        let get_reserves_fn = abi::get_reserves_function();
+        let (reserve0, reserve1) = match abi::call(provider, pair, &get_reserves_fn, &[], None)
+            .await?
+            .as_slice()
+        {
+            [Token::Uint(reserve0), Token::Uint(reserve1), _] => (*reserve0, *reserve1),
+            _ => anyhow::bail!("Unexpected getReserves result format"),
+        };
+
+        // Pair tokens are sorted by address
+        let (base_reserve, quote_reserve) = if base < quote {
+            (reserve0, reserve1)
+        } else {
+            (reserve1, reserve0)
+        };
+
+        let native_reserve = if base == weth {
+            base_reserve
+        } else {
+            quote_reserve
+        };
+        if native_reserve < U256::exp10(18) * U256::from(MIN_V2_NATIVE_RESERVE) {
+            return Ok(None);
+        }
+
+        let base_reserve = erc20::to_decimal_units(base_reserve, base_decimals)?;
+        let quote_reserve = erc20::to_decimal_units(quote_reserve, quote_decimals)?;
+        if base_reserve.is_zero() {
+            return Ok(None);
+        }
+
+        Ok(Some(quote_reserve / base_reserve))
+    }
+
+    /// Best QuoterV2 quote for one whole `base` over all fee tiers, with the fee added back
+    /// so the result approximates the pool price rather than the amount received
+    async fn v3_price(
+        &self,
+        network: &Network,
+        (base, base_decimals): (Address, u8),
+        (quote, quote_decimals): (Address, u8),
+    ) -> Result<Decimal> {
+        let swap_provider = SwapProvider::new(network.provider.clone(), network.chain);
+        let amount_in = U256::exp10(base_decimals as usize);
+
+        let quotes = futures::future::join_all(V3_FEE_TIERS.iter().map(|&fee| {
+            let swap_provider = &swap_provider;
+            async move {
+                let amount_out = swap_provider
+                    .try_get_v3_expected_output_quoter_v2(base, quote, fee, amount_in)
+                    .await?;
+                let amount_out = erc20::to_decimal_units(amount_out, quote_decimals)?;
+                let fee = Decimal::new(fee as i64, 6);
+                Ok::<_, anyhow::Error>(amount_out / (Decimal::ONE - fee))
+            }
+        }))
+        .await;
+
+        quotes
+            .into_iter()
+            .zip(V3_FEE_TIERS)
+            .filter_map(|(quote, fee)| {
+                quote
+                    .map_err(|e| debug!("No V3 quote for fee {}: {:#}", fee, e))
+                    .ok()
+            })
+            .max()
+            .with_context(|| format!("No Uniswap V3 pool quotes {base:?} for {quote:?}"))
+    }
+
+    /// Time-weighted average price of one `base` in `quote` over the TWAP window ending at
+    /// `block`, read with `observe()` from the V3 pool with the most in-range liquidity
+    async fn v3_twap(
+        &self,
+        network: &Network,
+        factory: Address,
+        (base, base_decimals): (Address, u8),
+        (quote, quote_decimals): (Address, u8),
+        block: &ResolvedBlock,
+    ) -> Result<Decimal> {
+        let provider = &network.provider;
+        let (pool, fee) = self
+            .deepest_v3_pool(network, factory, base, quote, block)
+            .await?;
+
+        let observe_fn = abi::view_function(
+            "observe",
+            vec![ParamType::Array(Box::new(ParamType::Uint(32)))],
+            vec![
+                ParamType::Array(Box::new(ParamType::Int(56))),
+                ParamType::Array(Box::new(ParamType::Uint(160))),
+            ],
+        );
+        let window = self.twap_window_secs;
+        let seconds_agos =
+            Token::Array(vec![Token::Uint(window.into()), Token::Uint(U256::zero())]);
+        let observations = abi::call(
+            provider,
+            pool,
+            &observe_fn,
+            &[seconds_agos],
+            Some(block.id()),
+        )
+        .await
+        .with_context(|| {
+            format!(
+                "Failed to observe pool {pool:?} {window}s before block {} (its oracle history may be shorter)",
+                block.number
+            )
+        })?;
+        let (start, end) = match observations.first() {
+            Some(Token::Array(cumulatives)) => match cumulatives.as_slice() {
+                [Token::Int(start), Token::Int(end)] => (*start, *end),
+                _ => anyhow::bail!("Unexpected observe result length"),
+            },
+            _ => anyhow::bail!("Unexpected observe result format"),
+        };
+
+        // Average tick, rounded towards negative infinity like the Uniswap OracleLibrary
+        let elapsed = I256::from(window);
+        let delta = I256::from_raw(end) - I256::from_raw(start);
+        let mut tick = delta / elapsed;
+        if delta.is_negative() && !(delta % elapsed).is_zero() {
+            tick -= I256::one();
+        }
+        let tick = i32::try_from(tick).map_err(|_| anyhow::anyhow!("Invalid TWAP tick {tick}"))?;
+
+        // The pool price is token1 per token0 in raw units; tokens are sorted by address
+        let (token0_decimals, token1_decimals) = if base < quote {
+            (base_decimals, quote_decimals)
+        } else {
+            (quote_decimals, base_decimals)
+        };
+        let price0 = TICK_BASE.powi(tick)
+            * 10f64.powi(i32::from(token0_decimals) - i32::from(token1_decimals));
+        let price = if base < quote { price0 } else { 1.0 / price0 };
+
+        debug!(
+            "V3 TWAP of {:?} in {:?} at block {}: tick {} over {}s in pool {:?} (fee {})",
+            base, quote, block.number, tick, window, pool, fee
+        );
+        Decimal::try_from(price).with_context(|| format!("TWAP price {price} is out of range"))
+    }
+
+    /// The V3 pool for `base`/`quote` with the most in-range liquidity at `block`, and its fee
+    async fn deepest_v3_pool(
+        &self,
+        network: &Network,
+        factory: Address,
+        base: Address,
+        quote: Address,
+        block: &ResolvedBlock,
+    ) -> Result<(Address, u32)> {
+        let get_pool_fn = abi::get_pool_function();
+        let mut multicall = Multicall::new(&network.provider, network.chain)?.at_block(block.id());
+        for fee in V3_FEE_TIERS {
+            multicall.add_call(
+                factory,
+                &get_pool_fn,
+                &[
+                    Token::Address(base),
+                    Token::Address(quote),
+                    Token::Uint(fee.into()),
+                ],
+            )?;
+        }
+        let pools: Vec<(Address, u32)> = multicall
+            .call()
+            .await?
+            .into_iter()
+            .zip(V3_FEE_TIERS)
+            .filter_map(|(result, fee)| match result {
+                Ok(tokens) => match tokens.first() {
+                    Some(Token::Address(pool)) if !pool.is_zero() => Some((*pool, fee)),
+                    _ => None,
+                },
+                Err(e) => {
+                    debug!("No V3 pool for fee {}: {:#}", fee, e);
+                    None
+                }
+            })
+            .collect();
+
+        let liquidity_fn = abi::view_function("liquidity", vec![], vec![ParamType::Uint(128)]);
+        let mut multicall = Multicall::new(&network.provider, network.chain)?.at_block(block.id());
+        for (pool, _) in &pools {
+            multicall.add_call(*pool, &liquidity_fn, &[])?;
+        }
+        multicall
+            .call()
+            .await?
+            .into_iter()
+            .zip(pools)
+            .filter_map(|(result, (pool, fee))| match result.ok()?.first() {
+                Some(Token::Uint(liquidity)) if !liquidity.is_zero() => {
+                    Some((pool, fee, *liquidity))
+                }
+                _ => None,
+            })
+            .max_by_key(|(_, _, liquidity)| *liquidity)
+            .map(|(pool, fee, _)| (pool, fee))
+            .with_context(|| {
+                format!(
+                    "No Uniswap V3 pool for {base:?}/{quote:?} has liquidity at block {}",
+                    block.number
+                )
+            })
+    }
+}
+
+#[async_trait]
+impl PriceSource for UniswapPriceSource {
+    fn name(&self) -> &'static str {
+        "uniswap"
+    }
+
+    async fn get_price(&self, query: &PriceQuery, network: &Network) -> Result<TokenPriceOutput> {
+        let chain = network.chain;
+        let native_is_ether = chain.native_symbol == "ETH";
+        let weth = Address::from_str(chain.contracts.weth).context("Invalid WETH address")?;
+        let usdc = Address::from_str(chain.contracts.usdc).context("Invalid USDC address")?;
+        let (token, label) = query_token(query, chain, weth)?;
+
+        let decimals = erc20::get_decimals(&network.provider, chain, &[token, usdc]).await?;
+        let (token_decimals, usdc_decimals) = (decimals[0], decimals[1]);
+
+        let mut sources = Vec::new();
+        let price_native = if token == weth {
+            Decimal::ONE
+        } else {
+            let (price, source) = self
+                .mid_price(network, (token, token_decimals), (weth, 18))
+                .await
+                .context("Failed to price token in the wrapped native token")?;
+            sources.push(source);
+            price
+        };
+        let (native_usd, source) = self
+            .mid_price(network, (weth, 18), (usdc, usdc_decimals))
+            .await
+            .context("Failed to price the wrapped native token in USDC")?;
+        sources.push(source);
+        sources.dedup();
+
+        let price_usd = price_native * native_usd;
+        info!(
+            "Uniswap price of {} on {}: {} USD ({} {})",
+            label, chain.name, price_usd, price_native, chain.native_symbol
+        );
+
+        Ok(TokenPriceOutput {
+            token: label,
+            token_address: matches!(query, PriceQuery::Address(_)).then(|| format!("{token:?}")),
+            price_usd: Some(format!("{:.6}", price_usd.round_dp(6))),
+            price_eth: native_is_ether.then(|| format!("{:.18}", price_native.round_dp(18))),
+            last_updated: None,
+            source: sources.join("+"),
+        })
+    }
+
+    async fn get_historical_price(
+        &self,
+        query: &PriceQuery,
+        network: &Network,
+        block: &ResolvedBlock,
+    ) -> Result<TokenPriceOutput> {
+        let chain = network.chain;
+        let native_is_ether = chain.native_symbol == "ETH";
+        let weth = Address::from_str(chain.contracts.weth).context("Invalid WETH address")?;
+        let usdc = Address::from_str(chain.contracts.usdc).context("Invalid USDC address")?;
+        let (token, label) = query_token(query, chain, weth)?;
+
+        let decimals = erc20::get_decimals(&network.provider, chain, &[token, usdc]).await?;
+        let (token_decimals, usdc_decimals) = (decimals[0], decimals[1]);
+        let quoter = Address::from_str(chain.contracts.uniswap_v3_quoter_v2)
+            .context("Invalid quoter address")?;
+        let factory = abi::get_factory(&network.provider, quoter).await?;
+
+        let price_native = if token == weth {
+            Decimal::ONE
+        } else {
+            self.v3_twap(network, factory, (token, token_decimals), (weth, 18), block)
+                .await
+                .context("Failed to read the token TWAP in the wrapped native token")?
+        };
+        let native_usd = self
+            .v3_twap(network, factory, (weth, 18), (usdc, usdc_decimals), block)
+            .await
+            .context("Failed to read the wrapped native token TWAP in USDC")?;
+
+        let price_usd = price_native * native_usd;
+        info!(
+            "Uniswap V3 TWAP of {} on {} at block {}: {} USD ({} {})",
+            label, chain.name, block.number, price_usd, price_native, chain.native_symbol
+        );
+
+        Ok(TokenPriceOutput {
+            token: label,
+            token_address: matches!(query, PriceQuery::Address(_)).then(|| format!("{token:?}")),
+            price_usd: Some(format!("{:.6}", price_usd.round_dp(6))),
+            price_eth: native_is_ether.then(|| format!("{:.18}", price_native.round_dp(18))),
+            last_updated: format_timestamp(block.timestamp),
+            source: "uniswap_v3_twap".to_string(),
+        })
+    }
+}
+
+/// The token contract that prices `query` on `chain`, and the label to report it under.
+/// The native token is priced through its wrapped version
+fn query_token(query: &PriceQuery, chain: &Chain, weth: Address) -> Result<(Address, String)> {
+    match query {
+        PriceQuery::Address(address) => Ok((*address, format!("{address:?}"))),
+        PriceQuery::Ether if chain.native_symbol == "ETH" => Ok((weth, "ETH".to_string())),
+        PriceQuery::Ether => anyhow::bail!(
+            "ETH is not the native token of {}; pass the WETH token address instead",
+            chain.name
+        ),
+        PriceQuery::Symbol(symbol) if chain.is_native_token(symbol) => {
+            Ok((weth, chain.native_symbol.to_string()))
+        }
+        PriceQuery::Symbol(symbol) => {
+            anyhow::bail!(
+                "Uniswap prices need a token address, and {symbol} is not in the token registry"
+            )
+        }
+    }
+}
@@ -0,0 +1,671 @@
+// Copyright 2025 chenjjiaa
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use ethers::providers::{Http, HttpClientError, JsonRpcClient, Provider, ProviderError};
+use ethers::types::U64;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use std::fmt::Debug;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tracing::{debug, warn};
+
+/// Provider type used for every chain
+pub type RpcProvider = Provider<RpcClient>;
+
+/// Read methods whose results are checked for agreement in quorum mode, with the position
+/// of their block parameter. Methods that report the chain head itself (`eth_blockNumber`,
+/// fee and gas price estimates) can't agree across endpoints and always use failover
+const QUORUM_METHODS: &[(&str, usize)] = &[
+    ("eth_call", 1),
+    ("eth_getBalance", 1),
+    ("eth_getCode", 1),
+    ("eth_getStorageAt", 2),
+];
+
+/// Retry, failover and quorum settings for a chain's RPC endpoints
+#[derive(Debug, Clone)]
+pub struct RpcSettings {
+    /// Number of extra rounds over all endpoints after the first round fails
+    pub max_retries: u32,
+    /// Backoff before the first retry round, doubled on every further round
+    pub initial_backoff: Duration,
+    /// How long a failing endpoint is skipped before it's tried again
+    pub unhealthy_cooldown: Duration,
+    /// Number of endpoints that must return the same read result. 0 or 1 disables quorum mode
+    pub quorum: usize,
+}
+
+/// RPC endpoints and settings for a single chain
+#[derive(Debug, Clone)]
+pub struct RpcEndpoints {
+    pub urls: Vec<String>,
+    pub settings: RpcSettings,
+}
+
+#[derive(Debug, Error)]
+pub enum RpcClientError {
+    /// The endpoint answered with an error that another endpoint would return as well
+    /// (e.g. an execution revert), so it isn't retried
+    #[error(transparent)]
+    Client(#[from] HttpClientError),
+
+    #[error("All RPC endpoints failed for {method}, last error: {source}")]
+    AllEndpointsFailed {
+        method: String,
+        source: HttpClientError,
+    },
+
+    #[error("RPC quorum not reached for {method}: at most {agreeing} of {required} endpoints agreed ({responded} responded)")]
+    QuorumNotReached {
+        method: String,
+        agreeing: usize,
+        required: usize,
+        responded: usize,
+    },
+
+    #[error(transparent)]
+    SerdeJson(#[from] serde_json::Error),
+}
+
+impl From<RpcClientError> for ProviderError {
+    fn from(src: RpcClientError) -> Self {
+        ProviderError::JsonRpcClientError(Box::new(src))
+    }
+}
+
+#[derive(Debug, Default)]
+struct EndpointHealth {
+    consecutive_failures: u32,
+    unhealthy_until: Option<Instant>,
+}
+
+#[derive(Debug)]
+struct Endpoint {
+    url: String,
+    client: Http,
+    health: Mutex<EndpointHealth>,
+}
+
+impl Endpoint {
+    fn is_healthy(&self) -> bool {
+        let health = self.health.lock().unwrap_or_else(|e| e.into_inner());
+        health
+            .unhealthy_until
+            .is_none_or(|until| Instant::now() >= until)
+    }
+
+    fn record_success(&self) {
+        let mut health = self.health.lock().unwrap_or_else(|e| e.into_inner());
+        if health.consecutive_failures > 0 {
+            debug!("RPC endpoint {} recovered", self.url);
+        }
+        *health = EndpointHealth::default();
+    }
+
+    fn record_failure(&self, cooldown: Duration) {
+        let mut health = self.health.lock().unwrap_or_else(|e| e.into_inner());
+        health.consecutive_failures += 1;
+        health.unhealthy_until = Some(Instant::now() + cooldown);
+        warn!(
+            "RPC endpoint {} marked unhealthy for {:?} ({} consecutive failures)",
+            self.url, cooldown, health.consecutive_failures
+        );
+    }
+}
+
+/// JSON-RPC client over several HTTP endpoints with health tracking, failover,
+/// retry with exponential backoff and an optional read quorum.
+#[derive(Debug)]
+pub struct RpcClient {
+    endpoints: Vec<Endpoint>,
+    settings: RpcSettings,
+}
+
+impl RpcClient {
+    pub fn new(endpoints: &RpcEndpoints) -> Result<Self> {
+        if endpoints.urls.is_empty() {
+            anyhow::bail!("At least one RPC URL is required");
+        }
+
+        let clients = endpoints
+            .urls
+            .iter()
+            .map(|url| {
+                Ok(Endpoint {
+                    url: url.clone(),
+                    client: Http::from_str(url)
+                        .with_context(|| format!("Invalid RPC URL: {url}"))?,
+                    health: Mutex::new(EndpointHealth::default()),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            endpoints: clients,
+            settings: endpoints.settings.clone(),
+        })
+    }
+
+    /// Endpoints in configured priority order, healthy ones first
+    fn ordered_endpoints(&self) -> Vec<&Endpoint> {
+        let (mut healthy, unhealthy): (Vec<_>, Vec<_>) =
+            self.endpoints.iter().partition(|e| e.is_healthy());
+        healthy.extend(unhealthy);
+        healthy
+    }
+
+    async fn request_with_failover(
+        &self,
+        method: &str,
+        params: &Value,
+    ) -> Result<Value, RpcClientError> {
+        let mut backoff = self.settings.initial_backoff;
+        let mut last_error = None;
+
+        for attempt in 0..=self.settings.max_retries {
+            if attempt > 0 {
+                debug!(
+                    "Retrying {} in {:?} (attempt {})",
+                    method,
+                    backoff,
+                    attempt + 1
+                );
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+
+            for endpoint in self.ordered_endpoints() {
+                match endpoint.client.request::<_, Value>(method, params).await {
+                    Ok(result) => {
+                        endpoint.record_success();
+                        return Ok(result);
+                    }
+                    Err(e) if !is_retryable(&e) => return Err(e.into()),
+                    Err(e) => {
+                        warn!("RPC {} failed on {}: {}", method, endpoint.url, e);
+                        endpoint.record_failure(self.settings.unhealthy_cooldown);
+                        last_error = Some(e);
+                    }
+                }
+            }
+        }
+
+        let source = last_error.expect("RpcClient always has at least one endpoint");
+        Err(RpcClientError::AllEndpointsFailed {
+            method: method.to_string(),
+            source,
+        })
+    }
+
+    /// Send a read to every endpoint and return the result `quorum` of them agree on,
+    /// retrying with backoff as in [`RpcClient::request_with_failover`] while they don't
+    async fn request_with_quorum(
+        &self,
+        method: &str,
+        block_index: usize,
+        params: &Value,
+    ) -> Result<Value, RpcClientError> {
+        let mut backoff = self.settings.initial_backoff;
+        let mut last_error = None;
+
+        for attempt in 0..=self.settings.max_retries {
+            if attempt > 0 {
+                debug!(
+                    "Retrying {} in {:?} (attempt {})",
+                    method,
+                    backoff,
+                    attempt + 1
+                );
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+
+            match self.quorum_round(method, block_index, params).await {
+                Err(e @ RpcClientError::QuorumNotReached { .. }) => {
+                    warn!("{}", e);
+                    last_error = Some(e);
+                }
+                result => return result,
+            }
+        }
+
+        Err(last_error.expect("at least one quorum round runs"))
+    }
+
+    async fn quorum_round(
+        &self,
+        method: &str,
+        block_index: usize,
+        params: &Value,
+    ) -> Result<Value, RpcClientError> {
+        let required = self.settings.quorum;
+        let params = &self.pin_block(block_index, params).await?;
+        let responses =
+            futures::future::join_all(self.endpoints.iter().map(|endpoint| async move {
+                match endpoint.client.request::<_, Value>(method, params).await {
+                    Ok(result) => {
+                        endpoint.record_success();
+                        Some(Ok(result))
+                    }
+                    Err(e) if !is_retryable(&e) => Some(Err(e)),
+                    Err(e) => {
+                        warn!("RPC {} failed on {}: {}", method, endpoint.url, e);
+                        endpoint.record_failure(self.settings.unhealthy_cooldown);
+                        None
+                    }
+                }
+            }))
+            .await;
+
+        tally(method, required, responses)
+    }
+
+    /// `params` with a `latest` or `pending` (or omitted) block parameter replaced by the
+    /// quorum head, so that endpoints a block apart read the same state
+    async fn pin_block(&self, block_index: usize, params: &Value) -> Result<Value, RpcClientError> {
+        let mut params = params.clone();
+        let Some(list) = params.as_array_mut() else {
+            return Ok(params);
+        };
+        let follows_head = match list.get(block_index) {
+            None | Some(Value::Null) => true,
+            Some(Value::String(tag)) => tag == "latest" || tag == "pending",
+            Some(_) => false,
+        };
+        if !follows_head {
+            return Ok(params);
+        }
+
+        let head = self.quorum_head().await?;
+        list.resize(list.len().max(block_index + 1), Value::Null);
+        list[block_index] = Value::String(format!("{head:#x}"));
+        Ok(params)
+    }
+
+    /// The highest block that at least `quorum` endpoints have, i.e. the lowest head among
+    /// the `quorum` most advanced endpoints
+    async fn quorum_head(&self) -> Result<u64, RpcClientError> {
+        let required = self.settings.quorum;
+        let heads = futures::future::join_all(self.endpoints.iter().map(|endpoint| async move {
+            match endpoint
+                .client
+                .request::<_, U64>("eth_blockNumber", ())
+                .await
+            {
+                Ok(head) => {
+                    endpoint.record_success();
+                    Some(head.as_u64())
+                }
+                Err(e) => {
+                    warn!("RPC eth_blockNumber failed on {}: {}", endpoint.url, e);
+                    if is_retryable(&e) {
+                        endpoint.record_failure(self.settings.unhealthy_cooldown);
+                    }
+                    None
+                }
+            }
+        }))
+        .await;
+
+        let mut heads: Vec<u64> = heads.into_iter().flatten().collect();
+        heads.sort_unstable_by(|a, b| b.cmp(a));
+        let head = heads.get(required.saturating_sub(1)).copied().ok_or(
+            RpcClientError::QuorumNotReached {
+                method: "eth_blockNumber".to_string(),
+                agreeing: heads.len(),
+                required,
+                responded: heads.len(),
+            },
+        )?;
+        debug!(
+            "Quorum head is block {} (endpoint heads: {:?})",
+            head, heads
+        );
+        Ok(head)
+    }
+}
+
+#[async_trait]
+impl JsonRpcClient for RpcClient {
+    type Error = RpcClientError;
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, Self::Error>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned,
+    {
+        let params = serde_json::to_value(params)?;
+
+        let quorum_method = QUORUM_METHODS.iter().find(|(name, _)| *name == method);
+        let result = match quorum_method {
+            Some(&(_, block_index)) if self.settings.quorum > 1 => {
+                self.request_with_quorum(method, block_index, &params)
+                    .await?
+            }
+            _ => self.request_with_failover(method, &params).await?,
+        };
+
+        Ok(serde_json::from_value(result)?)
+    }
+}
+
+/// Whether an error is caused by the endpoint (connectivity, rate limiting, malformed
+/// responses) rather than by the request itself, so another endpoint may succeed
+fn is_retryable(error: &HttpClientError) -> bool {
+    match error {
+        HttpClientError::ReqwestError(_) => true,
+        // Non JSON-RPC bodies are typically HTTP 429 / 5xx pages
+        HttpClientError::SerdeJson { .. } => true,
+        HttpClientError::JsonRpcError(e) => {
+            let message = e.message.to_lowercase();
+            // Some nodes report reverts as -32603 as well; every endpoint would revert again
+            if message.contains("revert") || has_revert_data(e.data.as_ref()) {
+                return false;
+            }
+            // 429: rate limited, -32005: limit exceeded, -32603: internal node error
+            matches!(e.code, 429 | -32005 | -32603)
+                || message.contains("rate limit")
+                || message.contains("too many requests")
+                || message.contains("timeout")
+        }
+    }
+}
+
+/// The response at least `required` endpoints agree on, out of the responses of a quorum
+/// round (`None` for endpoints that failed). Deterministic errors such as reverts count as
+/// responses too, so a revert every endpoint agrees on is returned as such
+fn tally(
+    method: &str,
+    required: usize,
+    responses: Vec<Option<Result<Value, HttpClientError>>>,
+) -> Result<Value, RpcClientError> {
+    let mut tallies: Vec<(Result<Value, HttpClientError>, usize)> = Vec::new();
+    let mut responded = 0;
+    for response in responses.into_iter().flatten() {
+        responded += 1;
+        match tallies
+            .iter_mut()
+            .find(|(seen, _)| same_response(seen, &response))
+        {
+            Some((_, count)) => *count += 1,
+            None => tallies.push((response, 1)),
+        }
+    }
+
+    let agreeing = tallies.iter().map(|(_, count)| *count).max().unwrap_or(0);
+    if agreeing < required {
+        return Err(RpcClientError::QuorumNotReached {
+            method: method.to_string(),
+            agreeing,
+            required,
+            responded,
+        });
+    }
+
+    let (response, _) = tallies
+        .into_iter()
+        .find(|(_, count)| *count == agreeing)
+        .expect("tally with the maximum count exists");
+    Ok(response?)
+}
+
+/// Whether JSON-RPC error data holds revert data, either as the hex string itself or
+/// nested under `data` as some nodes return it
+fn has_revert_data(data: Option<&Value>) -> bool {
+    match data {
+        Some(Value::String(data)) => data.starts_with("0x"),
+        Some(Value::Object(data)) => has_revert_data(data.get("data")),
+        _ => false,
+    }
+}
+
+fn same_response(a: &Result<Value, HttpClientError>, b: &Result<Value, HttpClientError>) -> bool {
+    match (a, b) {
+        (Ok(a), Ok(b)) => a == b,
+        (Err(HttpClientError::JsonRpcError(a)), Err(HttpClientError::JsonRpcError(b))) => {
+            a.code == b.code && a.data == b.data
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    fn client(urls: &[&str]) -> RpcClient {
+        RpcClient::new(&RpcEndpoints {
+            urls: urls.iter().map(ToString::to_string).collect(),
+            settings: RpcSettings {
+                max_retries: 0,
+                initial_backoff: Duration::ZERO,
+                unhealthy_cooldown: Duration::from_secs(60),
+                quorum: 1,
+            },
+        })
+        .unwrap()
+    }
+
+    /// An endpoint answering every request with the JSON-RPC `response`, and the number of
+    /// requests it got
+    async fn serve(response: Value) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        let body = response.to_string();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                read_request(&mut stream).await;
+                counter.fetch_add(1, Ordering::SeqCst);
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        (url, requests)
+    }
+
+    async fn read_request(stream: &mut TcpStream) {
+        let mut request = Vec::new();
+        let mut buf = [0; 4096];
+        loop {
+            let read = stream.read(&mut buf).await.unwrap();
+            if read == 0 {
+                return;
+            }
+            request.extend_from_slice(&buf[..read]);
+            let text = String::from_utf8_lossy(&request);
+            if let Some(end) = text.find("\r\n\r\n") {
+                let length = text[..end]
+                    .lines()
+                    .find_map(|line| {
+                        let line = line.to_lowercase();
+                        line.strip_prefix("content-length:")
+                            .map(|length| length.trim().parse::<usize>().unwrap())
+                    })
+                    .unwrap_or(0);
+                if request.len() >= end + 4 + length {
+                    return;
+                }
+            }
+        }
+    }
+
+    /// A URL nothing listens on
+    async fn dead_url() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        format!("http://{}", listener.local_addr().unwrap())
+    }
+
+    fn result(result: &str) -> Value {
+        json!({ "jsonrpc": "2.0", "id": 1, "result": result })
+    }
+
+    fn error(code: i64, message: &str, data: Option<&str>) -> Value {
+        json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "error": { "code": code, "message": message, "data": data },
+        })
+    }
+
+    fn urls(client: &RpcClient) -> Vec<&str> {
+        client
+            .ordered_endpoints()
+            .iter()
+            .map(|endpoint| endpoint.url.as_str())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn failover_moves_failed_endpoints_last() {
+        let dead = dead_url().await;
+        let (first, first_requests) = serve(result("0x1")).await;
+        let (second, second_requests) = serve(result("0x2")).await;
+        let client = client(&[&dead, &first, &second]);
+
+        for _ in 0..2 {
+            let head = client
+                .request_with_failover("eth_blockNumber", &json!([]))
+                .await
+                .unwrap();
+            assert_eq!(head, json!("0x1"));
+        }
+        // The dead endpoint was only tried once, then skipped while unhealthy
+        assert_eq!(urls(&client), [&first, &second, &dead]);
+        assert_eq!(first_requests.load(Ordering::SeqCst), 2);
+        assert_eq!(second_requests.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn reverts_fail_fast() {
+        let revert = error(-32603, "execution reverted", Some("0x08c379a0"));
+        let (first, _) = serve(revert).await;
+        let (second, second_requests) = serve(result("0x")).await;
+        let client = client(&[&first, &second]);
+
+        let error = client
+            .request_with_failover("eth_call", &json!([]))
+            .await
+            .unwrap_err();
+        assert!(matches!(error, RpcClientError::Client(_)));
+        assert_eq!(second_requests.load(Ordering::SeqCst), 0);
+        assert_eq!(urls(&client), [&first, &second]);
+    }
+
+    #[tokio::test]
+    async fn internal_errors_without_revert_data_fail_over() {
+        let (first, _) = serve(error(-32603, "internal error", None)).await;
+        let (second, _) = serve(result("0x")).await;
+        let client = client(&[&first, &second]);
+
+        let result = client
+            .request_with_failover("eth_call", &json!([]))
+            .await
+            .unwrap();
+        assert_eq!(result, json!("0x"));
+        assert_eq!(urls(&client), [&second, &first]);
+    }
+
+    #[test]
+    fn revert_data_is_not_retryable() {
+        let error = |data: Value| {
+            HttpClientError::JsonRpcError(
+                serde_json::from_value(json!({ "code": -32603, "message": "error", "data": data }))
+                    .unwrap(),
+            )
+        };
+
+        assert!(!is_retryable(&error(json!("0x08c379a0"))));
+        assert!(!is_retryable(&error(json!({ "data": "0x08c379a0" }))));
+        assert!(is_retryable(&error(Value::Null)));
+        assert!(is_retryable(&error(
+            json!({ "see": "https://example.com" })
+        )));
+    }
+
+    fn response(value: Value) -> Option<Result<Value, HttpClientError>> {
+        match value.get("error") {
+            Some(error) => Some(Err(HttpClientError::JsonRpcError(
+                serde_json::from_value(error.clone()).unwrap(),
+            ))),
+            None => Some(Ok(value["result"].clone())),
+        }
+    }
+
+    #[test]
+    fn quorum_returns_the_agreed_result() {
+        let responses = vec![
+            response(result("0x1")),
+            response(result("0x2")),
+            response(result("0x1")),
+        ];
+        assert_eq!(tally("eth_call", 2, responses).unwrap(), json!("0x1"));
+    }
+
+    #[test]
+    fn quorum_mismatch_is_an_error() {
+        let responses = vec![
+            response(result("0x1")),
+            response(result("0x2")),
+            response(result("0x3")),
+        ];
+        match tally("eth_call", 2, responses) {
+            Err(RpcClientError::QuorumNotReached {
+                agreeing,
+                required,
+                responded,
+                ..
+            }) => assert_eq!((agreeing, required, responded), (1, 2, 3)),
+            result => panic!("Unexpected tally: {result:?}"),
+        }
+    }
+
+    #[test]
+    fn failed_endpoints_do_not_count_toward_quorum() {
+        let responses = vec![response(result("0x1")), None, None];
+        match tally("eth_call", 2, responses) {
+            Err(RpcClientError::QuorumNotReached {
+                agreeing,
+                responded,
+                ..
+            }) => assert_eq!((agreeing, responded), (1, 1)),
+            result => panic!("Unexpected tally: {result:?}"),
+        }
+    }
+
+    #[test]
+    fn agreed_reverts_are_returned() {
+        let revert = || response(error(3, "execution reverted", Some("0x08c379a0")));
+        let responses = vec![revert(), revert(), response(result("0x1"))];
+        assert!(matches!(
+            tally("eth_call", 2, responses),
+            Err(RpcClientError::Client(HttpClientError::JsonRpcError(_)))
+        ));
+    }
+}
@@ -13,11 +13,8 @@
 // limitations under the License.
 
 use anyhow::{Context, Result};
-use ethabi::{Function, Param, ParamType, StateMutability, Token};
-use ethers::{
-    prelude::*,
-    types::{Address, TransactionRequest},
-};
+use ethabi::{ParamType, Token};
+use ethers::{prelude::*, types::Address};
 use rmcp::{
     handler::server::{router::tool::ToolRouter, wrapper::Parameters},
     model::{CallToolResult, Content, ServerCapabilities, ServerInfo},
@@ -28,17 +25,49 @@ use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::str::FromStr;
 use std::sync::Arc;
-use tracing::{info, instrument};
-
-use crate::swap::{SwapInput, SwapProvider};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{info, instrument, warn};
+
+use crate::abi;
+use crate::allowance::{self, AllowanceOutput, GetAllowanceInput};
+use crate::blocks::{self, ResolvedBlock};
+use crate::chains::{ChainRegistry, Network};
+use crate::config::Config;
+use crate::ens::{self, ResolvedName};
+use crate::erc20;
+use crate::multicall::Multicall;
+use crate::policy::{self, Policy, PolicyCheck};
+use crate::prices::{self, chainlink, PriceQuery, PriceSources, TokenPriceOutput};
+use crate::swap::{
+    BuildSwapTransactionInput, CompareSwapQuotesInput, SimulationStatus, SwapInput, SwapProvider,
+    SwapTransactionOutput, UnsignedTransaction,
+};
+use crate::tokens::TokenRegistry;
+use crate::wallet::{
+    self, ExecuteSwapInput, ExecuteSwapOutput, PartialSwapOutput, SendError, SendTransactionInput,
+    SentTransactionOutput, SwapSendError, Wallet,
+};
 
 #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct GetBalanceInput {
-    /// Wallet address to query
+    /// Wallet address or ENS name to query
     pub wallet_address: String,
-    /// Optional ERC20 token contract address. If not provided, returns ETH balance
+    /// Optional ERC20 token contract address, ENS name or symbol (e.g. "USDC"). If not provided, returns ETH balance
     #[serde(default)]
     pub token_address: Option<String>,
+    /// Chain name (e.g. "mainnet", "arbitrum", "base"). Defaults to the server's default chain
+    #[serde(default)]
+    pub chain: Option<String>,
+    /// Chain ID (e.g. 1, 42161, 8453). Alternative to `chain`
+    #[serde(default)]
+    pub chain_id: Option<u64>,
+    /// Block to query at: a number, a block hash, "latest", "safe" or "finalized". Defaults to latest
+    #[serde(default)]
+    pub block: Option<String>,
+    /// Unix timestamp (seconds) to query at, resolved to the last block at or before it.
+    /// Alternative to `block`
+    #[serde(default)]
+    pub timestamp: Option<u64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -53,50 +82,571 @@ pub struct BalanceOutput {
     pub decimals: u8,
     /// Raw balance (wei or token units)
     pub raw_balance: String,
+    /// Chain the balance was queried on
+    pub chain: String,
+    /// Number of the block the balance was computed at
+    pub block_number: u64,
+    /// Hash of the block the balance was computed at
+    pub block_hash: String,
+    /// ENS names in the input and the addresses they resolved to
+    pub ens_names: Vec<ResolvedName>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct GetTokenPriceInput {
-    /// Token contract address (0x...) or symbol (e.g., "USDC", "WETH")
+    /// Token contract address (0x...), ENS name or symbol (e.g., "USDC", "WETH")
+    pub token: String,
+    /// Chain name the token address lives on. Defaults to the server's default chain
+    #[serde(default)]
+    pub chain: Option<String>,
+    /// Chain ID the token address lives on. Alternative to `chain`
+    #[serde(default)]
+    pub chain_id: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct GetTokenPricesInput {
+    /// Token contract addresses (0x...), ENS names or symbols (e.g., "USDC", "WETH")
+    pub tokens: Vec<String>,
+    /// Chain name the token addresses live on. Defaults to the server's default chain
+    #[serde(default)]
+    pub chain: Option<String>,
+    /// Chain ID the token addresses live on. Alternative to `chain`
+    #[serde(default)]
+    pub chain_id: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenPriceError {
+    /// Token as given in the input
+    pub token: String,
+    /// Reason the token couldn't be priced
+    pub error: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenPricesOutput {
+    /// Chain the prices were queried for
+    pub chain: String,
+    /// Prices of the tokens that could be priced, in input order
+    pub prices: Vec<TokenPriceOutput>,
+    /// Tokens that couldn't be priced
+    pub errors: Vec<TokenPriceError>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct GetOraclePriceInput {
+    /// Chainlink pair (e.g. "ETH/USD", "STETH/ETH"), or an asset symbol for its USD feed (e.g. "BTC")
+    pub pair: String,
+    /// Chain name (e.g. "mainnet", "arbitrum", "base"). Defaults to the server's default chain
+    #[serde(default)]
+    pub chain: Option<String>,
+    /// Chain ID (e.g. 1, 42161, 8453). Alternative to `chain`
+    #[serde(default)]
+    pub chain_id: Option<u64>,
+    /// Age in seconds after which the answer is reported as stale. Defaults to the feed's heartbeat
+    #[serde(default)]
+    pub max_age_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OraclePriceOutput {
+    /// Price of the pair's base asset, in the same shape as `get_token_price`
+    #[serde(flatten)]
+    pub price: TokenPriceOutput,
+    /// Chainlink pair, e.g. "ETH/USD"
+    pub pair: String,
+    /// Chain the feed was read on
+    pub chain: String,
+    /// Feed proxy address
+    pub feed_address: String,
+    /// Feed description reported by the contract
+    pub description: String,
+    /// Number of decimals of the answer
+    pub decimals: u8,
+    /// Raw answer, scaled by 10^decimals
+    pub answer: String,
+    /// Round ID of the answer
+    pub round_id: String,
+    /// Unix timestamp of the last answer update
+    pub updated_at: u64,
+    /// Seconds since the last answer update
+    pub age_secs: u64,
+    /// Age after which the answer counts as stale
+    pub max_age_secs: u64,
+    /// Whether the answer is older than `max_age_secs`
+    pub is_stale: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct GetHistoricalPriceInput {
+    /// Token contract address (0x...), ENS name or symbol (e.g., "USDC", "WETH")
+    pub token: String,
+    /// Chain name the token address lives on. Defaults to the server's default chain
+    #[serde(default)]
+    pub chain: Option<String>,
+    /// Chain ID the token address lives on. Alternative to `chain`
+    #[serde(default)]
+    pub chain_id: Option<u64>,
+    /// Block to price at: a number, a block hash, "latest", "safe" or "finalized"
+    #[serde(default)]
+    pub block: Option<String>,
+    /// Unix timestamp (seconds) to price at, resolved to the last block at or before it.
+    /// Alternative to `block`
+    #[serde(default)]
+    pub timestamp: Option<u64>,
+    /// Price source to use: "coingecko" (market price closest to the block time) or
+    /// "uniswap" (V3 pool TWAP at the block). Defaults to the configured sources in order
+    #[serde(default)]
+    pub source: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoricalPriceOutput {
+    /// Price at the block, in the same shape as `get_token_price`. `last_updated` is the
+    /// time of the price data point used
+    #[serde(flatten)]
+    pub price: TokenPriceOutput,
+    /// Chain the price was queried for
+    pub chain: String,
+    /// Number of the block the price was computed at
+    pub block_number: u64,
+    /// Hash of the block the price was computed at
+    pub block_hash: String,
+    /// Unix timestamp of the block
+    pub block_timestamp: u64,
+    /// Averaging window of the TWAP in seconds, for on-chain TWAP prices
+    pub twap_window_secs: Option<u32>,
+}
+
+/// Length of the candles returned by `get_price_ohlc`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum OhlcInterval {
+    Hourly,
+    #[default]
+    Daily,
+}
+
+impl OhlcInterval {
+    fn secs(self) -> u64 {
+        match self {
+            Self::Hourly => 3600,
+            Self::Daily => 86400,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct GetPriceOhlcInput {
+    /// Token contract address (0x...), ENS name or symbol (e.g., "USDC", "WETH")
     pub token: String,
+    /// Chain name the token address lives on. Defaults to the server's default chain
+    #[serde(default)]
+    pub chain: Option<String>,
+    /// Chain ID the token address lives on. Alternative to `chain`
+    #[serde(default)]
+    pub chain_id: Option<u64>,
+    /// Start of the range (Unix seconds)
+    pub from: u64,
+    /// End of the range (Unix seconds)
+    pub to: u64,
+    /// Candle length: "hourly" or "daily". Defaults to daily
+    #[serde(default)]
+    pub interval: OhlcInterval,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OhlcCandle {
+    /// Start of the candle (Unix seconds)
+    pub timestamp: u64,
+    /// Start of the candle (RFC 3339)
+    pub time: Option<String>,
+    /// First price in the candle, in USD
+    pub open: String,
+    /// Highest price in the candle, in USD
+    pub high: String,
+    /// Lowest price in the candle, in USD
+    pub low: String,
+    /// Last price in the candle, in USD
+    pub close: String,
+    /// Number of price points the candle was built from
+    pub points: usize,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct TokenPriceOutput {
-    /// Token identifier (address or symbol)
+pub struct PriceOhlcOutput {
+    /// Token as given in the input
     pub token: String,
-    /// Token address if available
+    /// Chain the prices were queried for
+    pub chain: String,
+    /// Price source the candles were built from
+    pub source: String,
+    /// Candle length
+    pub interval: OhlcInterval,
+    /// Start of the range (Unix seconds)
+    pub from: u64,
+    /// End of the range (Unix seconds)
+    pub to: u64,
+    /// Candles in time order. Intervals without price data are omitted
+    pub candles: Vec<OhlcCandle>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ReverseResolveInput {
+    /// Address to look up the primary ENS name for
+    pub address: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReverseResolveOutput {
+    /// Address that was looked up
+    pub address: String,
+    /// Primary ENS name, null if none is set or it doesn't resolve back to the address
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct GetPortfolioInput {
+    /// Wallet address or ENS name to query
+    pub wallet_address: String,
+    /// ERC20 token contract addresses or ENS names to include
+    #[serde(default)]
+    pub tokens: Vec<String>,
+    /// Name of a token list from the server configuration (e.g. "stablecoins")
+    #[serde(default)]
+    pub token_list: Option<String>,
+    /// Chain name (e.g. "mainnet", "arbitrum", "base"). Defaults to the server's default chain
+    #[serde(default)]
+    pub chain: Option<String>,
+    /// Chain ID (e.g. 1, 42161, 8453). Alternative to `chain`
+    #[serde(default)]
+    pub chain_id: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PortfolioHolding {
+    /// Token address (null for the native token)
     pub token_address: Option<String>,
-    /// Price in USD
+    /// Token symbol, if the contract exposes one
+    pub symbol: Option<String>,
+    /// Balance as a string with proper decimals
+    pub balance: String,
+    /// Number of decimals
+    pub decimals: u8,
+    /// Raw balance (wei or token units)
+    pub raw_balance: String,
+    /// Price in USD, if available
     pub price_usd: Option<String>,
-    /// Price in ETH
-    pub price_eth: Option<String>,
-    /// Last updated timestamp
-    pub last_updated: Option<String>,
+    /// Balance value in USD, if the price is available
+    pub value_usd: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PortfolioTokenError {
+    /// Token address that couldn't be queried
+    pub token_address: String,
+    /// Reason the token was skipped
+    pub error: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PortfolioOutput {
+    /// Wallet address
+    pub wallet_address: String,
+    /// Chain the balances were queried on
+    pub chain: String,
+    /// Non-zero balances, native token first
+    pub holdings: Vec<PortfolioHolding>,
+    /// Sum of `value_usd` over all priced holdings
+    pub total_value_usd: String,
+    /// Tokens whose balance or metadata couldn't be read
+    pub errors: Vec<PortfolioTokenError>,
+    /// ENS names in the input and the addresses they resolved to
+    pub ens_names: Vec<ResolvedName>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct GetTokenInfoInput {
+    /// Token contract address or ENS name
+    pub token_address: String,
+    /// Chain name (e.g. "mainnet", "arbitrum", "base"). Defaults to the server's default chain
+    #[serde(default)]
+    pub chain: Option<String>,
+    /// Chain ID (e.g. 1, 42161, 8453). Alternative to `chain`
+    #[serde(default)]
+    pub chain_id: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenInfoOutput {
+    /// Token contract address
+    pub token_address: String,
+    /// Chain the token was queried on
+    pub chain: String,
+    /// Whether the address has contract code. All other fields are null if it doesn't
+    pub is_contract: bool,
+    /// Token name (null if the contract doesn't implement `name()`)
+    pub name: Option<String>,
+    /// Token symbol (null if the contract doesn't implement `symbol()`)
+    pub symbol: Option<String>,
+    /// Number of decimals
+    pub decimals: Option<u8>,
+    /// Total supply as a string with proper decimals
+    pub total_supply: Option<String>,
+    /// Raw total supply (token units)
+    pub raw_total_supply: Option<String>,
+    /// ENS names in the input and the addresses they resolved to
+    pub ens_names: Vec<ResolvedName>,
 }
 
+/// Maximum number of tokens accepted by `get_token_prices`
+const MAX_PRICE_BATCH: usize = 100;
+
+/// Maximum number of candles `get_price_ohlc` returns per call
+const MAX_OHLC_CANDLES: u64 = 1000;
+
+/// Tools that sign and broadcast transactions, only listed when the wallet is enabled
+const WALLET_TOOLS: [&str; 2] = ["send_transaction", "execute_swap"];
+
 #[derive(Clone)]
 pub struct EthMcpServer {
-    provider: Arc<Provider<Http>>,
+    chains: Arc<ChainRegistry>,
+    config: Arc<Config>,
+    prices: Arc<PriceSources>,
+    tokens: Arc<TokenRegistry>,
+    /// Signing accounts, `None` unless `wallet.enabled` is set
+    wallet: Option<Arc<Wallet>>,
+    /// Rules transactions must pass before the wallet signs them
+    policy: Arc<Policy>,
     tool_router: ToolRouter<Self>,
 }
 
 #[tool_router]
 impl EthMcpServer {
-    pub fn new(rpc_url: String) -> Result<Self> {
-        let provider = Provider::<Http>::try_from(rpc_url.as_str())
-            .context("Failed to create HTTP provider")?;
-
-        let tool_router = Self::tool_router();
+    pub fn new(chains: ChainRegistry, config: Config) -> Result<Self> {
+        let prices = PriceSources::from_config(&config.prices)?;
+        let tokens = TokenRegistry::from_config(&config.token_registry)?;
+        let wallet = Wallet::from_config(&config.wallet)?;
+        let policy = Policy::from_config(
+            &config.policy,
+            wallet.as_ref().map(Wallet::addresses).unwrap_or_default(),
+        )?;
+
+        let mut tool_router = Self::tool_router();
+        match &wallet {
+            Some(wallet) => info!("Wallet enabled with accounts {:?}", wallet.addresses()),
+            None => {
+                for tool in WALLET_TOOLS {
+                    tool_router.remove_route(tool);
+                }
+            }
+        }
         info!("Tool router initialized");
 
         Ok(Self {
-            provider: Arc::new(provider),
+            chains: Arc::new(chains),
+            config: Arc::new(config),
+            prices: Arc::new(prices),
+            tokens: Arc::new(tokens),
+            wallet: wallet.map(Arc::new),
+            policy: Arc::new(policy),
             tool_router,
         })
     }
 
-    #[tool(description = "Query ETH and ERC20 token balances for a wallet address")]
+    fn wallet(&self) -> Result<&Wallet, McpError> {
+        self.wallet.as_deref().ok_or_else(|| {
+            McpError::invalid_request("The wallet is disabled (set wallet.enabled)", None)
+        })
+    }
+
+    fn network(&self, chain: Option<&str>, chain_id: Option<u64>) -> Result<&Network, McpError> {
+        self.chains
+            .resolve(chain, chain_id)
+            .map_err(|e| McpError::invalid_params(format!("Invalid chain: {e}"), None))
+    }
+
+    /// Parse an address parameter, resolving ENS names on mainnet. Resolved names are
+    /// appended to `ens_names` so they can be echoed in the output
+    async fn resolve_address(
+        &self,
+        field: &str,
+        input: &str,
+        ens_names: &mut Vec<ResolvedName>,
+    ) -> Result<Address, McpError> {
+        if !ens::is_ens_name(input) {
+            return Address::from_str(input.trim())
+                .map_err(|e| McpError::invalid_params(format!("Invalid {field}: {e}"), None));
+        }
+
+        let mainnet = self
+            .chains
+            .mainnet()
+            .map_err(|e| McpError::internal_error(format!("ENS is unavailable: {e:#}"), None))?;
+        let address = ens::resolve_name(&mainnet.provider, input)
+            .await
+            .map_err(|e| {
+                McpError::invalid_params(format!("Failed to resolve {field} {input}: {e:#}"), None)
+            })?;
+
+        ens_names.push(ResolvedName {
+            name: input.to_string(),
+            address: format!("{address:?}"),
+        });
+        Ok(address)
+    }
+
+    /// Look up a token parameter in the token registry. Addresses, the native token and
+    /// unknown symbols give `None`; a symbol shared by several tokens is an error
+    fn registered_token(
+        &self,
+        network: &Network,
+        token: &str,
+    ) -> Result<Option<Address>, McpError> {
+        let chain = network.chain;
+        let token = token.trim();
+        if token.starts_with("0x") || chain.is_native_token(token) {
+            return Ok(None);
+        }
+
+        let registered = self
+            .tokens
+            .resolve_symbol(chain, token)
+            .map_err(|e| McpError::invalid_params(format!("{e:#}"), None))?;
+        Ok(registered.map(|registered| {
+            info!(
+                "Resolved {} on {} to {} {:?} ({} decimals)",
+                token, chain.name, registered.symbol, registered.address, registered.decimals
+            );
+            registered.address
+        }))
+    }
+
+    /// Resolve a token parameter to a hex address: symbols through the token registry, ENS
+    /// names on mainnet. Addresses and the native token symbol are returned unchanged.
+    ///
+    /// The registry is checked first, as some symbols contain dots (e.g. "USDC.e")
+    async fn resolve_token(
+        &self,
+        network: &Network,
+        token: &str,
+        ens_names: &mut Vec<ResolvedName>,
+    ) -> Result<String, McpError> {
+        if let Some(address) = self.registered_token(network, token)? {
+            return Ok(format!("{address:?}"));
+        }
+        if ens::is_ens_name(token) {
+            let address = self.resolve_address("token", token, ens_names).await?;
+            return Ok(format!("{address:?}"));
+        }
+        if token.trim().starts_with("0x") || network.chain.is_native_token(token.trim()) {
+            return Ok(token.to_string());
+        }
+
+        Err(McpError::invalid_params(
+            format!(
+                "Unknown token {} on {}: it is not in any token list, pass the token address instead",
+                token.trim(),
+                network.chain.name
+            ),
+            None,
+        ))
+    }
+
+    /// Parse the token parameter of a price tool. Symbols in the token registry are priced
+    /// by address and ENS names are resolved; other symbols are passed to the price sources
+    /// as-is, so CoinGecko coin IDs (e.g. "bitcoin") still work
+    async fn price_query(&self, network: &Network, token: &str) -> Result<PriceQuery, McpError> {
+        if let Some(address) = self.registered_token(network, token)? {
+            return Ok(PriceQuery::Address(address));
+        }
+
+        let token = if ens::is_ens_name(token) {
+            let address = self
+                .resolve_address("token", token, &mut Vec::new())
+                .await?;
+            format!("{address:?}")
+        } else {
+            token.to_string()
+        };
+        PriceQuery::parse(&token)
+            .map_err(|e| McpError::invalid_params(format!("Invalid token: {e:#}"), None))
+    }
+
+    /// Reject slippage tolerances above `limits.max_slippage_tolerance`
+    fn check_slippage(&self, slippage_tolerance: &str) -> Result<(), McpError> {
+        let slippage = Decimal::from_str(slippage_tolerance).map_err(|e| {
+            McpError::invalid_params(format!("Invalid slippage tolerance: {e}"), None)
+        })?;
+        let max_slippage = self.config.limits.max_slippage_tolerance;
+        if slippage > max_slippage {
+            return Err(McpError::invalid_params(
+                format!("Slippage tolerance {slippage}% exceeds the configured maximum of {max_slippage}%"),
+                None,
+            ));
+        }
+        Ok(())
+    }
+
+    /// Resolve the tokens, sender and recipient of a swap transaction to addresses, and
+    /// check its slippage tolerance
+    async fn resolve_swap_transaction(
+        &self,
+        network: &Network,
+        input: &mut BuildSwapTransactionInput,
+        ens_names: &mut Vec<ResolvedName>,
+    ) -> Result<(), McpError> {
+        input.from_token = self
+            .resolve_token(network, &input.from_token, ens_names)
+            .await?;
+        input.to_token = self
+            .resolve_token(network, &input.to_token, ens_names)
+            .await?;
+        let sender = self
+            .resolve_address("sender", &input.sender, ens_names)
+            .await?;
+        input.sender = format!("{sender:?}");
+        if let Some(recipient) = &input.recipient {
+            let recipient = self
+                .resolve_address("recipient", recipient, ens_names)
+                .await?;
+            input.recipient = Some(format!("{recipient:?}"));
+        }
+        self.check_slippage(&input.slippage_tolerance)
+    }
+
+    /// Resolve the optional `block` / `timestamp` tool parameters to a concrete block
+    async fn resolve_block(
+        &self,
+        network: &Network,
+        block: Option<&str>,
+        timestamp: Option<u64>,
+    ) -> Result<ResolvedBlock, McpError> {
+        let resolved = match (block, timestamp) {
+            (Some(_), Some(_)) => {
+                return Err(McpError::invalid_params(
+                    "Pass either `block` or `timestamp`, not both",
+                    None,
+                ))
+            }
+            (None, Some(timestamp)) => {
+                blocks::find_block_by_timestamp(&network.provider, timestamp).await
+            }
+            (block, None) => {
+                let block_id = blocks::parse_block_id(block.unwrap_or("latest"))
+                    .map_err(|e| McpError::invalid_params(format!("{e:#}"), None))?;
+                blocks::resolve_block(&network.provider, block_id).await
+            }
+        };
+
+        resolved
+            .map_err(|e| McpError::internal_error(format!("Failed to resolve block: {e:#}"), None))
+    }
+
+    #[tool(
+        description = "Query ETH and ERC20 token balances for a wallet address, optionally at a historical block (number, hash, latest/safe/finalized) or Unix timestamp. Returns the block number and hash the balance was computed at"
+    )]
     #[instrument(skip(self))]
     async fn get_balance(
         &self,
@@ -104,29 +654,44 @@ impl EthMcpServer {
     ) -> Result<CallToolResult, McpError> {
         info!("get_balance called with params: {:?}", params.0);
         let input = params.0;
-        let wallet_address = Address::from_str(&input.wallet_address)
-            .map_err(|e| McpError::invalid_params(format!("Invalid wallet address: {e}"), None))?;
+        let network = self.network(input.chain.as_deref(), input.chain_id)?;
+        let mut ens_names = Vec::new();
+        let wallet_address = self
+            .resolve_address("wallet address", &input.wallet_address, &mut ens_names)
+            .await?;
+        let token_address = match &input.token_address {
+            Some(token) if network.chain.is_native_token(token.trim()) => None,
+            Some(token) => Some(self.resolve_token(network, token, &mut ens_names).await?),
+            None => None,
+        };
+
+        let block = self
+            .resolve_block(network, input.block.as_deref(), input.timestamp)
+            .await?;
 
         info!(
-            "Querying balance for wallet: {:?}, token: {:?}",
-            wallet_address, input.token_address
+            "Querying balance for wallet: {:?}, token: {:?}, chain: {}, block: {}",
+            wallet_address, token_address, network.chain.name, block.number
         );
 
-        let result = if let Some(token_address_str) = input.token_address {
+        let mut result = if let Some(token_address_str) = token_address {
             // Query ERC20 token balance
             info!("Querying ERC20 balance");
-            self.get_erc20_balance(wallet_address, token_address_str)
+            self.get_erc20_balance(network, wallet_address, token_address_str, block)
                 .await
                 .map_err(|e| {
-                    McpError::internal_error(format!("Failed to get ERC20 balance: {e}"), None)
+                    McpError::internal_error(format!("Failed to get ERC20 balance: {e:#}"), None)
                 })?
         } else {
             // Query ETH balance
             info!("Querying ETH balance");
-            self.get_eth_balance(wallet_address).await.map_err(|e| {
-                McpError::internal_error(format!("Failed to get ETH balance: {e}"), None)
-            })?
+            self.get_eth_balance(network, wallet_address, block)
+                .await
+                .map_err(|e| {
+                    McpError::internal_error(format!("Failed to get ETH balance: {e:#}"), None)
+                })?
         };
+        result.ens_names = ens_names;
 
         info!("Balance query completed, serializing result");
         let json_result = serde_json::to_string_pretty(&result).map_err(|e| {
@@ -146,11 +711,16 @@ impl EthMcpServer {
     ) -> Result<CallToolResult, McpError> {
         info!("get_token_price called with params: {:?}", params.0);
         let input = params.0;
+        let network = self.network(input.chain.as_deref(), input.chain_id)?;
+        let query = self.price_query(network, &input.token).await?;
 
-        info!("Fetching price for token: {}", input.token);
+        info!(
+            "Fetching price for token: {:?} on {}",
+            query, network.chain.name
+        );
 
-        let result = self.fetch_token_price(&input.token).await.map_err(|e| {
-            McpError::internal_error(format!("Failed to get token price: {e}"), None)
+        let result = self.prices.get_price(&query, network).await.map_err(|e| {
+            McpError::internal_error(format!("Failed to get token price: {e:#}"), None)
         })?;
 
         info!("Price query completed, serializing result");
@@ -162,25 +732,70 @@ impl EthMcpServer {
     }
 
     #[tool(
-        description = "Simulate a token swap on Uniswap V2. Constructs a real transaction and simulates it using eth_call without executing on-chain. Returns estimated output and gas costs."
+        description = "Get current prices in USD and ETH of several tokens (contract addresses or symbols) in one call, with as few upstream requests as possible. Tokens that can't be priced are listed under errors"
     )]
     #[instrument(skip(self))]
-    async fn swap_tokens(&self, params: Parameters<SwapInput>) -> Result<CallToolResult, McpError> {
-        info!("swap_tokens called with params: {:?}", params.0);
+    async fn get_token_prices(
+        &self,
+        params: Parameters<GetTokenPricesInput>,
+    ) -> Result<CallToolResult, McpError> {
+        info!("get_token_prices called with params: {:?}", params.0);
         let input = params.0;
+        let network = self.network(input.chain.as_deref(), input.chain_id)?;
+
+        if input.tokens.is_empty() {
+            return Err(McpError::invalid_params("`tokens` must not be empty", None));
+        }
+        if input.tokens.len() > MAX_PRICE_BATCH {
+            return Err(McpError::invalid_params(
+                format!("At most {MAX_PRICE_BATCH} tokens can be priced per call"),
+                None,
+            ));
+        }
+
+        let mut errors = Vec::new();
+        let mut queries = Vec::new();
+        for token in &input.tokens {
+            let query = self
+                .price_query(network, token)
+                .await
+                .map_err(|e| e.message.to_string());
+            match query {
+                Ok(query) => queries.push((token, query)),
+                Err(error) => errors.push(TokenPriceError {
+                    token: token.clone(),
+                    error,
+                }),
+            }
+        }
 
         info!(
-            "Simulating swap: {} -> {} (amount: {}, slippage: {}%)",
-            input.from_token, input.to_token, input.amount, input.slippage_tolerance
+            "Fetching prices for {} tokens on {}",
+            queries.len(),
+            network.chain.name
         );
 
-        let provider = SwapProvider::new(self.provider.clone());
-        let result = provider
-            .estimate_swap(input)
-            .await
-            .map_err(|e| McpError::internal_error(format!("Failed to estimate swap: {e}"), None))?;
+        let batch: Vec<PriceQuery> = queries.iter().map(|(_, query)| query.clone()).collect();
+        let results = self.prices.get_prices(&batch, network).await;
+
+        let mut prices = Vec::new();
+        for ((token, _), result) in queries.into_iter().zip(results) {
+            match result {
+                Ok(price) => prices.push(price),
+                Err(e) => errors.push(TokenPriceError {
+                    token: token.clone(),
+                    error: format!("{e:#}"),
+                }),
+            }
+        }
 
-        info!("Swap simulation completed, serializing result");
+        let result = TokenPricesOutput {
+            chain: network.chain.name.to_string(),
+            prices,
+            errors,
+        };
+
+        info!("Batch price query completed, serializing result");
         let json_result = serde_json::to_string_pretty(&result).map_err(|e| {
             McpError::internal_error(format!("Error serializing result: {e}"), None)
         })?;
@@ -188,189 +803,714 @@ impl EthMcpServer {
         Ok(CallToolResult::success(vec![Content::text(json_result)]))
     }
 
+    #[tool(
+        description = "Read a Chainlink price feed (e.g. ETH/USD, BTC/USD, USDC/USD) from the built-in per-chain feed registry. Returns the price with its round ID, update time and whether it is stale"
+    )]
     #[instrument(skip(self))]
-    async fn fetch_token_price(&self, token: &str) -> Result<TokenPriceOutput> {
-        let client = reqwest::Client::new();
-        let token_lower = token.to_lowercase();
-
-        // Check if input is an Ethereum address (starts with 0x)
-        let is_address = token_lower.starts_with("0x") && token_lower.len() == 42;
-
-        let result = if is_address {
-            // Query by contract address
-            self.fetch_price_by_address(&client, &token_lower).await?
-        } else if token_lower == "eth" || token_lower == "ethereum" {
-            // Special case for ETH
-            self.fetch_eth_price(&client).await?
-        } else {
-            // Query by symbol
-            self.fetch_price_by_symbol(&client, &token_lower).await?
+    async fn get_oracle_price(
+        &self,
+        params: Parameters<GetOraclePriceInput>,
+    ) -> Result<CallToolResult, McpError> {
+        info!("get_oracle_price called with params: {:?}", params.0);
+        let input = params.0;
+        let network = self.network(input.chain.as_deref(), input.chain_id)?;
+        let chain = network.chain;
+
+        let mut pair = input.pair.trim().to_uppercase();
+        if !pair.contains('/') {
+            pair.push_str("/USD");
+        }
+        let feed = chain.chainlink_feed(&pair).ok_or_else(|| {
+            let available: Vec<_> = chain.chainlink_feeds.iter().map(|feed| feed.pair).collect();
+            McpError::invalid_params(
+                format!(
+                    "No Chainlink feed for {pair} on {} (available: {})",
+                    chain.name,
+                    available.join(", ")
+                ),
+                None,
+            )
+        })?;
+        let feed_address = Address::from_str(feed.address).map_err(|e| {
+            McpError::internal_error(format!("Invalid feed address {}: {e}", feed.address), None)
+        })?;
+
+        let round = chainlink::read_latest_round(&network.provider, chain, feed_address)
+            .await
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to read Chainlink feed: {e:#}"), None)
+            })?;
+        if round.answer <= I256::zero() {
+            return Err(McpError::internal_error(
+                format!(
+                    "Chainlink feed {pair} returned an invalid answer: {}",
+                    round.answer
+                ),
+                None,
+            ));
+        }
+
+        let price = erc20::to_decimal_units(round.answer.into_raw(), round.decimals)
+            .map_err(|e| McpError::internal_error(format!("{e:#}"), None))?;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or_default();
+        let age_secs = now.saturating_sub(round.updated_at);
+        let max_age_secs = input.max_age_secs.unwrap_or(feed.heartbeat_secs);
+        let (base, quote) = feed.pair.split_once('/').unwrap_or((feed.pair, ""));
+
+        let result = OraclePriceOutput {
+            price: TokenPriceOutput {
+                token: base.to_string(),
+                token_address: None,
+                price_usd: (quote == "USD").then(|| price.to_string()),
+                price_eth: (quote == "ETH").then(|| price.to_string()),
+                last_updated: prices::format_timestamp(round.updated_at),
+                source: "chainlink".to_string(),
+            },
+            pair: feed.pair.to_string(),
+            chain: chain.name.to_string(),
+            feed_address: format!("{feed_address:?}"),
+            description: round.description,
+            decimals: round.decimals,
+            answer: round.answer.to_string(),
+            round_id: round.round_id.to_string(),
+            updated_at: round.updated_at,
+            age_secs,
+            max_age_secs,
+            is_stale: age_secs > max_age_secs,
         };
 
-        Ok(result)
+        info!("Oracle price query completed, serializing result");
+        let json_result = serde_json::to_string_pretty(&result).map_err(|e| {
+            McpError::internal_error(format!("Error serializing result: {e}"), None)
+        })?;
+
+        Ok(CallToolResult::success(vec![Content::text(json_result)]))
     }
 
-    async fn fetch_price_by_address(
+    #[tool(
+        description = "Get the price of a token at a past block or Unix timestamp. Uses CoinGecko market data closest to the block time, or with source \"uniswap\" a Uniswap V3 pool TWAP read on-chain at the block. Returns the block the price was computed at"
+    )]
+    #[instrument(skip(self))]
+    async fn get_historical_price(
         &self,
-        client: &reqwest::Client,
-        address: &str,
-    ) -> Result<TokenPriceOutput> {
-        let url = format!(
-            "https://api.coingecko.com/api/v3/simple/token_price/ethereum?contract_addresses={address}&vs_currencies=usd,eth"
-          );
-
-        info!("Fetching price by address from CoinGecko: {}", url);
-
-        let response = client
-            .get(&url)
-            .send()
+        params: Parameters<GetHistoricalPriceInput>,
+    ) -> Result<CallToolResult, McpError> {
+        info!("get_historical_price called with params: {:?}", params.0);
+        let input = params.0;
+        let network = self.network(input.chain.as_deref(), input.chain_id)?;
+        let query = self.price_query(network, &input.token).await?;
+
+        let source = input
+            .source
+            .as_deref()
+            .map(str::trim)
+            .map(str::to_lowercase);
+        if let Some(source) = &source {
+            let configured = self.prices.source_names();
+            if !configured.contains(&source.as_str()) {
+                return Err(McpError::invalid_params(
+                    format!(
+                        "Price source {source} is not configured (configured: {})",
+                        configured.join(", ")
+                    ),
+                    None,
+                ));
+            }
+        }
+        if input.block.is_none() && input.timestamp.is_none() {
+            return Err(McpError::invalid_params(
+                "Pass either `block` or `timestamp`",
+                None,
+            ));
+        }
+        let block = self
+            .resolve_block(network, input.block.as_deref(), input.timestamp)
+            .await?;
+
+        info!(
+            "Fetching price for token: {:?} on {} at block {}",
+            query, network.chain.name, block.number
+        );
+
+        let price = self
+            .prices
+            .get_historical_price(&query, network, &block, source.as_deref())
             .await
-            .context("Failed to send request to CoinGecko")?;
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to get historical price: {e:#}"), None)
+            })?;
+
+        let twap_window_secs =
+            (price.source == "uniswap_v3_twap").then_some(self.config.prices.twap_window_secs);
+        let result = HistoricalPriceOutput {
+            price,
+            chain: network.chain.name.to_string(),
+            block_number: block.number,
+            block_hash: format!("{:?}", block.hash),
+            block_timestamp: block.timestamp,
+            twap_window_secs,
+        };
 
-        if !response.status().is_success() {
-            anyhow::bail!("CoinGecko API returned error: {}", response.status());
+        info!("Historical price query completed, serializing result");
+        let json_result = serde_json::to_string_pretty(&result).map_err(|e| {
+            McpError::internal_error(format!("Error serializing result: {e}"), None)
+        })?;
+
+        Ok(CallToolResult::success(vec![Content::text(json_result)]))
+    }
+
+    #[tool(
+        description = "Get hourly or daily OHLC (open, high, low, close) USD price candles of a token between two Unix timestamps, built from CoinGecko market data"
+    )]
+    #[instrument(skip(self))]
+    async fn get_price_ohlc(
+        &self,
+        params: Parameters<GetPriceOhlcInput>,
+    ) -> Result<CallToolResult, McpError> {
+        info!("get_price_ohlc called with params: {:?}", params.0);
+        let input = params.0;
+        let network = self.network(input.chain.as_deref(), input.chain_id)?;
+        let query = self.price_query(network, &input.token).await?;
+
+        if input.from >= input.to {
+            return Err(McpError::invalid_params("`from` must be before `to`", None));
+        }
+        let interval_secs = input.interval.secs();
+        if (input.to - input.from) / interval_secs >= MAX_OHLC_CANDLES {
+            return Err(McpError::invalid_params(
+                format!(
+                    "At most {MAX_OHLC_CANDLES} candles can be returned per call; narrow the range or use a longer interval"
+                ),
+                None,
+            ));
         }
 
-        let json: serde_json::Value = response
-            .json()
+        info!(
+            "Fetching price history for token: {:?} on {} from {} to {}",
+            query, network.chain.name, input.from, input.to
+        );
+
+        let (points, source) = self
+            .prices
+            .get_price_history(&query, network, input.from, input.to)
             .await
-            .context("Failed to parse CoinGecko response")?;
-
-        let token_data = json
-            .get(address)
-            .and_then(|v| v.as_object())
-            .context("Token not found in CoinGecko response")?;
-
-        let price_usd = token_data
-            .get("usd")
-            .and_then(|v| v.as_f64())
-            .map(|v| format!("{v:.6}"));
-
-        let price_eth = token_data
-            .get("eth")
-            .and_then(|v| v.as_f64())
-            .map(|v| format!("{v:.18}"));
-
-        Ok(TokenPriceOutput {
-            token: address.to_string(),
-            token_address: Some(address.to_string()),
-            price_usd,
-            price_eth,
-            last_updated: None,
-        })
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to get price history: {e:#}"), None)
+            })?;
+
+        let format_price = |price: Decimal| price.normalize().to_string();
+        let candles = prices::ohlc(&points, interval_secs)
+            .into_iter()
+            .map(|candle| OhlcCandle {
+                timestamp: candle.start,
+                time: prices::format_timestamp(candle.start),
+                open: format_price(candle.open),
+                high: format_price(candle.high),
+                low: format_price(candle.low),
+                close: format_price(candle.close),
+                points: candle.points,
+            })
+            .collect();
+
+        let result = PriceOhlcOutput {
+            token: input.token,
+            chain: network.chain.name.to_string(),
+            source: source.to_string(),
+            interval: input.interval,
+            from: input.from,
+            to: input.to,
+            candles,
+        };
+
+        info!("OHLC query completed, serializing result");
+        let json_result = serde_json::to_string_pretty(&result).map_err(|e| {
+            McpError::internal_error(format!("Error serializing result: {e}"), None)
+        })?;
+
+        Ok(CallToolResult::success(vec![Content::text(json_result)]))
     }
 
-    async fn fetch_price_by_symbol(
+    #[tool(
+        description = "Get every non-zero balance of a wallet (native token plus a list of ERC20 tokens or a configured token list) with symbol, decimals, USD value and a USD total"
+    )]
+    #[instrument(skip(self))]
+    async fn get_portfolio(
         &self,
-        client: &reqwest::Client,
-        symbol: &str,
-    ) -> Result<TokenPriceOutput> {
-        // Map common symbols to CoinGecko IDs
-        let coin_id = match symbol {
-            "usdc" => "usd-coin",
-            "usdt" => "tether",
-            "dai" => "dai",
-            "weth" => "weth",
-            "wbtc" => "wrapped-bitcoin",
-            "link" => "chainlink",
-            "uni" => "uniswap",
-            "aave" => "aave",
-            "mkr" => "maker",
-            "comp" => "compound-governance-token",
-            _ => symbol,
-        };
+        params: Parameters<GetPortfolioInput>,
+    ) -> Result<CallToolResult, McpError> {
+        info!("get_portfolio called with params: {:?}", params.0);
+        let input = params.0;
+        let network = self.network(input.chain.as_deref(), input.chain_id)?;
+        let mut ens_names = Vec::new();
+        let wallet_address = self
+            .resolve_address("wallet address", &input.wallet_address, &mut ens_names)
+            .await?;
+
+        let mut token_strs = input.tokens;
+        if let Some(list_name) = &input.token_list {
+            let list = self.config.token_lists.get(list_name).ok_or_else(|| {
+                McpError::invalid_params(format!("Unknown token list: {list_name}"), None)
+            })?;
+            token_strs.extend(list.iter().cloned());
+        }
+        if token_strs.is_empty() {
+            return Err(McpError::invalid_params(
+                "Provide `tokens`, `token_list` or both",
+                None,
+            ));
+        }
+
+        let mut tokens: Vec<Address> = Vec::new();
+        for token in &token_strs {
+            let address = self
+                .resolve_address("token address", token, &mut ens_names)
+                .await?;
+            if !tokens.contains(&address) {
+                tokens.push(address);
+            }
+        }
 
-        let url = format!(
-            "https://api.coingecko.com/api/v3/simple/price?ids={coin_id}&vs_currencies=usd,eth"
+        info!(
+            "Querying portfolio for wallet: {:?}, {} tokens, chain: {}",
+            wallet_address,
+            tokens.len(),
+            network.chain.name
         );
 
-        info!("Fetching price by symbol from CoinGecko: {}", url);
+        let mut result = self
+            .get_portfolio_balances(network, wallet_address, &tokens)
+            .await
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to get portfolio: {e:#}"), None)
+            })?;
+        result.ens_names = ens_names;
+
+        info!("Portfolio query completed, serializing result");
+        let json_result = serde_json::to_string_pretty(&result).map_err(|e| {
+            McpError::internal_error(format!("Error serializing result: {e}"), None)
+        })?;
 
-        let response = client
-            .get(&url)
-            .send()
+        Ok(CallToolResult::success(vec![Content::text(json_result)]))
+    }
+
+    #[tool(
+        description = "Get ERC20 token metadata: name, symbol, decimals, total supply and whether the address is a contract at all. Handles tokens that return name/symbol as bytes32 (e.g. MKR)"
+    )]
+    #[instrument(skip(self))]
+    async fn get_token_info(
+        &self,
+        params: Parameters<GetTokenInfoInput>,
+    ) -> Result<CallToolResult, McpError> {
+        info!("get_token_info called with params: {:?}", params.0);
+        let input = params.0;
+        let network = self.network(input.chain.as_deref(), input.chain_id)?;
+        let mut ens_names = Vec::new();
+        let token_address = self
+            .resolve_address("token address", &input.token_address, &mut ens_names)
+            .await?;
+
+        let mut result = self
+            .get_token_metadata(network, token_address)
             .await
-            .context("Failed to send request to CoinGecko")?;
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to get token info: {e:#}"), None)
+            })?;
+        result.ens_names = ens_names;
 
-        if !response.status().is_success() {
-            anyhow::bail!("CoinGecko API returned error: {}", response.status());
-        }
+        info!("Token info query completed, serializing result");
+        let json_result = serde_json::to_string_pretty(&result).map_err(|e| {
+            McpError::internal_error(format!("Error serializing result: {e}"), None)
+        })?;
 
-        let json: serde_json::Value = response
-            .json()
+        Ok(CallToolResult::success(vec![Content::text(json_result)]))
+    }
+
+    #[tool(
+        description = "Look up the primary ENS name of an address. Only names that resolve back to the address are returned"
+    )]
+    #[instrument(skip(self))]
+    async fn reverse_resolve(
+        &self,
+        params: Parameters<ReverseResolveInput>,
+    ) -> Result<CallToolResult, McpError> {
+        info!("reverse_resolve called with params: {:?}", params.0);
+        let input = params.0;
+        let address = Address::from_str(&input.address)
+            .map_err(|e| McpError::invalid_params(format!("Invalid address: {e}"), None))?;
+        let mainnet = self
+            .chains
+            .mainnet()
+            .map_err(|e| McpError::internal_error(format!("ENS is unavailable: {e:#}"), None))?;
+
+        let name = ens::lookup_address(&mainnet.provider, address)
             .await
-            .context("Failed to parse CoinGecko response")?;
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to reverse resolve address: {e:#}"), None)
+            })?;
 
-        let token_data = json
-            .get(coin_id)
-            .and_then(|v| v.as_object())
-            .context("Token not found in CoinGecko response")?;
+        let result = ReverseResolveOutput {
+            address: format!("{address:?}"),
+            name,
+        };
 
-        let price_usd = token_data
-            .get("usd")
-            .and_then(|v| v.as_f64())
-            .map(|v| format!("{v:.6}"));
+        let json_result = serde_json::to_string_pretty(&result).map_err(|e| {
+            McpError::internal_error(format!("Error serializing result: {e}"), None)
+        })?;
 
-        let price_eth = token_data
-            .get("eth")
-            .and_then(|v| v.as_f64())
-            .map(|v| format!("{v:.18}"));
+        Ok(CallToolResult::success(vec![Content::text(json_result)]))
+    }
 
-        Ok(TokenPriceOutput {
-            token: symbol.to_string(),
-            token_address: None,
-            price_usd,
-            price_eth,
-            last_updated: None,
-        })
+    #[tool(
+        description = "Simulate a token swap on Uniswap V2. Constructs a real transaction and simulates it using eth_call without executing on-chain. Returns estimated output and gas costs. In exact_output mode the amount is what to buy, and the estimated input and maximum input are returned instead."
+    )]
+    #[instrument(skip(self))]
+    async fn swap_tokens(&self, params: Parameters<SwapInput>) -> Result<CallToolResult, McpError> {
+        info!("swap_tokens called with params: {:?}", params.0);
+        let mut input = params.0;
+        let network = self.network(input.chain.as_deref(), input.chain_id)?;
+        let mut ens_names = Vec::new();
+        input.from_token = self
+            .resolve_token(network, &input.from_token, &mut ens_names)
+            .await?;
+        input.to_token = self
+            .resolve_token(network, &input.to_token, &mut ens_names)
+            .await?;
+        if let Some(sender) = &input.sender {
+            let sender = self
+                .resolve_address("sender", sender, &mut ens_names)
+                .await?;
+            input.sender = Some(format!("{sender:?}"));
+        }
+
+        info!(
+            "Simulating swap: {} -> {} (amount: {}, slippage: {}%, chain: {})",
+            input.from_token,
+            input.to_token,
+            input.amount,
+            input.slippage_tolerance,
+            network.chain.name
+        );
+
+        self.check_slippage(&input.slippage_tolerance)?;
+
+        let provider = SwapProvider::new(network.provider.clone(), network.chain)
+            .with_price_impact_warning(self.config.limits.price_impact_warning);
+        let mut result = provider.estimate_swap(input).await.map_err(|e| {
+            McpError::internal_error(format!("Failed to estimate swap: {e:#}"), None)
+        })?;
+        result.ens_names = ens_names;
+
+        info!("Swap simulation completed, serializing result");
+        let json_result = serde_json::to_string_pretty(&result).map_err(|e| {
+            McpError::internal_error(format!("Error serializing result: {e}"), None)
+        })?;
+
+        Ok(CallToolResult::success(vec![Content::text(json_result)]))
+    }
+
+    #[tool(
+        description = "Build an unsigned, ready-to-sign EIP-1559 swap transaction on Uniswap V2 or V3 for a sender (and optional recipient): router address, calldata with a deadline and the minimum output (or maximum input) from the slippage tolerance, value, gas limit from a simulation, fees, nonce and chain ID. Also returns the swap estimate and any approvals to send first."
+    )]
+    #[instrument(skip(self))]
+    async fn build_swap_transaction(
+        &self,
+        params: Parameters<BuildSwapTransactionInput>,
+    ) -> Result<CallToolResult, McpError> {
+        info!("build_swap_transaction called with params: {:?}", params.0);
+        let mut input = params.0;
+        let network = self.network(input.chain.as_deref(), input.chain_id)?;
+        let mut ens_names = Vec::new();
+        self.resolve_swap_transaction(network, &mut input, &mut ens_names)
+            .await?;
+
+        let provider = SwapProvider::new(network.provider.clone(), network.chain)
+            .with_price_impact_warning(self.config.limits.price_impact_warning);
+        let mut result = provider.build_swap_transaction(input).await.map_err(|e| {
+            McpError::internal_error(format!("Failed to build swap transaction: {e:#}"), None)
+        })?;
+        result.ens_names = ens_names;
+
+        let json_result = serde_json::to_string_pretty(&result).map_err(|e| {
+            McpError::internal_error(format!("Error serializing result: {e}"), None)
+        })?;
+
+        Ok(CallToolResult::success(vec![Content::text(json_result)]))
     }
 
-    async fn fetch_eth_price(&self, client: &reqwest::Client) -> Result<TokenPriceOutput> {
-        let url = "https://api.coingecko.com/api/v3/simple/price?ids=ethereum&vs_currencies=usd";
+    #[tool(
+        description = "Sign a transaction with a wallet account and broadcast it with eth_sendRawTransaction. Sends ETH and/or calldata (e.g. an approval from get_allowance) to an address; nonce, EIP-1559 fees and gas limit are filled in. Only available when the wallet is enabled."
+    )]
+    #[instrument(skip(self))]
+    async fn send_transaction(
+        &self,
+        params: Parameters<SendTransactionInput>,
+    ) -> Result<CallToolResult, McpError> {
+        info!("send_transaction called with params: {:?}", params.0);
+        let input = params.0;
+        let wallet = self.wallet()?;
+        let network = self.network(input.chain.as_deref(), input.chain_id)?;
+        let mut ens_names = Vec::new();
 
-        info!("Fetching ETH price from CoinGecko");
+        let from = match &input.from {
+            Some(from) => Some(self.resolve_address("from", from, &mut ens_names).await?),
+            None => None,
+        };
+        let from = wallet
+            .account(from)
+            .map_err(|e| McpError::invalid_params(format!("{e:#}"), None))?;
+        let to = self
+            .resolve_address("to", &input.to, &mut ens_names)
+            .await?;
+        let value = input
+            .value
+            .as_deref()
+            .map(|value| ethers::utils::parse_ether(value.trim()))
+            .transpose()
+            .map_err(|e| McpError::invalid_params(format!("Invalid value: {e}"), None))?
+            .unwrap_or_default();
+        let data = input
+            .data
+            .as_deref()
+            .map(|data| Bytes::from_str(data.trim()))
+            .transpose()
+            .map_err(|e| McpError::invalid_params(format!("Invalid data: {e}"), None))?
+            .unwrap_or_default();
+
+        let transaction = wallet::fill_transaction(
+            &*network.provider,
+            network.chain.id,
+            from,
+            to,
+            value,
+            data,
+            input.gas_limit,
+        )
+        .await
+        .map_err(|e| {
+            McpError::internal_error(format!("Failed to prepare transaction: {e:#}"), None)
+        })?;
 
-        let response = client
-            .get(url)
-            .send()
+        let check = self
+            .transaction_policy_check(network, &transaction)
             .await
-            .context("Failed to send request to CoinGecko")?;
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to check spending policy: {e:#}"), None)
+            })?;
+        let authorization = self.policy.authorize(&check).map_err(|violation| {
+            McpError::invalid_request(format!("Rejected by spending policy: {violation}"), None)
+        })?;
+        let hash = match wallet
+            .send_transaction(&*network.provider, &transaction)
+            .await
+        {
+            Ok(hash) => hash,
+            Err(e) => {
+                // A transaction that may have gone out keeps its spend
+                if !e.may_be_broadcast() {
+                    self.policy.refund(authorization);
+                }
+                return Err(McpError::internal_error(
+                    format!("Failed to send transaction: {e}"),
+                    None,
+                ));
+            }
+        };
 
-        if !response.status().is_success() {
-            anyhow::bail!("CoinGecko API returned error: {}", response.status());
-        }
+        let result = SentTransactionOutput {
+            transaction_hash: format!("{hash:?}"),
+            transaction,
+            chain: network.chain.name.to_string(),
+            ens_names,
+        };
+        let json_result = serde_json::to_string_pretty(&result).map_err(|e| {
+            McpError::internal_error(format!("Error serializing result: {e}"), None)
+        })?;
+
+        Ok(CallToolResult::success(vec![Content::text(json_result)]))
+    }
 
-        let json: serde_json::Value = response
-            .json()
+    #[tool(
+        description = "Build a swap transaction as build_swap_transaction does, sign it with the sender's wallet account and broadcast it. Swaps that fail in simulation, or whose sender doesn't hold the input amount and gas, are not sent. With approve, the approvals the swap needs are sent first, and if sending fails after some went out the error result lists their hashes and the nonce it stopped at; otherwise such swaps are rejected. Only available when the wallet is enabled."
+    )]
+    #[instrument(skip(self))]
+    async fn execute_swap(
+        &self,
+        params: Parameters<ExecuteSwapInput>,
+    ) -> Result<CallToolResult, McpError> {
+        info!("execute_swap called with params: {:?}", params.0);
+        let mut input = params.0;
+        let wallet = self.wallet()?;
+        let network = self.network(input.swap.chain.as_deref(), input.swap.chain_id)?;
+        let mut ens_names = Vec::new();
+        self.resolve_swap_transaction(network, &mut input.swap, &mut ens_names)
+            .await?;
+        let sender = Address::from_str(&input.swap.sender)
+            .map_err(|e| McpError::invalid_params(format!("Invalid sender: {e}"), None))?;
+        wallet
+            .account(Some(sender))
+            .map_err(|e| McpError::invalid_params(format!("{e:#}"), None))?;
+
+        let provider = SwapProvider::new(network.provider.clone(), network.chain)
+            .with_price_impact_warning(self.config.limits.price_impact_warning);
+        let built = provider
+            .build_swap_transaction(input.swap)
             .await
-            .context("Failed to parse CoinGecko response")?;
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to build swap transaction: {e:#}"), None)
+            })?;
 
-        let token_data = json
-            .get("ethereum")
-            .and_then(|v| v.as_object())
-            .context("ETH price not found in CoinGecko response")?;
+        // Checked before anything is signed, approvals included
+        let policy_error = |e: anyhow::Error| {
+            McpError::internal_error(format!("Failed to check spending policy: {e:#}"), None)
+        };
+        let mut checks = if input.approve {
+            PolicyCheck::for_swap_approvals(&built).map_err(policy_error)?
+        } else {
+            Vec::new()
+        };
+        checks.push(PolicyCheck::for_swap(&built).map_err(policy_error)?);
+        let authorizations = self.policy.authorize_all(&checks).map_err(|violation| {
+            McpError::invalid_request(format!("Rejected by spending policy: {violation}"), None)
+        })?;
 
-        let price_usd = token_data
-            .get("usd")
-            .and_then(|v| v.as_f64())
-            .map(|v| format!("{v:.6}"));
+        let mut result = match self.send_swap(network, wallet, built, input.approve).await {
+            Ok(result) => result,
+            Err(e) => {
+                // The checks are in sending order, approvals first, and only the spends of
+                // transactions that didn't go out are given back
+                for authorization in authorizations.into_iter().skip(e.sent) {
+                    self.policy.refund(authorization);
+                }
+                // Once approvals went out, the caller needs them and the nonce sending
+                // stopped at, not just the error
+                let failed_nonce = match e.failed_nonce {
+                    Some(nonce) if !e.approval_hashes.is_empty() => nonce,
+                    _ => {
+                        return Err(McpError::internal_error(
+                            format!("Failed to execute swap: {e}"),
+                            None,
+                        ))
+                    }
+                };
+                let partial = PartialSwapOutput {
+                    error: format!("Failed to execute swap: {:#}", e.error),
+                    approval_hashes: e.approval_hashes,
+                    failed_nonce,
+                    chain: network.chain.name.to_string(),
+                };
+                let json_result = serde_json::to_string_pretty(&partial).map_err(|e| {
+                    McpError::internal_error(format!("Error serializing result: {e}"), None)
+                })?;
+                return Ok(CallToolResult::error(vec![Content::text(json_result)]));
+            }
+        };
+        result.built.ens_names = ens_names;
 
-        Ok(TokenPriceOutput {
-            token: "ETH".to_string(),
-            token_address: None,
-            price_usd,
-            price_eth: Some("1.0".to_string()),
-            last_updated: None,
-        })
+        let json_result = serde_json::to_string_pretty(&result).map_err(|e| {
+            McpError::internal_error(format!("Error serializing result: {e}"), None)
+        })?;
+
+        Ok(CallToolResult::success(vec![Content::text(json_result)]))
+    }
+
+    #[tool(
+        description = "Compare swap quotes across Uniswap V2 and every V3 fee tier (0.01%, 0.05%, 0.3%, 1%). Quotes all candidates in parallel and ranks them by the output received net of gas costs, listing every candidate with its route, output, gas and net output."
+    )]
+    #[instrument(skip(self))]
+    async fn compare_swap_quotes(
+        &self,
+        params: Parameters<CompareSwapQuotesInput>,
+    ) -> Result<CallToolResult, McpError> {
+        info!("compare_swap_quotes called with params: {:?}", params.0);
+        let mut input = params.0;
+        let network = self.network(input.chain.as_deref(), input.chain_id)?;
+        let mut ens_names = Vec::new();
+        input.from_token = self
+            .resolve_token(network, &input.from_token, &mut ens_names)
+            .await?;
+        input.to_token = self
+            .resolve_token(network, &input.to_token, &mut ens_names)
+            .await?;
+
+        let provider = SwapProvider::new(network.provider.clone(), network.chain);
+        let mut result = provider.compare_quotes(input).await.map_err(|e| {
+            McpError::internal_error(format!("Failed to compare swap quotes: {e:#}"), None)
+        })?;
+        result.ens_names = ens_names;
+
+        let json_result = serde_json::to_string_pretty(&result).map_err(|e| {
+            McpError::internal_error(format!("Error serializing result: {e}"), None)
+        })?;
+
+        Ok(CallToolResult::success(vec![Content::text(json_result)]))
     }
 
+    #[tool(
+        description = "Check the ERC20 allowance of an owner to a spender (an address, or \"uniswap_v2\" / \"uniswap_v3\" for the Uniswap routers), both directly and through Permit2. Given an amount, says whether each allowance covers it and returns the unsigned approve transactions that are missing."
+    )]
     #[instrument(skip(self))]
-    async fn get_eth_balance(&self, address: Address) -> Result<BalanceOutput> {
+    async fn get_allowance(
+        &self,
+        params: Parameters<GetAllowanceInput>,
+    ) -> Result<CallToolResult, McpError> {
+        info!("get_allowance called with params: {:?}", params.0);
+        let input = params.0;
+        let network = self.network(input.chain.as_deref(), input.chain_id)?;
+        let mut ens_names = Vec::new();
+        let owner = self
+            .resolve_address("owner", &input.owner, &mut ens_names)
+            .await?;
+        let token = self
+            .resolve_token(network, &input.token, &mut ens_names)
+            .await?;
+        if network.chain.is_native_token(token.trim()) {
+            return Err(McpError::invalid_params(
+                format!("{} is the native token and has no allowance", token.trim()),
+                None,
+            ));
+        }
+        let token = Address::from_str(token.trim())
+            .map_err(|e| McpError::invalid_params(format!("Invalid token: {e}"), None))?;
+        let spender = match allowance::router_alias(&input.spender, network.chain)
+            .map_err(|e| McpError::internal_error(format!("Invalid router address: {e:#}"), None))?
+        {
+            Some(router) => router,
+            None => {
+                self.resolve_address("spender", &input.spender, &mut ens_names)
+                    .await?
+            }
+        };
+
+        let mut result = self
+            .get_allowances(network, owner, token, spender, input.amount.as_deref())
+            .await
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to get allowance: {e:#}"), None)
+            })?;
+        result.ens_names = ens_names;
+
+        let json_result = serde_json::to_string_pretty(&result).map_err(|e| {
+            McpError::internal_error(format!("Error serializing result: {e}"), None)
+        })?;
+
+        Ok(CallToolResult::success(vec![Content::text(json_result)]))
+    }
+
+    #[instrument(skip(self, network), fields(chain = network.chain.name))]
+    async fn get_eth_balance(
+        &self,
+        network: &Network,
+        address: Address,
+        block: ResolvedBlock,
+    ) -> Result<BalanceOutput> {
         info!("Querying ETH balance for address: {:?}", address);
 
-        let balance = self
+        let balance = network
             .provider
-            .get_balance(address, None)
+            .get_balance(address, Some(block.id()))
             .await
             .context("Failed to query ETH balance")?;
 
@@ -384,48 +1524,20 @@ impl EthMcpServer {
             balance: format!("{eth_balance:.18}"),
             decimals: 18,
             raw_balance: balance.to_string(),
+            chain: network.chain.name.to_string(),
+            block_number: block.number,
+            block_hash: format!("{:?}", block.hash),
+            ens_names: Vec::new(),
         })
     }
 
-    fn create_balance_of_function() -> Function {
-        Function {
-            name: "balanceOf".to_string(),
-            inputs: vec![Param {
-                name: "owner".to_string(),
-                kind: ParamType::Address,
-                internal_type: None,
-            }],
-            outputs: vec![Param {
-                name: "".to_string(),
-                kind: ParamType::Uint(256),
-                internal_type: None,
-            }],
-            #[allow(deprecated)]
-            constant: None,
-            state_mutability: StateMutability::View,
-        }
-    }
-
-    fn create_decimals_function() -> Function {
-        Function {
-            name: "decimals".to_string(),
-            inputs: vec![],
-            outputs: vec![Param {
-                name: "".to_string(),
-                kind: ParamType::Uint(8),
-                internal_type: None,
-            }],
-            #[allow(deprecated)]
-            constant: None,
-            state_mutability: StateMutability::View,
-        }
-    }
-
-    #[instrument(skip(self))]
+    #[instrument(skip(self, network), fields(chain = network.chain.name))]
     async fn get_erc20_balance(
         &self,
+        network: &Network,
         wallet_address: Address,
         token_address_str: String,
+        block: ResolvedBlock,
     ) -> Result<BalanceOutput> {
         let token_address =
             Address::from_str(&token_address_str).context("Invalid token contract address")?;
@@ -436,72 +1548,460 @@ impl EthMcpServer {
         );
 
         // Create ERC20 functions
-        let balance_of = Self::create_balance_of_function();
-        let decimals_fn = Self::create_decimals_function();
-
-        // Call balanceOf
-        let balance_input = balance_of
-            .encode_input(&[Token::Address(wallet_address)])
-            .context("Failed to encode balanceOf call")?;
-
-        let balance_tx = TransactionRequest::new()
-            .to(token_address)
-            .data(balance_input);
-        let balance_call_result = self
-            .provider
-            .call(&balance_tx.into(), None)
-            .await
+        let balance_of = erc20::balance_of_function();
+        let decimals_fn = erc20::decimals_function();
+
+        // Batch balanceOf and decimals into a single round trip
+        let mut multicall = Multicall::new(&network.provider, network.chain)?.at_block(block.id());
+        multicall.add_call(
+            token_address,
+            &balance_of,
+            &[Token::Address(wallet_address)],
+        )?;
+        multicall.add_call(token_address, &decimals_fn, &[])?;
+
+        let mut results = multicall.call().await?.into_iter();
+        let balance_tokens = results
+            .next()
+            .context("Missing balanceOf result")?
             .context("Failed to call balanceOf")?;
+        let decimals_tokens = results
+            .next()
+            .context("Missing decimals result")?
+            .context("Failed to call decimals")?;
 
-        // Call decimals
-        let decimals_input = decimals_fn
-            .encode_input(&[])
-            .context("Failed to encode decimals call")?;
+        let balance = erc20::decode_uint(balance_tokens)?;
+        let decimals = erc20::decode_decimals(decimals_tokens)?;
 
-        let decimals_tx = TransactionRequest::new()
-            .to(token_address)
-            .data(decimals_input);
-        let decimals_call_result = self
+        let token_balance = erc20::to_decimal_units(balance, decimals)?;
+
+        Ok(BalanceOutput {
+            wallet_address: format!("{wallet_address:?}"),
+            token_address: Some(token_address_str),
+            balance: format!("{:.prec$}", token_balance, prec = decimals as usize),
+            decimals,
+            raw_balance: balance.to_string(),
+            chain: network.chain.name.to_string(),
+            block_number: block.number,
+            block_hash: format!("{:?}", block.hash),
+            ens_names: Vec::new(),
+        })
+    }
+
+    #[instrument(skip(self, network), fields(chain = network.chain.name))]
+    async fn get_allowances(
+        &self,
+        network: &Network,
+        owner: Address,
+        token: Address,
+        spender: Address,
+        amount: Option<&str>,
+    ) -> Result<AllowanceOutput> {
+        let decimals = erc20::get_decimals(&network.provider, network.chain, &[token])
+            .await?
+            .first()
+            .copied()
+            .context("Missing decimals result")?;
+        let required = amount
+            .map(|amount| {
+                ethers::utils::parse_units(amount.trim(), u32::from(decimals))
+                    .map(U256::from)
+                    .with_context(|| format!("Invalid amount {amount}"))
+            })
+            .transpose()?;
+
+        let (direct, permit2) = futures::join!(
+            allowance::check_allowance(
+                &network.provider,
+                network.chain,
+                token,
+                owner,
+                spender,
+                decimals,
+                required,
+            ),
+            allowance::check_permit2_allowance(
+                &network.provider,
+                network.chain,
+                token,
+                owner,
+                spender,
+                decimals,
+                required,
+            ),
+        );
+        let permit2 = permit2
+            .map_err(|e| warn!("Failed to read Permit2 allowance: {:#}", e))
+            .ok();
+
+        Ok(AllowanceOutput {
+            owner: format!("{owner:?}"),
+            token: format!("{token:?}"),
+            spender: format!("{spender:?}"),
+            decimals,
+            direct: direct?,
+            permit2,
+            chain: network.chain.name.to_string(),
+            ens_names: Vec::new(),
+        })
+    }
+
+    /// What `transaction` spends and calls, for the spending policy. ERC20 `transfer` and
+    /// `approve` calls and Permit2 approvals without ETH are checked as spends of their token;
+    /// other calldata as an opaque call to `transaction.to`
+    async fn transaction_policy_check(
+        &self,
+        network: &Network,
+        transaction: &UnsignedTransaction,
+    ) -> Result<PolicyCheck> {
+        let token_call = match policy::decode_token_call(&transaction.data) {
+            Some(call) if transaction.value.is_zero() => {
+                let token = call.token(transaction.to);
+                let decimals =
+                    erc20::get_decimals(&network.provider, network.chain, &[token]).await?[0];
+                Some((call, decimals))
+            }
+            _ => None,
+        };
+        PolicyCheck::for_transaction(network.chain.id, transaction, token_call)
+    }
+
+    /// Send a built swap transaction, after the approvals it needs if `approve` is set. The
+    /// approvals and the swap take consecutive nonces from the sender's pending nonce, and
+    /// sending stops at the first failure, which reports the transactions already sent
+    #[instrument(skip_all, fields(chain = network.chain.name))]
+    async fn send_swap(
+        &self,
+        network: &Network,
+        wallet: &Wallet,
+        mut built: SwapTransactionOutput,
+        approve: bool,
+    ) -> Result<ExecuteSwapOutput, SwapSendError> {
+        if built.swap.simulation != SimulationStatus::Full {
+            return Err(anyhow::anyhow!(
+                "The swap failed in simulation, so it wasn't sent: {}",
+                built
+                    .swap
+                    .simulation_error
+                    .as_deref()
+                    .unwrap_or("unknown error")
+            )
+            .into());
+        }
+
+        let approvals = built
+            .swap
+            .allowance
+            .as_ref()
+            .map(|allowance| allowance.approvals.clone())
+            .unwrap_or_default();
+        if !approvals.is_empty() && !approve {
+            return Err(anyhow::anyhow!(
+                "{} needs {} approval(s) before the swap; pass `approve: true` to send them first",
+                built.sender,
+                approvals.len()
+            )
+            .into());
+        }
+
+        let approval_calls = approvals
+            .iter()
+            .map(|approval| {
+                Ok((
+                    Address::from_str(&approval.to).context("Invalid approval target")?,
+                    Bytes::from_str(&approval.data).context("Invalid approval calldata")?,
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let mut transactions =
+            wallet::fill_swap_transactions(&*network.provider, &built.transaction, &approval_calls)
+                .await?;
+        self.check_swap_funds(network, &built, &transactions)
+            .await?;
+
+        let swap = transactions.pop().context("Missing swap transaction")?;
+        let mut approval_hashes = Vec::new();
+        let failed =
+            |approval_hashes: Vec<String>, transaction: &UnsignedTransaction, e: SendError| {
+                SwapSendError {
+                    sent: approval_hashes.len() + usize::from(e.may_be_broadcast()),
+                    approval_hashes,
+                    failed_nonce: Some(transaction.nonce.as_u64()),
+                    error: e.into(),
+                }
+            };
+        for (transaction, approval) in transactions.iter().zip(&approvals) {
+            info!("Sending approval: {}", approval.description);
+            match wallet
+                .send_transaction(&*network.provider, transaction)
+                .await
+            {
+                Ok(hash) => approval_hashes.push(format!("{hash:?}")),
+                Err(e) => return Err(failed(approval_hashes, transaction, e)),
+            }
+        }
+
+        let hash = match wallet.send_transaction(&*network.provider, &swap).await {
+            Ok(hash) => hash,
+            Err(e) => return Err(failed(approval_hashes, &swap, e)),
+        };
+        built.nonce = swap.nonce.as_u64();
+        built.transaction = swap;
+
+        Ok(ExecuteSwapOutput {
+            approval_hashes,
+            transaction_hash: format!("{hash:?}"),
+            built,
+        })
+    }
+
+    /// Refuse a swap the sender can't pay for. The simulation grants the sender its input
+    /// through state overrides, so it passes whatever the sender holds: this reads the real
+    /// balance of the input token, and of the native token for the value and maximum gas
+    /// cost of `transactions`
+    async fn check_swap_funds(
+        &self,
+        network: &Network,
+        built: &SwapTransactionOutput,
+        transactions: &[UnsignedTransaction],
+    ) -> Result<()> {
+        let sender = built.transaction.from;
+        let native_needed = transactions.iter().fold(U256::zero(), |total, tx| {
+            total + tx.value + tx.gas * tx.max_fee_per_gas
+        });
+        let native_balance = network
             .provider
-            .call(&decimals_tx.into(), None)
+            .get_balance(sender, None)
             .await
-            .context("Failed to call decimals")?;
+            .context("Failed to get the sender's balance")?;
+        if native_balance < native_needed {
+            anyhow::bail!(
+                "{sender:?} holds {} {symbol} but the swap needs up to {} {symbol} for its value and gas",
+                erc20::to_decimal_units(native_balance, 18)?,
+                erc20::to_decimal_units(native_needed, 18)?,
+                symbol = network.chain.native_symbol
+            );
+        }
 
-        // Decode balanceOf result
-        let balance_tokens = balance_of
-            .decode_output(&balance_call_result)
-            .context("Failed to decode balanceOf result")?;
-        let balance_token = balance_tokens.first().context("No balance in result")?;
+        // Anything but an address is the native token, already counted in the value
+        let Ok(token) = Address::from_str(&built.swap.from_token) else {
+            return Ok(());
+        };
+        let amount = built
+            .swap
+            .maximum_input
+            .as_deref()
+            .unwrap_or(&built.swap.input_amount);
+
+        let mut multicall = Multicall::new(&network.provider, network.chain)?;
+        multicall.add_call(
+            token,
+            &erc20::balance_of_function(),
+            &[Token::Address(sender)],
+        )?;
+        multicall.add_call(token, &erc20::decimals_function(), &[])?;
+        let mut results = multicall.call().await?.into_iter();
+        let balance = erc20::decode_uint(
+            results
+                .next()
+                .context("Missing balanceOf result")?
+                .context("Failed to call balanceOf")?,
+        )?;
+        let decimals = erc20::decode_decimals(
+            results
+                .next()
+                .context("Missing decimals result")?
+                .context("Failed to call decimals")?,
+        )?;
+
+        let needed: U256 = ethers::utils::parse_units(amount, u32::from(decimals))
+            .with_context(|| format!("Invalid swap input amount: {amount}"))?
+            .into();
+        if balance < needed {
+            anyhow::bail!(
+                "{sender:?} holds {} of {token:?} but the swap needs {amount}",
+                erc20::to_decimal_units(balance, decimals)?
+            );
+        }
+        Ok(())
+    }
 
-        let balance = match balance_token {
-            Token::Uint(val) => *val,
-            _ => anyhow::bail!("Unexpected balance token type"),
+    #[instrument(skip(self, network), fields(chain = network.chain.name))]
+    async fn get_token_metadata(
+        &self,
+        network: &Network,
+        token_address: Address,
+    ) -> Result<TokenInfoOutput> {
+        let mut output = TokenInfoOutput {
+            token_address: format!("{token_address:?}"),
+            chain: network.chain.name.to_string(),
+            is_contract: false,
+            name: None,
+            symbol: None,
+            decimals: None,
+            total_supply: None,
+            raw_total_supply: None,
+            ens_names: Vec::new(),
         };
 
-        // Decode decimals result
-        let decimals_tokens = decimals_fn
-            .decode_output(&decimals_call_result)
-            .context("Failed to decode decimals result")?;
-        let decimals_token = decimals_tokens.first().context("No decimals in result")?;
+        if !erc20::is_contract(&network.provider, token_address).await? {
+            info!("{:?} has no contract code", token_address);
+            return Ok(output);
+        }
 
-        let decimals = match decimals_token {
-            Token::Uint(val) => val.to_string().parse::<u64>()? as u8,
-            _ => anyhow::bail!("Unexpected decimals token type"),
-        };
+        let info = erc20::get_token_info(&network.provider, network.chain, token_address).await?;
+        let total_supply = erc20::to_decimal_units(info.total_supply, info.decimals)?;
+
+        output.is_contract = true;
+        output.name = info.name;
+        output.symbol = info.symbol;
+        output.decimals = Some(info.decimals);
+        output.total_supply = Some(format!(
+            "{:.prec$}",
+            total_supply,
+            prec = info.decimals as usize
+        ));
+        output.raw_total_supply = Some(info.total_supply.to_string());
+        Ok(output)
+    }
 
-        // Convert to decimal with proper precision
-        let decimals_u32 = u32::from(decimals);
-        let divisor = Decimal::from(10u64.pow(decimals_u32));
-        let token_balance = Decimal::from_str(&balance.to_string())
-            .context("Failed to convert balance to Decimal")?
-            / divisor;
+    #[instrument(skip(self, network, tokens), fields(chain = network.chain.name))]
+    async fn get_portfolio_balances(
+        &self,
+        network: &Network,
+        wallet_address: Address,
+        tokens: &[Address],
+    ) -> Result<PortfolioOutput> {
+        let balance_of = erc20::balance_of_function();
+        let decimals_fn = erc20::decimals_function();
+        let symbol_fn = erc20::symbol_function();
+        // Multicall3 `getEthBalance`, so the native balance joins the same batch as token reads
+        let get_eth_balance = abi::view_function(
+            "getEthBalance",
+            vec![ParamType::Address],
+            vec![ParamType::Uint(256)],
+        );
+        let multicall_address = Address::from_str(network.chain.contracts.multicall3)?;
+
+        // Native balance plus balanceOf / decimals / symbol for every token in one round trip
+        let mut multicall = Multicall::new(&network.provider, network.chain)?;
+        multicall.add_call(
+            multicall_address,
+            &get_eth_balance,
+            &[Token::Address(wallet_address)],
+        )?;
+        for token in tokens {
+            multicall.add_call(*token, &balance_of, &[Token::Address(wallet_address)])?;
+            multicall.add_call(*token, &decimals_fn, &[])?;
+            multicall.add_call(*token, &symbol_fn, &[])?;
+        }
+        // Raw results, since some tokens return their symbol as bytes32
+        let mut results = multicall.call_raw().await?.into_iter();
+
+        let native_balance = results
+            .next()
+            .context("Missing getEthBalance result")?
+            .and_then(|data| Ok(get_eth_balance.decode_output(&data)?))
+            .and_then(erc20::decode_uint)
+            .context("Failed to query native balance")?;
+
+        // (token address, symbol, raw balance, decimals)
+        let mut balances: Vec<(Option<Address>, Option<String>, U256, u8)> = Vec::new();
+        let mut errors = Vec::new();
+
+        if !native_balance.is_zero() {
+            balances.push((
+                None,
+                Some(network.chain.native_symbol.to_string()),
+                native_balance,
+                18,
+            ));
+        }
 
-        Ok(BalanceOutput {
+        for token in tokens {
+            let balance = results.next().context("Missing balanceOf result")?;
+            let decimals = results.next().context("Missing decimals result")?;
+            let symbol = results.next().context("Missing symbol result")?;
+
+            let token_balance = balance
+                .and_then(|data| Ok(balance_of.decode_output(&data)?))
+                .and_then(erc20::decode_uint)
+                .context("Failed to call balanceOf")
+                .and_then(|balance| {
+                    let decimals = decimals
+                        .and_then(|data| Ok(decimals_fn.decode_output(&data)?))
+                        .and_then(erc20::decode_decimals)
+                        .context("Failed to call decimals")?;
+                    // Balances beyond the Decimal range, e.g. of spam tokens, are reported
+                    // as errors of their token rather than failing the whole portfolio
+                    erc20::to_decimal_units(balance, decimals)
+                        .with_context(|| format!("Balance {balance} is too large"))?;
+                    Ok((balance, decimals))
+                });
+
+            match token_balance {
+                Ok((balance, _)) if balance.is_zero() => {}
+                Ok((balance, decimals)) => {
+                    let symbol = symbol
+                        .and_then(|data| erc20::decode_string_or_bytes32(&data))
+                        .ok();
+                    balances.push((Some(*token), symbol, balance, decimals));
+                }
+                Err(e) => errors.push(PortfolioTokenError {
+                    token_address: format!("{token:?}"),
+                    error: format!("{e:#}"),
+                }),
+            }
+        }
+
+        // Native balances are valued at the price of the wrapped native token
+        let weth =
+            Address::from_str(network.chain.contracts.weth).context("Invalid WETH address")?;
+        let queries: Vec<PriceQuery> = balances
+            .iter()
+            .map(|(token, _, _, _)| PriceQuery::Address(token.unwrap_or(weth)))
+            .collect();
+        let prices = self.prices.get_prices(&queries, network).await;
+
+        let mut holdings = Vec::new();
+        let mut total_value_usd = Decimal::ZERO;
+        for ((token, symbol, raw_balance, decimals), price) in balances.into_iter().zip(prices) {
+            // Checked above for tokens, and native balances fit
+            let balance = erc20::to_decimal_units(raw_balance, decimals)?;
+
+            let price_usd = match price {
+                Ok(price) => price.price_usd,
+                Err(e) => {
+                    warn!("Failed to get price for {:?}: {:#}", token, e);
+                    None
+                }
+            };
+            let value_usd = price_usd
+                .as_deref()
+                .and_then(|price| Decimal::from_str(price).ok())
+                .and_then(|price| price.checked_mul(balance));
+            if let Some(value) = value_usd {
+                total_value_usd = total_value_usd.saturating_add(value);
+            }
+
+            holdings.push(PortfolioHolding {
+                token_address: token.map(|token| format!("{token:?}")),
+                symbol,
+                balance: format!("{:.prec$}", balance, prec = decimals as usize),
+                decimals,
+                raw_balance: raw_balance.to_string(),
+                price_usd,
+                value_usd: value_usd.map(|value| format!("{:.2}", value.round_dp(2))),
+            });
+        }
+
+        Ok(PortfolioOutput {
             wallet_address: format!("{wallet_address:?}"),
-            token_address: Some(token_address_str),
-            balance: format!("{:.prec$}", token_balance, prec = decimals_u32 as usize),
-            decimals,
-            raw_balance: balance.to_string(),
+            chain: network.chain.name.to_string(),
+            holdings,
+            total_value_usd: format!("{:.2}", total_value_usd.round_dp(2)),
+            errors,
+            ens_names: Vec::new(),
         })
     }
 }
@@ -24,11 +24,45 @@ use std::str::FromStr;
 use std::sync::Arc;
 use tracing::{info, instrument, warn};
 
-pub const UNISWAP_V2_ROUTER: &str = "0x7a250d5630B4cF539739dF2C5dAcb4c659F2488D";
-pub const UNISWAP_V3_ROUTER: &str = "0xE592427A0AEce92De3Edee1F18E0157C05861564";
-pub const UNISWAP_V3_QUOTER_V2: &str = "0x61fFE014bA17989E743c5F6cB21bF9697530B21e";
-pub const UNISWAP_V3_QUOTER: &str = "0xb27308f9F90D607463bb33eA1BeBb41C27CE5AB6"; // Old Quoter as fallback
-pub const WETH_ADDRESS: &str = "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2";
+use crate::allowance::{self, AllowanceCheck};
+use crate::chains::{Chain, V3RouterKind};
+use crate::ens::ResolvedName;
+use crate::erc20;
+use crate::multicall::Multicall;
+use crate::rpc::RpcProvider;
+
+mod impact;
+mod overrides;
+mod routing;
+mod transaction;
+
+use routing::{Quote, QuotedRoute, Route};
+
+/// Swaps are simulated from this address when no sender is given. Any address works, as
+/// the balance and allowance it needs are granted through state overrides
+const PLACEHOLDER_SENDER: &str = "0xd8dA6BF26964aF9D7eEd9e03E53415D37aA96045";
+
+/// Deadline of simulated swaps, which never expire
+const NO_DEADLINE: u64 = u64::MAX;
+
+/// V3 fee tiers tried when no pool fee is specified
+pub const V3_FEE_TIERS: [u32; 4] = [100, 500, 3000, 10000];
+
+/// QuoterV2 calls per Multicall3 batch in route searches. Every quote runs the swap and
+/// can use several hundred thousand gas, so one batch of all candidate routes would
+/// exceed the `eth_call` gas cap of many RPC providers
+const QUOTES_PER_MULTICALL: usize = 8;
+
+/// Approximate gas of a V2 swap through one pair, including the transaction's base cost.
+/// `getAmountsOut` has no gas estimate, so quote comparisons cost V2 routes with these
+const V2_SWAP_GAS: u64 = 120_000;
+/// Approximate gas of each further pair on a V2 route
+const V2_HOP_GAS: u64 = 60_000;
+/// Gas of a V3 swap besides its pools (transaction base cost and router), added to the
+/// QuoterV2 estimate, which only covers the pools
+const V3_SWAP_OVERHEAD_GAS: u64 = 50_000;
+/// Approximate gas of one V3 pool, for quotes without a QuoterV2 estimate
+const V3_HOP_GAS: u64 = 80_000;
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, schemars::JsonSchema, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
@@ -39,23 +73,153 @@ pub enum UniswapVersion {
     V3,
 }
 
+/// Which side of a swap is fixed
+#[derive(
+    Debug, Clone, Copy, Default, Serialize, Deserialize, schemars::JsonSchema, PartialEq, Eq,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum SwapMode {
+    /// `amount` is the exact input; slippage bounds the minimum output
+    #[default]
+    ExactInput,
+    /// `amount` is the exact output; slippage bounds the maximum input
+    ExactOutput,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
 pub struct SwapInput {
-    /// Source token address (use "ETH" for native ETH)
+    /// Source token address, ENS name or symbol (e.g. "USDC"; use "ETH" for native ETH)
     pub from_token: String,
-    /// Destination token address (use "ETH" for native ETH)
+    /// Destination token address, ENS name or symbol (e.g. "USDC"; use "ETH" for native ETH)
     pub to_token: String,
-    /// Amount to swap (in human-readable format, e.g., "1.0")
+    /// Amount to swap (in human-readable format, e.g., "1.0"): the amount of `from_token` sold,
+    /// or the amount of `to_token` bought in exact-output mode
+    pub amount: String,
+    /// Slippage tolerance as percentage (e.g., "0.5" for 0.5%)
+    pub slippage_tolerance: String,
+
+    /// Uniswap version to use (V2 or V3). If not specified, defaults to V2
+    #[serde(default)]
+    pub version: Option<UniswapVersion>,
+    /// "exact_input" (sell `amount`) or "exact_output" (buy `amount`). Defaults to
+    /// "exact_input"
+    #[serde(default)]
+    pub mode: Option<SwapMode>,
+    /// Pool fee for V3 swaps (100, 500, 3000, or 10000). Every hop of the route uses this fee
+    /// tier; if not specified, all tiers are tried. Ignored for V2
+    #[serde(default)]
+    pub pool_fee: Option<u32>,
+    /// Chain name (e.g. "mainnet", "arbitrum", "base"). Defaults to the server's default chain
+    #[serde(default)]
+    pub chain: Option<String>,
+    /// Chain ID (e.g. 1, 42161, 8453). Alternative to `chain`
+    #[serde(default)]
+    pub chain_id: Option<u64>,
+    /// Address or ENS name the swap is simulated from and pays out to. Its token balance
+    /// and router allowance are granted through state overrides, so any address can be
+    /// simulated. Defaults to a placeholder address
+    #[serde(default)]
+    pub sender: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct BuildSwapTransactionInput {
+    /// Source token address, ENS name or symbol (e.g. "USDC"; use "ETH" for native ETH)
+    pub from_token: String,
+    /// Destination token address, ENS name or symbol (e.g. "USDC"; use "ETH" for native ETH)
+    pub to_token: String,
+    /// Amount to swap (in human-readable format, e.g., "1.0"): the amount of `from_token` sold,
+    /// or the amount of `to_token` bought in exact-output mode
     pub amount: String,
     /// Slippage tolerance as percentage (e.g., "0.5" for 0.5%)
     pub slippage_tolerance: String,
+    /// Address or ENS name that signs and sends the transaction
+    pub sender: String,
+    /// Address or ENS name the output is paid to. Defaults to the sender
+    #[serde(default)]
+    pub recipient: Option<String>,
+    /// Seconds from now after which the router rejects the swap. Defaults to 30 minutes
+    #[serde(default)]
+    pub deadline_secs: Option<u64>,
 
     /// Uniswap version to use (V2 or V3). If not specified, defaults to V2
     #[serde(default)]
     pub version: Option<UniswapVersion>,
-    /// Pool fee for V3 swaps (500, 3000, or 10000). Required for V3, ignored for V2
+    /// "exact_input" (sell `amount`) or "exact_output" (buy `amount`). Defaults to
+    /// "exact_input"
+    #[serde(default)]
+    pub mode: Option<SwapMode>,
+    /// Pool fee for V3 swaps (100, 500, 3000, or 10000). Every hop of the route uses this fee
+    /// tier; if not specified, all tiers are tried. Ignored for V2
     #[serde(default)]
     pub pool_fee: Option<u32>,
+    /// Chain name (e.g. "mainnet", "arbitrum", "base"). Defaults to the server's default chain
+    #[serde(default)]
+    pub chain: Option<String>,
+    /// Chain ID (e.g. 1, 42161, 8453). Alternative to `chain`
+    #[serde(default)]
+    pub chain_id: Option<u64>,
+}
+
+/// An unsigned EIP-1559 transaction, with quantities hex-encoded as in JSON-RPC
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UnsignedTransaction {
+    /// Transaction type, always 2 (EIP-1559)
+    #[serde(rename = "type")]
+    pub tx_type: U64,
+    pub chain_id: U64,
+    pub from: Address,
+    pub to: Address,
+    pub nonce: U256,
+    /// Gas limit
+    pub gas: U256,
+    pub max_fee_per_gas: U256,
+    pub max_priority_fee_per_gas: U256,
+    /// ETH sent, in wei
+    pub value: U256,
+    pub data: Bytes,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SwapTransactionOutput {
+    /// Unsigned EIP-1559 transaction, ready to sign and send
+    pub transaction: UnsignedTransaction,
+    /// Address the transaction is sent from
+    pub sender: String,
+    /// Address the output is paid to
+    pub recipient: String,
+    /// Unix time (seconds) after which the router rejects the swap
+    pub deadline: u64,
+    /// Gas limit: the simulated gas plus a safety margin
+    pub gas_limit: String,
+    /// Maximum fee per gas, in gwei
+    pub max_fee_per_gas_gwei: String,
+    /// Maximum priority fee per gas, in gwei
+    pub max_priority_fee_per_gas_gwei: String,
+    /// Most the transaction can cost in gas (gas limit times the maximum fee), in ETH
+    pub max_gas_cost_eth: String,
+    /// Nonce of the transaction
+    pub nonce: u64,
+    /// Chain ID the transaction is signed for
+    pub chain_id: u64,
+    /// Reasons the transaction may fail or be unfavourable, such as missing approvals
+    pub warnings: Vec<String>,
+    /// Estimate of the swap the transaction executes, including the minimum output or
+    /// maximum input it enforces and the approvals it needs
+    pub swap: SwapOutput,
+    /// ENS names in the input and the addresses they resolved to
+    pub ens_names: Vec<ResolvedName>,
+}
+
+/// How a swap estimate was obtained
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SimulationStatus {
+    /// The swap transaction itself was simulated with `eth_call`
+    Full,
+    /// The simulation failed, so the amounts come from the router or quoter quote
+    QuoteOnly,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -64,568 +228,1040 @@ pub struct SwapOutput {
     pub from_token: String,
     /// Destination token address
     pub to_token: String,
-    /// Input amount (in human-readable format)
+    /// Input amount (in human-readable format), estimated in exact-output mode
     pub input_amount: String,
-    /// Estimated output amount (in human-readable format)
+    /// Estimated output amount (in human-readable format), the requested amount in
+    /// exact-output mode
     pub estimated_output: String,
-    /// Minimum output amount considering slippage
-    pub minimum_output: String,
+    /// Minimum output amount considering slippage (exact-input mode)
+    pub minimum_output: Option<String>,
+    /// Maximum input amount considering slippage (exact-output mode)
+    pub maximum_input: Option<String>,
     /// Slippage tolerance percentage
     pub slippage_tolerance: String,
+    /// Which side of the swap is fixed
+    pub mode: SwapMode,
+    /// Whether the amounts come from a full simulation or only from a quote
+    pub simulation: SimulationStatus,
+    /// Why the simulation failed, for quote-only estimates
+    pub simulation_error: Option<String>,
     /// Estimated gas cost in wei
     pub estimated_gas: String,
     /// Estimated gas cost in ETH
     pub estimated_gas_eth: String,
-    /// Price impact percentage (if calculable)
+    /// Price impact percentage against the mid price of the route's pools, excluding pool
+    /// fees (if calculable)
     pub price_impact: Option<String>,
+    /// Set when the price impact exceeds the configured warning threshold
+    pub price_impact_warning: Option<String>,
     /// Whether the swap involves ETH
     pub involves_eth: bool,
     /// Uniswap version used for this swap
     pub version: String,
+    /// Chain the swap was simulated on
+    pub chain: String,
+    /// Pools the swap is routed through, in order
+    pub route: Vec<RouteHop>,
+    /// Best quote of every V3 fee tier that was searched (V3 only)
+    pub fee_tiers: Option<Vec<FeeTierQuote>>,
+    /// The sender's allowance to the router for the input token and the approvals needed
+    /// before the swap (only with a sender, for token input)
+    pub allowance: Option<AllowanceCheck>,
+    /// ENS names in the input and the addresses they resolved to
+    pub ens_names: Vec<ResolvedName>,
+}
+
+/// One pool on a swap route
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RouteHop {
+    /// Token sold to the pool (WETH when swapping from ETH)
+    pub token_in: String,
+    /// Token bought from the pool (WETH when swapping to ETH)
+    pub token_out: String,
+    /// Pool fee tier (V3 only)
+    pub fee: Option<u32>,
+}
+
+/// Best V3 route whose pools all use one fee tier
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeeTierQuote {
+    /// Pool fee tier in hundredths of a basis point (100 = 0.01%, 3000 = 0.3%)
+    pub fee: u32,
+    /// Pools of the best route in this tier
+    pub route: Vec<RouteHop>,
+    /// Expected output (in human-readable format, exact-input mode)
+    pub estimated_output: Option<String>,
+    /// Expected input (in human-readable format, exact-output mode)
+    pub estimated_input: Option<String>,
+    /// Gas used by the swap, as estimated by QuoterV2
+    pub estimated_gas: Option<String>,
+    /// Expected output minus the gas cost valued in the output token (exact-input mode)
+    pub net_output: Option<String>,
+    /// Expected input plus the gas cost valued in the input token (exact-output mode)
+    pub net_input: Option<String>,
+    /// Why no route in this tier could be quoted
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct CompareSwapQuotesInput {
+    /// Source token address, ENS name or symbol (e.g. "USDC"; use "ETH" for native ETH)
+    pub from_token: String,
+    /// Destination token address, ENS name or symbol (e.g. "USDC"; use "ETH" for native ETH)
+    pub to_token: String,
+    /// Amount to swap (in human-readable format, e.g., "1.0")
+    pub amount: String,
+    /// Chain name (e.g. "mainnet", "arbitrum", "base"). Defaults to the server's default chain
+    #[serde(default)]
+    pub chain: Option<String>,
+    /// Chain ID (e.g. 1, 42161, 8453). Alternative to `chain`
+    #[serde(default)]
+    pub chain_id: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SwapQuoteComparison {
+    /// Source token address
+    pub from_token: String,
+    /// Destination token address
+    pub to_token: String,
+    /// Input amount (in human-readable format)
+    pub input_amount: String,
+    /// Chain the quotes were taken on
+    pub chain: String,
+    /// Gas price the gas costs are based on, in wei
+    pub gas_price: String,
+    /// "net_output", or "estimated_output" if gas couldn't be valued in the output token
+    pub ranked_by: String,
+    /// V2 and every V3 fee tier, best first. Candidates that couldn't be quoted come last
+    pub quotes: Vec<ComparedQuote>,
+    /// ENS names in the input and the addresses they resolved to
+    pub ens_names: Vec<ResolvedName>,
+}
+
+/// One candidate of a quote comparison
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ComparedQuote {
+    /// Position in the ranking, starting at 1. `None` if the candidate couldn't be quoted
+    pub rank: Option<usize>,
+    /// Uniswap version
+    pub version: String,
+    /// V3 fee tier shared by every pool of the route
+    pub fee: Option<u32>,
+    /// Pools of the best route of this candidate
+    pub route: Vec<RouteHop>,
+    /// Expected output (in human-readable format)
+    pub estimated_output: Option<String>,
+    /// Approximate gas of the swap
+    pub estimated_gas: Option<String>,
+    /// Approximate gas cost in ETH
+    pub estimated_gas_eth: Option<String>,
+    /// Expected output minus the gas cost valued in the output token
+    pub net_output: Option<String>,
+    /// Why this candidate couldn't be quoted
+    pub error: Option<String>,
+}
+
+/// The tokens and input amount of a swap, resolved on chain
+struct SwapPair {
+    /// Token sold, WETH when swapping from ETH
+    token_in: Address,
+    /// Token bought, WETH when swapping to ETH
+    token_out: Address,
+    from_is_eth: bool,
+    to_is_eth: bool,
+    from_decimals: u8,
+    to_decimals: u8,
+    /// The exact input in the smallest unit of `token_in`, or the exact output in the
+    /// smallest unit of `token_out` in exact-output mode
+    amount: U256,
+}
+
+/// Outcome of [`SwapProvider::best_v3_route`]
+struct V3RouteSearch {
+    route: Route,
+    quote: Quote,
+    /// Best route of every fee tier that was searched
+    fee_tiers: Vec<(u32, QuotedRoute)>,
+}
+
+/// Who sends a swap transaction, who receives its output, and until when it is valid
+#[derive(Debug, Clone, Copy)]
+struct SwapParties {
+    sender: Address,
+    recipient: Address,
+    /// Unix time (seconds) after which the router rejects the swap
+    deadline: U256,
+}
+
+/// A swap estimate together with the transaction it was simulated with
+struct PreparedSwap {
+    output: SwapOutput,
+    tx: TransactionRequest,
+    /// Gas estimate of `tx`, `None` if it couldn't be estimated
+    gas_estimate: Option<U256>,
 }
 
 pub struct SwapProvider {
-    provider: Arc<Provider<Http>>,
+    provider: Arc<RpcProvider>,
+    chain: &'static Chain,
+    /// Price impact percentage above which swap estimates carry a warning
+    price_impact_warning: Option<Decimal>,
 }
 
 impl SwapProvider {
-    pub fn new(provider: Arc<Provider<Http>>) -> Self {
-        Self { provider }
+    pub fn new(provider: Arc<RpcProvider>, chain: &'static Chain) -> Self {
+        Self {
+            provider,
+            chain,
+            price_impact_warning: None,
+        }
+    }
+
+    /// Warn in swap estimates whose price impact exceeds `threshold` percent
+    pub fn with_price_impact_warning(mut self, threshold: Decimal) -> Self {
+        self.price_impact_warning = Some(threshold);
+        self
     }
 
     #[instrument(skip(self))]
     pub async fn estimate_swap(&self, input: SwapInput) -> Result<SwapOutput> {
+        let sender = parse_sender(input.sender.as_deref())?;
+        let parties = SwapParties {
+            sender,
+            recipient: sender,
+            deadline: U256::from(NO_DEADLINE),
+        };
+        Ok(self.prepare_swap(input, parties).await?.output)
+    }
+
+    /// Route, build and simulate the swap transaction of `input` between `parties`
+    async fn prepare_swap(&self, input: SwapInput, parties: SwapParties) -> Result<PreparedSwap> {
         let version = input.version.unwrap_or(UniswapVersion::V2);
         info!(
-            "Estimating swap: {} -> {} using Uniswap {:?}",
-            input.from_token, input.to_token, version
+            "Estimating swap: {} -> {} using Uniswap {:?} on {}",
+            input.from_token, input.to_token, version, self.chain.name
         );
 
         match version {
-            UniswapVersion::V2 => self.estimate_swap_v2(input).await,
-            UniswapVersion::V3 => self.estimate_swap_v3(input).await,
+            UniswapVersion::V2 => self.prepare_swap_v2(input, parties).await,
+            UniswapVersion::V3 => self.prepare_swap_v3(input, parties).await,
         }
     }
 
+    /// Quote the swap on V2 and on every V3 fee tier concurrently, and rank the candidates
+    /// by output net of gas. Gas is approximated per route (see [`V2_SWAP_GAS`] and
+    /// [`V3_SWAP_OVERHEAD_GAS`]) and valued in the output token at [`SwapProvider::weth_value`]
     #[instrument(skip(self))]
-    async fn estimate_swap_v2(&self, input: SwapInput) -> Result<SwapOutput> {
-        info!("Using Uniswap V2 for swap estimation");
+    pub async fn compare_quotes(
+        &self,
+        input: CompareSwapQuotesInput,
+    ) -> Result<SwapQuoteComparison> {
+        let pair = self
+            .resolve_pair(
+                &input.from_token,
+                &input.to_token,
+                &input.amount,
+                SwapMode::ExactInput,
+            )
+            .await?;
+        let (gas_price, weth_value) =
+            futures::try_join!(self.gas_price(), self.weth_value(pair.token_out))?;
+
+        let (v2, v3) = futures::join!(
+            self.best_v2_route(
+                pair.token_in,
+                pair.token_out,
+                pair.amount,
+                SwapMode::ExactInput
+            ),
+            self.best_v3_route(
+                pair.token_in,
+                pair.token_out,
+                None,
+                pair.amount,
+                SwapMode::ExactInput,
+                gas_price,
+                weth_value,
+            ),
+        );
 
-        let from_token = normalize_token_address(&input.from_token)?;
-        let to_token = normalize_token_address(&input.to_token)?;
-        let slippage = parse_slippage(&input.slippage_tolerance)?;
+        // Every candidate with the gas of its whole swap, so V2 and V3 compare fairly
+        let mut candidates: Vec<(UniswapVersion, Option<u32>, QuotedRoute)> = vec![(
+            UniswapVersion::V2,
+            None,
+            v2.map(|(route, amount)| {
+                let hops = (route.tokens.len() as u64).saturating_sub(1);
+                let gas = V2_SWAP_GAS + V2_HOP_GAS * hops.saturating_sub(1);
+                let quote = Quote {
+                    amount,
+                    gas_estimate: Some(U256::from(gas)),
+                    net_amount: None,
+                };
+                (route, quote)
+            }),
+        )];
+        match v3 {
+            Ok(search) => candidates.extend(search.fee_tiers.into_iter().map(|(fee, best)| {
+                let best = best.map(|(route, mut quote)| {
+                    let pool_gas = quote.gas_estimate.unwrap_or_else(|| {
+                        U256::from(V3_HOP_GAS * (route.tokens.len() as u64 - 1))
+                    });
+                    quote.gas_estimate = Some(pool_gas + V3_SWAP_OVERHEAD_GAS);
+                    (route, quote)
+                });
+                (UniswapVersion::V3, Some(fee), best)
+            })),
+            Err(e) => candidates.extend(
+                V3_FEE_TIERS
+                    .iter()
+                    .map(|&fee| (UniswapVersion::V3, Some(fee), Err(anyhow::anyhow!("{e:#}")))),
+            ),
+        }
 
-        let from_is_eth = input.from_token.to_lowercase() == "eth";
-        let to_is_eth = input.to_token.to_lowercase() == "eth";
+        for (_, _, best) in &mut candidates {
+            if let (Ok((_, quote)), Some(weth_value)) = (best, weth_value) {
+                let gas = quote.gas_estimate.unwrap_or_default();
+                quote.net_amount = Some(
+                    quote
+                        .amount
+                        .saturating_sub(gas_cost_in(gas, gas_price, weth_value)),
+                );
+            }
+        }
 
-        let from_token_decimals = if from_is_eth {
-            18
-        } else {
-            let from_token_addr = Address::from_str(&from_token)?;
-            self.get_token_decimals(from_token_addr).await.unwrap_or(18)
-        };
+        // Quoted candidates first, best score first; the sort is stable, so ties and
+        // failures keep V2-then-V3 order
+        candidates.sort_by_key(|(_, _, best)| {
+            std::cmp::Reverse(best.as_ref().ok().map(|(_, quote)| quote.score()))
+        });
+
+        let decimals = pair.to_decimals;
+        let quotes = candidates
+            .into_iter()
+            .enumerate()
+            .map(|(i, (version, fee, best))| {
+                let version = format!("{version:?}");
+                Ok(match best {
+                    Ok((route, quote)) => {
+                        let gas = quote.gas_estimate.unwrap_or_default();
+                        ComparedQuote {
+                            rank: Some(i + 1),
+                            version,
+                            fee,
+                            route: route.hops(),
+                            estimated_output: Some(format_amount(quote.amount, decimals)?),
+                            estimated_gas: Some(gas.to_string()),
+                            estimated_gas_eth: Some(format_amount(gas * gas_price, 18)?),
+                            net_output: quote
+                                .net_amount
+                                .map(|net| format_amount(net, decimals))
+                                .transpose()?,
+                            error: None,
+                        }
+                    }
+                    Err(e) => ComparedQuote {
+                        rank: None,
+                        version,
+                        fee,
+                        route: Vec::new(),
+                        estimated_output: None,
+                        estimated_gas: None,
+                        estimated_gas_eth: None,
+                        net_output: None,
+                        error: Some(format!("{e:#}")),
+                    },
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        if let Some(best) = quotes.first().filter(|quote| quote.rank.is_some()) {
+            info!(
+                "Best quote: {} fee {:?} with net output {:?}",
+                best.version, best.fee, best.net_output
+            );
+        }
 
-        let amount = parse_amount(&input.amount, from_token_decimals)?;
-
-        let path = if from_is_eth && !to_is_eth {
-            vec![
-                Address::from_str(WETH_ADDRESS)?,
-                Address::from_str(&to_token)?,
-            ]
-        } else if !from_is_eth && to_is_eth {
-            vec![
-                Address::from_str(&from_token)?,
-                Address::from_str(WETH_ADDRESS)?,
-            ]
-        } else if !from_is_eth && !to_is_eth {
-            vec![
-                Address::from_str(&from_token)?,
-                Address::from_str(&to_token)?,
-            ]
-        } else {
-            anyhow::bail!("ETH to ETH swap is not supported");
-        };
+        Ok(SwapQuoteComparison {
+            from_token: input.from_token,
+            to_token: input.to_token,
+            input_amount: input.amount,
+            chain: self.chain.name.to_string(),
+            gas_price: gas_price.to_string(),
+            ranked_by: if weth_value.is_some() {
+                "net_output"
+            } else {
+                "estimated_output"
+            }
+            .to_string(),
+            quotes,
+            ens_names: Vec::new(),
+        })
+    }
 
-        let router_address = Address::from_str(UNISWAP_V2_ROUTER)?;
+    #[instrument(skip(self))]
+    async fn prepare_swap_v2(
+        &self,
+        input: SwapInput,
+        parties: SwapParties,
+    ) -> Result<PreparedSwap> {
+        info!("Using Uniswap V2 for swap estimation");
 
-        let expected_output = self
-            .get_v2_expected_output(&path, amount)
+        let mode = input.mode.unwrap_or_default();
+        let slippage = parse_slippage(&input.slippage_tolerance)?;
+        let SwapParties {
+            sender,
+            recipient,
+            deadline,
+        } = parties;
+        let SwapPair {
+            token_in,
+            token_out,
+            from_is_eth,
+            to_is_eth,
+            from_decimals,
+            to_decimals,
+            amount,
+        } = self
+            .resolve_pair(&input.from_token, &input.to_token, &input.amount, mode)
+            .await?;
+
+        let router_address = Address::from_str(self.chain.contracts.uniswap_v2_router)?;
+
+        let (route, expected_amount) = self
+            .best_v2_route(token_in, token_out, amount, mode)
             .await
-            .context("Failed to get expected output from V2")?;
-
-        let amount_out_min = calculate_min_output(expected_output, slippage)?;
-
-        let (swap_fn, call_data, _value) = if from_is_eth && !to_is_eth {
-            prepare_v2_swap_exact_eth_for_tokens(&path, amount, amount_out_min, Address::zero())?
-        } else if !from_is_eth && to_is_eth {
-            prepare_v2_swap_exact_tokens_for_eth(&path, amount, amount_out_min, Address::zero())?
-        } else {
-            prepare_v2_swap_exact_tokens_for_tokens(&path, amount, amount_out_min, Address::zero())?
+            .context("Failed to get expected amount from V2")?;
+        info!("Routing V2 swap through {}", route);
+        let path = route.tokens.clone();
+
+        let (swap_fn, call_data, value, limit) = match mode {
+            SwapMode::ExactInput => {
+                let amount_out_min = calculate_min_output(expected_amount, slippage)?;
+                let (swap_fn, call_data, value) = if from_is_eth && !to_is_eth {
+                    prepare_v2_swap_exact_eth_for_tokens(
+                        &path,
+                        amount,
+                        amount_out_min,
+                        recipient,
+                        deadline,
+                    )?
+                } else if !from_is_eth && to_is_eth {
+                    prepare_v2_swap_exact_tokens_for_eth(
+                        &path,
+                        amount,
+                        amount_out_min,
+                        recipient,
+                        deadline,
+                    )?
+                } else {
+                    prepare_v2_swap_exact_tokens_for_tokens(
+                        &path,
+                        amount,
+                        amount_out_min,
+                        recipient,
+                        deadline,
+                    )?
+                };
+                (swap_fn, call_data, value, amount_out_min)
+            }
+            SwapMode::ExactOutput => {
+                let amount_in_max = calculate_max_input(expected_amount, slippage)?;
+                let (swap_fn, call_data, value) = if from_is_eth && !to_is_eth {
+                    prepare_v2_swap_eth_for_exact_tokens(
+                        &path,
+                        amount,
+                        amount_in_max,
+                        recipient,
+                        deadline,
+                    )?
+                } else if !from_is_eth && to_is_eth {
+                    prepare_v2_swap_tokens_for_exact_eth(
+                        &path,
+                        amount,
+                        amount_in_max,
+                        recipient,
+                        deadline,
+                    )?
+                } else {
+                    prepare_v2_swap_tokens_for_exact_tokens(
+                        &path,
+                        amount,
+                        amount_in_max,
+                        recipient,
+                        deadline,
+                    )?
+                };
+                (swap_fn, call_data, value, amount_in_max)
+            }
         };
 
-        // Use a dummy address for simulation (eth_call doesn't require real balance)
-        // Using a well-known address that likely has some balance for better simulation
-        let dummy_from_address = Address::from_str("0xd8dA6BF26964aF9D7eEd9e03E53415D37aA96045")?;
+        let max_amount_in = match mode {
+            SwapMode::ExactInput => amount,
+            SwapMode::ExactOutput => limit,
+        };
 
         let mut tx_request = TransactionRequest::new()
             .to(router_address)
-            .from(dummy_from_address)
+            .from(sender)
             .data(call_data.clone());
 
         if from_is_eth {
-            tx_request = tx_request.value(amount);
+            tx_request = tx_request.value(value);
         }
 
         warn!(
             "Simulating V2 swap: to={:?}, from={:?}, data_len={}, value={:?}",
             router_address,
-            dummy_from_address,
+            sender,
             call_data.len(),
-            if from_is_eth { Some(amount) } else { None }
+            if from_is_eth { Some(value) } else { None }
         );
 
-        // Try to simulate the swap, but if it fails (e.g., due to approval or balance checks),
-        // fall back to using the expected output from getAmountsOut
-        let actual_output = match self.provider.call(&tx_request.clone().into(), None).await {
-            Ok(call_result) => match decode_v2_swap_result(&swap_fn, &call_result) {
-                Ok(output) => output,
-                Err(e) => {
-                    warn!(
-                        "Failed to decode V2 swap result: {}, using expected output",
-                        e
-                    );
-                    expected_output
-                }
-            },
-            Err(e) => {
-                warn!(
-                    "V2 swap simulation call failed: {}, using expected output from getAmountsOut",
-                    e
-                );
-                // Use the expected output from getAmountsOut as fallback
-                expected_output
-            }
-        };
-
-        // Try to estimate gas, but if it fails (e.g., due to transaction revert),
-        // use a reasonable default gas estimate
-        let gas_estimate = match self
-            .provider
-            .estimate_gas(&tx_request.clone().into(), None)
-            .await
+        // Simulate the swap with the sender granted its input, and if it still fails,
+        // fall back to the expected amount from the router quote
+        let simulation = self
+            .simulate_swap(
+                tx_request.clone().into(),
+                sender,
+                router_address,
+                (!from_is_eth).then_some(token_in),
+                max_amount_in,
+            )
+            .await;
+        let (actual_amount, simulation_status, simulation_error) = match simulation
+            .output
+            .and_then(|result| decode_v2_swap_result(&swap_fn, &result, mode))
         {
-            Ok(gas) => gas,
+            Ok(amount) => (amount, SimulationStatus::Full, None),
             Err(e) => {
                 warn!(
-                    "Failed to estimate gas for V2 swap: {}, using default gas estimate",
+                    "V2 swap simulation failed: {:#}, using expected amount from the router quote",
                     e
                 );
-                // Use default gas estimates for Uniswap V2 swaps
-                // V2 swaps typically use 100k-200k gas
-                U256::from(150_000u64)
+                (
+                    expected_amount,
+                    SimulationStatus::QuoteOnly,
+                    Some(format!("{e:#}")),
+                )
             }
         };
 
-        let gas_price = self
-            .provider
-            .get_gas_price()
-            .await
-            .context("Failed to get gas price")?;
-
-        let gas_cost_wei = gas_estimate * gas_price;
-        let gas_cost_eth = Decimal::from_str(&gas_cost_wei.to_string())
-            .context("Failed to convert gas cost to Decimal")?
-            / Decimal::from(1_000_000_000_000_000_000u64);
-
-        let to_token_decimals = if to_is_eth {
-            18
-        } else {
-            self.get_token_decimals(Address::from_str(&to_token)?)
-                .await
-                .unwrap_or(18)
-        };
+        let price_impact = self
+            .route_price_impact(&route, UniswapVersion::V2, mode, amount, actual_amount)
+            .await;
+        let allowance = self
+            .sender_allowance(
+                input.sender.is_some().then_some(sender),
+                (!from_is_eth).then_some(token_in),
+                router_address,
+                from_decimals,
+                max_amount_in,
+            )
+            .await;
 
-        let output_decimal = Decimal::from_str(&actual_output.to_string())
-            .context("Failed to convert output to Decimal")?
-            / Decimal::from(10u64.pow(u32::from(to_token_decimals)));
+        // If gas can't be estimated (e.g., due to transaction revert), use a reasonable
+        // default: V2 swaps typically use 100k-200k gas
+        let gas_estimate = simulation.gas_estimate.unwrap_or_else(|| {
+            warn!("Using default gas estimate for V2 swap");
+            U256::from(150_000u64)
+        });
 
-        let min_output_decimal = Decimal::from_str(&amount_out_min.to_string())
-            .context("Failed to convert min output to Decimal")?
-            / Decimal::from(10u64.pow(u32::from(to_token_decimals)));
+        let gas_price = self.gas_price().await?;
 
-        Ok(SwapOutput {
+        let mut output = SwapOutput {
             from_token: input.from_token,
             to_token: input.to_token,
-            input_amount: input.amount,
-            estimated_output: format!(
-                "{:.prec$}",
-                output_decimal,
-                prec = to_token_decimals as usize
-            ),
-            minimum_output: format!(
-                "{:.prec$}",
-                min_output_decimal,
-                prec = to_token_decimals as usize
-            ),
+            input_amount: input.amount.clone(),
+            estimated_output: input.amount,
+            minimum_output: None,
+            maximum_input: None,
             slippage_tolerance: input.slippage_tolerance,
+            mode,
+            simulation: simulation_status,
+            simulation_error,
             estimated_gas: gas_estimate.to_string(),
-            estimated_gas_eth: format!("{gas_cost_eth:.18}"),
-            price_impact: None,
+            estimated_gas_eth: format_amount(gas_estimate * gas_price, 18)?,
+            price_impact: price_impact.map(|impact| impact.to_string()),
+            price_impact_warning: price_impact.and_then(|impact| self.impact_warning(impact)),
             involves_eth: from_is_eth || to_is_eth,
             version: "V2".to_string(),
+            chain: self.chain.name.to_string(),
+            route: route.hops(),
+            fee_tiers: None,
+            allowance,
+            ens_names: Vec::new(),
+        };
+        set_swap_amounts(
+            &mut output,
+            actual_amount,
+            limit,
+            from_decimals,
+            to_decimals,
+        )?;
+        Ok(PreparedSwap {
+            output,
+            tx: tx_request,
+            gas_estimate: simulation.gas_estimate,
         })
     }
 
     #[instrument(skip(self))]
-    async fn estimate_swap_v3(&self, input: SwapInput) -> Result<SwapOutput> {
+    async fn prepare_swap_v3(
+        &self,
+        input: SwapInput,
+        parties: SwapParties,
+    ) -> Result<PreparedSwap> {
         info!("Using Uniswap V3 for swap estimation");
 
-        let pool_fee = input.pool_fee.unwrap_or(3000);
-        if !matches!(pool_fee, 500 | 3000 | 10000) {
-            anyhow::bail!("Pool fee must be 500, 3000, or 10000");
+        if input
+            .pool_fee
+            .is_some_and(|fee| !V3_FEE_TIERS.contains(&fee))
+        {
+            anyhow::bail!("Pool fee must be 100, 500, 3000, or 10000");
         }
 
-        let from_token = normalize_token_address(&input.from_token)?;
-        let to_token = normalize_token_address(&input.to_token)?;
+        let mode = input.mode.unwrap_or_default();
         let slippage = parse_slippage(&input.slippage_tolerance)?;
-
-        let from_is_eth = input.from_token.to_lowercase() == "eth";
-        let to_is_eth = input.to_token.to_lowercase() == "eth";
-
-        let from_token_decimals = if from_is_eth {
-            18
-        } else {
-            let from_token_addr = Address::from_str(&from_token)?;
-            self.get_token_decimals(from_token_addr).await.unwrap_or(18)
-        };
-
-        let amount = parse_amount(&input.amount, from_token_decimals)?;
-
-        // For V3, use actual token addresses (WETH for ETH)
-        let token_in = if from_is_eth {
-            Address::from_str(WETH_ADDRESS)?
-        } else {
-            Address::from_str(&from_token)?
-        };
-
-        let token_out = if to_is_eth {
-            Address::from_str(WETH_ADDRESS)?
-        } else {
-            Address::from_str(&to_token)?
+        let SwapParties {
+            sender,
+            recipient,
+            deadline,
+        } = parties;
+        let SwapPair {
+            token_in,
+            token_out,
+            from_is_eth,
+            to_is_eth,
+            from_decimals,
+            to_decimals,
+            amount,
+        } = self
+            .resolve_pair(&input.from_token, &input.to_token, &input.amount, mode)
+            .await?;
+        let weth_address = Address::from_str(self.chain.contracts.weth)?;
+
+        let router_address = Address::from_str(self.chain.contracts.uniswap_v3_router)?;
+        let router_kind = self.chain.contracts.uniswap_v3_router_kind;
+
+        // Gas is valued in the token the quotes are in: the output, or the input for
+        // exact-output swaps
+        let quoted_token = match mode {
+            SwapMode::ExactInput => token_out,
+            SwapMode::ExactOutput => token_in,
         };
-
-        let router_address = Address::from_str(UNISWAP_V3_ROUTER)?;
-
-        let expected_output = self
-            .get_v3_expected_output(token_in, token_out, pool_fee, amount, false)
-            .await
-            .context("Failed to get expected output from V3")?;
-
-        let amount_out_min = calculate_min_output(expected_output, slippage)?;
-
-        let (swap_fn, call_data, _value) = if from_is_eth && !to_is_eth {
-            prepare_v3_exact_input_single_native(
-                token_out,
-                pool_fee,
-                amount,
-                amount_out_min,
-                Address::zero(),
-            )?
-        } else {
-            prepare_v3_exact_input_single(
+        let (gas_price, weth_value) =
+            futures::try_join!(self.gas_price(), self.weth_value(quoted_token))?;
+
+        let V3RouteSearch {
+            route,
+            quote,
+            fee_tiers,
+        } = self
+            .best_v3_route(
                 token_in,
                 token_out,
-                pool_fee,
+                input.pool_fee,
                 amount,
-                amount_out_min,
-                Address::zero(),
-            )?
+                mode,
+                gas_price,
+                weth_value,
+            )
+            .await
+            .context("Failed to get expected amount from V3")?;
+        info!("Routing V3 swap through {}", route);
+        let expected_amount = quote.amount;
+
+        // The router can only pay out ETH by unwrapping WETH it holds itself, so swaps to
+        // ETH send their output to the router and unwrap it to the recipient afterwards
+        let swap_recipient = if to_is_eth { router_address } else { recipient };
+
+        // Single-pool routes use the *Single functions, longer ones the encoded-path ones
+        let (swap_fn, swap_call, value, limit) = match mode {
+            SwapMode::ExactInput => {
+                let amount_out_min = calculate_min_output(expected_amount, slippage)?;
+                let (swap_fn, call_data, _value) = if !route.is_direct() {
+                    prepare_v3_exact_input(
+                        router_kind,
+                        route.encode_v3_path(),
+                        amount,
+                        amount_out_min,
+                        swap_recipient,
+                        deadline,
+                    )?
+                } else if from_is_eth && !to_is_eth {
+                    prepare_v3_exact_input_single_native(
+                        router_kind,
+                        weth_address,
+                        token_out,
+                        route.fees[0],
+                        amount,
+                        amount_out_min,
+                        swap_recipient,
+                        deadline,
+                    )?
+                } else {
+                    prepare_v3_exact_input_single(
+                        router_kind,
+                        token_in,
+                        token_out,
+                        route.fees[0],
+                        amount,
+                        amount_out_min,
+                        swap_recipient,
+                        deadline,
+                    )?
+                };
+                (swap_fn, call_data, amount, amount_out_min)
+            }
+            SwapMode::ExactOutput => {
+                let amount_in_max = calculate_max_input(expected_amount, slippage)?;
+                let (swap_fn, call_data, _value) = if !route.is_direct() {
+                    prepare_v3_exact_output(
+                        router_kind,
+                        route.reversed().encode_v3_path(),
+                        amount,
+                        amount_in_max,
+                        swap_recipient,
+                        deadline,
+                    )?
+                } else {
+                    prepare_v3_exact_output_single(
+                        router_kind,
+                        token_in,
+                        token_out,
+                        route.fees[0],
+                        amount,
+                        amount_in_max,
+                        swap_recipient,
+                        deadline,
+                    )?
+                };
+                // The maximum input is sent, and what the swap leaves over refunded below
+                (swap_fn, call_data, amount_in_max, amount_in_max)
+            }
         };
 
-        // Use a dummy address for simulation (eth_call doesn't require real balance)
-        let dummy_from_address = Address::from_str("0xd8dA6BF26964aF9D7eEd9e03E53415D37aA96045")?;
+        let mut calls = vec![swap_call];
+        if to_is_eth {
+            let amount_out_min = match mode {
+                SwapMode::ExactInput => limit,
+                SwapMode::ExactOutput => amount,
+            };
+            calls.push(transaction::prepare_v3_unwrap_weth9(
+                amount_out_min,
+                recipient,
+            )?);
+        }
+        if from_is_eth && mode == SwapMode::ExactOutput {
+            calls.push(transaction::prepare_v3_refund_eth()?);
+        }
+        let (call_data, is_multicall) =
+            transaction::v3_router_calldata(router_kind, calls, deadline)?;
+
+        let max_amount_in = match mode {
+            SwapMode::ExactInput => amount,
+            SwapMode::ExactOutput => limit,
+        };
 
         let mut tx_request = TransactionRequest::new()
             .to(router_address)
-            .from(dummy_from_address)
+            .from(sender)
             .data(call_data.clone());
 
         if from_is_eth {
-            tx_request = tx_request.value(amount);
+            tx_request = tx_request.value(value);
         }
 
         warn!(
             "Simulating V3 swap: to={:?}, from={:?}, data_len={}, value={:?}",
             router_address,
-            dummy_from_address,
+            sender,
             call_data.len(),
-            if from_is_eth { Some(amount) } else { None }
+            if from_is_eth { Some(value) } else { None }
         );
 
-        // Try to simulate the swap, but if it fails (e.g., due to approval or balance checks),
-        // fall back to using the expected output from quoteExactInputSingle
-        let actual_output = match self.provider.call(&tx_request.clone().into(), None).await {
-            Ok(call_result) => match decode_v3_swap_result(&swap_fn, &call_result) {
-                Ok(output) => output,
-                Err(e) => {
-                    warn!(
-                        "Failed to decode V3 swap result: {}, using expected output",
-                        e
-                    );
-                    expected_output
-                }
-            },
-            Err(e) => {
-                warn!("V3 swap simulation call failed: {}, using expected output from quoteExactInputSingle", e);
-                // Use the expected output from quoteExactInputSingle as fallback
-                expected_output
-            }
-        };
-
-        // Try to estimate gas, but if it fails (e.g., due to transaction revert),
-        // use a reasonable default gas estimate
-        let gas_estimate = match self
-            .provider
-            .estimate_gas(&tx_request.clone().into(), None)
-            .await
+        // Simulate the swap with the sender granted its input, and if it still fails,
+        // fall back to the expected amount from the quoter
+        let simulation = self
+            .simulate_swap(
+                tx_request.clone().into(),
+                sender,
+                router_address,
+                (!from_is_eth).then_some(token_in),
+                max_amount_in,
+            )
+            .await;
+        let (actual_amount, simulation_status, simulation_error) = match simulation
+            .output
+            .and_then(|result| transaction::first_v3_router_result(result, is_multicall))
+            .and_then(|result| decode_v3_swap_result(&swap_fn, &result))
         {
-            Ok(gas) => gas,
+            Ok(amount) => (amount, SimulationStatus::Full, None),
             Err(e) => {
                 warn!(
-                    "Failed to estimate gas for V3 swap: {}, using default gas estimate",
+                    "V3 swap simulation failed: {:#}, using expected amount from the quoter",
                     e
                 );
-                // Use default gas estimates for Uniswap V3 swaps
-                // V3 swaps typically use 150k-250k gas
-                U256::from(200_000u64)
+                (
+                    expected_amount,
+                    SimulationStatus::QuoteOnly,
+                    Some(format!("{e:#}")),
+                )
             }
         };
 
-        let gas_price = self
-            .provider
-            .get_gas_price()
-            .await
-            .context("Failed to get gas price")?;
-
-        let gas_cost_wei = gas_estimate * gas_price;
-        let gas_cost_eth = Decimal::from_str(&gas_cost_wei.to_string())
-            .context("Failed to convert gas cost to Decimal")?
-            / Decimal::from(1_000_000_000_000_000_000u64);
-
-        let to_token_decimals = if to_is_eth {
-            18
-        } else {
-            self.get_token_decimals(token_out).await.unwrap_or(18)
+        let price_impact = self
+            .route_price_impact(&route, UniswapVersion::V3, mode, amount, actual_amount)
+            .await;
+        let allowance = self
+            .sender_allowance(
+                input.sender.is_some().then_some(sender),
+                (!from_is_eth).then_some(token_in),
+                router_address,
+                from_decimals,
+                max_amount_in,
+            )
+            .await;
+
+        // If gas can't be estimated (e.g., due to transaction revert), use a reasonable
+        // default: V3 swaps typically use 150k-250k gas
+        let gas_estimate = simulation.gas_estimate.unwrap_or_else(|| {
+            warn!("Using default gas estimate for V3 swap");
+            U256::from(200_000u64)
+        });
+
+        let quoted_decimals = match mode {
+            SwapMode::ExactInput => to_decimals,
+            SwapMode::ExactOutput => from_decimals,
         };
-
-        let output_decimal = Decimal::from_str(&actual_output.to_string())
-            .context("Failed to convert output to Decimal")?
-            / Decimal::from(10u64.pow(u32::from(to_token_decimals)));
-
-        let min_output_decimal = Decimal::from_str(&amount_out_min.to_string())
-            .context("Failed to convert min output to Decimal")?
-            / Decimal::from(10u64.pow(u32::from(to_token_decimals)));
-
-        Ok(SwapOutput {
+        let mut output = SwapOutput {
             from_token: input.from_token,
             to_token: input.to_token,
-            input_amount: input.amount,
-            estimated_output: format!(
-                "{:.prec$}",
-                output_decimal,
-                prec = to_token_decimals as usize
-            ),
-            minimum_output: format!(
-                "{:.prec$}",
-                min_output_decimal,
-                prec = to_token_decimals as usize
-            ),
+            input_amount: input.amount.clone(),
+            estimated_output: input.amount,
+            minimum_output: None,
+            maximum_input: None,
             slippage_tolerance: input.slippage_tolerance,
+            mode,
+            simulation: simulation_status,
+            simulation_error,
             estimated_gas: gas_estimate.to_string(),
-            estimated_gas_eth: format!("{gas_cost_eth:.18}"),
-            price_impact: None,
+            estimated_gas_eth: format_amount(gas_estimate * gas_price, 18)?,
+            price_impact: price_impact.map(|impact| impact.to_string()),
+            price_impact_warning: price_impact.and_then(|impact| self.impact_warning(impact)),
             involves_eth: from_is_eth || to_is_eth,
             version: "V3".to_string(),
+            chain: self.chain.name.to_string(),
+            route: route.hops(),
+            fee_tiers: Some(
+                fee_tiers
+                    .iter()
+                    .map(|(fee, best)| fee_tier_quote(*fee, best, mode, quoted_decimals))
+                    .collect::<Result<_>>()?,
+            ),
+            allowance,
+            ens_names: Vec::new(),
+        };
+        set_swap_amounts(
+            &mut output,
+            actual_amount,
+            limit,
+            from_decimals,
+            to_decimals,
+        )?;
+        Ok(PreparedSwap {
+            output,
+            tx: tx_request,
+            gas_estimate: simulation.gas_estimate,
         })
     }
 
-    /// Query Uniswap V2 Router to get expected output amount for a given input amount and swap path.
-    ///
-    /// This function calls the `getAmountsOut` function on the Uniswap V2 Router contract.
-    /// It performs a read-only query (using `eth_call`) to calculate the expected output amount
-    /// based on current pool reserves and prices, without executing an actual swap transaction.
-    ///
-    /// # Function Flow
-    /// 1. Constructs the `getAmountsOut` function signature with parameters:
-    ///    - `amountIn`: The input amount (in token's smallest unit, e.g., wei for ETH)
-    ///    - `path`: Array of token addresses representing the swap path
-    ///      - Example: [WETH, USDC] for ETH -> USDC swap
-    ///      - Example: [USDC, WETH] for USDC -> ETH swap
-    /// 2. Encodes the function call data using ABI encoding
-    /// 3. Sends a read-only `eth_call` to the Uniswap V2 Router contract
-    /// 4. Decodes the response which returns an array of amounts at each step
-    /// 5. Extracts the last element (final output amount) from the amounts array
-    ///
-    /// # Parameters
-    /// - `path`: The swap path as an array of token addresses. The first element is the input token,
-    ///   and the last element is the output token. For direct swaps, the path has 2 elements.
-    ///   For multi-hop swaps, it can have more elements.
-    /// - `amount_in`: The input amount in the smallest unit of the input token (e.g., wei for ETH,
-    ///   or raw units for ERC20 tokens)
-    ///
-    /// # Returns
-    /// - `Ok(U256)`: The expected output amount in the smallest unit of the output token
-    /// - `Err`: If the function call fails, encoding/decoding fails, or the response format is unexpected
+    /// Find the V2 route with the largest output for `amount` of `token_in`, or in
+    /// exact-output mode the smallest input for `amount` of `token_out`.
     ///
-    /// # Example
-    /// ```
-    /// // For ETH -> USDC swap with 0.1 ETH input:
-    /// // path = [WETH_ADDRESS, USDC_ADDRESS]
-    /// // amount_in = 100000000000000000 (0.1 ETH in wei)
-    /// // Returns: expected USDC amount in raw units (6 decimals)
-    /// ```
+    /// Candidates are the direct pair and every two-hop path through an intermediate token
+    /// (see [`routing::intermediate_tokens`]). Each is quoted with the router's
+    /// `getAmountsOut` (or `getAmountsIn`), which reverts when a pair on the path doesn't
+    /// exist, and all quotes are sent in a single Multicall3 batch.
     ///
-    /// # Notes
-    /// - This is a read-only operation that does not modify blockchain state
-    /// - The result is based on current pool reserves and may change between calls
-    /// - No gas is consumed for this query (read-only `eth_call`)
-    async fn get_v2_expected_output(&self, path: &[Address], amount_in: U256) -> Result<U256> {
-        let router_address = Address::from_str(UNISWAP_V2_ROUTER)?;
-
-        let get_amounts_out_fn = Function {
-            name: "getAmountsOut".to_string(),
-            inputs: vec![
-                Param {
-                    name: "amountIn".to_string(),
-                    kind: ParamType::Uint(256),
-                    internal_type: None,
-                },
-                Param {
-                    name: "path".to_string(),
-                    kind: ParamType::Array(Box::new(ParamType::Address)),
-                    internal_type: None,
-                },
-            ],
-            outputs: vec![Param {
-                name: "amounts".to_string(),
-                kind: ParamType::Array(Box::new(ParamType::Uint(256))),
-                internal_type: None,
-            }],
-            #[allow(deprecated)]
-            constant: None,
-            state_mutability: StateMutability::View,
+    /// Returns the best route and its expected output in the smallest unit of `token_out`,
+    /// or its expected input in the smallest unit of `token_in`.
+    async fn best_v2_route(
+        &self,
+        token_in: Address,
+        token_out: Address,
+        amount: U256,
+        mode: SwapMode,
+    ) -> Result<(Route, U256)> {
+        let router_address = Address::from_str(self.chain.contracts.uniswap_v2_router)?;
+        let intermediates = routing::intermediate_tokens(self.chain)?;
+        let candidates = routing::v2_candidates(token_in, token_out, &intermediates);
+
+        let get_amounts_fn = match mode {
+            SwapMode::ExactInput => get_amounts_out_function(),
+            SwapMode::ExactOutput => get_amounts_in_function(),
         };
+        let mut multicall = Multicall::new(&self.provider, self.chain)?;
+        for route in &candidates {
+            let path = route.tokens.iter().map(|&token| Token::Address(token));
+            multicall.add_call(
+                router_address,
+                &get_amounts_fn,
+                &[Token::Uint(amount), Token::Array(path.collect())],
+            )?;
+        }
 
-        let path_tokens: Vec<Token> = path.iter().map(|&addr| Token::Address(addr)).collect();
-        let input_data = get_amounts_out_fn
-            .encode_input(&[Token::Uint(amount_in), Token::Array(path_tokens)])
-            .context("Failed to encode getAmountsOut call")?;
-
-        let tx_request = TransactionRequest::new()
-            .to(router_address)
-            .data(input_data);
-
-        let result = self
-            .provider
-            .call(&tx_request.into(), None)
+        let quotes = multicall
+            .call()
             .await
-            .context("Failed to call getAmountsOut")?;
-
-        let decoded = get_amounts_out_fn
-            .decode_output(&result)
-            .context("Failed to decode getAmountsOut result")?;
-
-        let amounts = match decoded.first() {
-            Some(Token::Array(arr)) => arr,
-            _ => anyhow::bail!("Unexpected getAmountsOut result format"),
-        };
-
-        match amounts.last() {
-            Some(Token::Uint(val)) => Ok(*val),
-            _ => anyhow::bail!("Failed to extract output amount"),
-        }
+            .with_context(|| format!("Failed to call {}", get_amounts_fn.name))?
+            .into_iter()
+            .map(|result| quoted_amount(&result?, mode).map(Quote::new))
+            .collect::<Vec<_>>();
+        let (route, quote) = routing::best_route(&candidates, &quotes, mode)?;
+        Ok((route, quote.amount))
     }
 
-    async fn get_v3_expected_output(
+    /// Find the V3 route with the best net output for `amount` of `token_in`, or in
+    /// exact-output mode the best net input for `amount` of `token_out`.
+    ///
+    /// Candidates are the direct pool and every two-hop path through an intermediate token,
+    /// with each hop in the `pool_fee` tier if given and in every tier otherwise. They are
+    /// quoted with QuoterV2 `quoteExactInput` (or `quoteExactOutput`) in a single Multicall3
+    /// batch. Each route's gas estimate is priced at `gas_price` and valued in the quoted
+    /// token at `weth_value` (see [`SwapProvider::weth_value`]), and routes are ranked by
+    /// output minus that cost (input plus it), or by the amount alone if `weth_value` is
+    /// unknown.
+    ///
+    /// If QuoterV2 can't quote any exact-input route, the old Quoter (where deployed) is
+    /// asked for the direct pool in every tier, ranked by output alone.
+    #[allow(clippy::too_many_arguments)]
+    async fn best_v3_route(
         &self,
         token_in: Address,
         token_out: Address,
-        fee: u32,
-        amount_in: U256,
-        _is_eth: bool,
-    ) -> Result<U256> {
-        // Try the specified fee first, then fallback to common fees if pool doesn't exist
-        let fees_to_try = vec![fee, 3000, 500, 10000];
-
-        let mut last_error = None;
-        for &try_fee in &fees_to_try {
-            match self
-                .try_get_v3_expected_output_quoter_v2(
-                    token_in, token_out, try_fee, amount_in, _is_eth,
-                )
-                .await
-            {
-                Ok(result) => {
-                    if try_fee != fee {
-                        warn!(
-                            "Pool with fee {} not found, using fee {} instead",
-                            fee, try_fee
-                        );
-                    }
-                    return Ok(result);
-                }
-                Err(e) => {
-                    warn!("Failed to get quote with QuoterV2 fee {}: {}", try_fee, e);
-                    last_error = Some(e);
-                    // Continue trying other fees
+        pool_fee: Option<u32>,
+        amount: U256,
+        mode: SwapMode,
+        gas_price: U256,
+        weth_value: Option<U256>,
+    ) -> Result<V3RouteSearch> {
+        let quoter_address = Address::from_str(self.chain.contracts.uniswap_v3_quoter_v2)?;
+        let fees = pool_fee.map_or(V3_FEE_TIERS.to_vec(), |fee| vec![fee]);
+        let intermediates = routing::intermediate_tokens(self.chain)?;
+        let candidates = routing::v3_candidates(token_in, token_out, &intermediates, &fees);
+
+        let quote_fn = match mode {
+            SwapMode::ExactInput => quote_exact_input_function(),
+            SwapMode::ExactOutput => quote_exact_output_function(),
+        };
+        let quote_fn = &quote_fn;
+        let batches = candidates
+            .chunks(QUOTES_PER_MULTICALL)
+            .map(|batch| async move {
+                let mut multicall = Multicall::new(&self.provider, self.chain)?;
+                for route in batch {
+                    // Exact-output paths run from the output token back to the input token
+                    let path = match mode {
+                        SwapMode::ExactInput => route.encode_v3_path(),
+                        SwapMode::ExactOutput => route.reversed().encode_v3_path(),
+                    };
+                    multicall.add_call(
+                        quoter_address,
+                        quote_fn,
+                        &[Token::Bytes(path), Token::Uint(amount)],
+                    )?;
                 }
+                multicall
+                    .call()
+                    .await
+                    .with_context(|| format!("Failed to call QuoterV2 {}", quote_fn.name))
+            });
+        let results = futures::future::try_join_all(batches).await?;
+
+        let quotes: Vec<Result<Quote>> = results
+            .into_iter()
+            .flatten()
+            .map(|result| {
+                let (amount, gas_estimate) = decode_v3_quote(&result?)?;
+                let net_amount = weth_value.map(|weth_value| {
+                    let gas_cost = gas_cost_in(gas_estimate, gas_price, weth_value);
+                    match mode {
+                        SwapMode::ExactInput => amount.saturating_sub(gas_cost),
+                        SwapMode::ExactOutput => amount.saturating_add(gas_cost),
+                    }
+                });
+                Ok(Quote {
+                    amount,
+                    gas_estimate: Some(gas_estimate),
+                    net_amount,
+                })
+            })
+            .collect();
+
+        match routing::best_route(&candidates, &quotes, mode) {
+            Ok((route, quote)) => {
+                return Ok(V3RouteSearch {
+                    route,
+                    quote,
+                    fee_tiers: routing::best_route_per_fee_tier(&candidates, &quotes, &fees, mode),
+                })
             }
-        }
-
-        // If QuoterV2 fails, try old Quoter as fallback
-        warn!("QuoterV2 failed for all fees, trying old Quoter as fallback");
-        for &try_fee in &fees_to_try {
-            match self
-                .try_get_v3_expected_output_quoter(token_in, token_out, try_fee, amount_in, _is_eth)
-                .await
+            // The old Quoter is only asked for exact-input quotes
+            Err(e)
+                if mode == SwapMode::ExactInput
+                    && self.chain.contracts.uniswap_v3_quoter.is_some() =>
             {
-                Ok(result) => {
-                    warn!("Using old Quoter result for fee {}", try_fee);
-                    return Ok(result);
-                }
-                Err(e) => {
-                    warn!("Failed to get quote with old Quoter fee {}: {}", try_fee, e);
-                    // Continue trying
-                }
+                warn!("QuoterV2 found no route ({e:#}), trying old Quoter as fallback")
             }
+            Err(e) => return Err(e),
         }
 
-        Err(last_error.unwrap_or_else(|| {
-            anyhow::anyhow!(
-                "Failed to get V3 quote with any fee tier or quoter (tried: {fees_to_try:?})"
-            )
-        }))
+        let candidates: Vec<Route> = fees
+            .iter()
+            .map(|&fee| Route {
+                tokens: vec![token_in, token_out],
+                fees: vec![fee],
+            })
+            .collect();
+        let quotes: Vec<Result<Quote>> =
+            futures::future::join_all(fees.iter().map(|&fee| {
+                self.try_get_v3_expected_output_quoter(token_in, token_out, fee, amount)
+            }))
+            .await
+            .into_iter()
+            .map(|result| result.map(Quote::new))
+            .collect();
+
+        let (route, quote) = routing::best_route(&candidates, &quotes, mode)?;
+        Ok(V3RouteSearch {
+            route,
+            quote,
+            fee_tiers: routing::best_route_per_fee_tier(&candidates, &quotes, &fees, mode),
+        })
     }
 
-    async fn try_get_v3_expected_output_quoter_v2(
+    /// Quote `amount_in` of `token_in` in the V3 pool with fee tier `fee` through QuoterV2
+    pub(crate) async fn try_get_v3_expected_output_quoter_v2(
         &self,
         token_in: Address,
         token_out: Address,
         fee: u32,
         amount_in: U256,
-        _is_eth: bool,
     ) -> Result<U256> {
-        let quoter_address = Address::from_str(UNISWAP_V3_QUOTER_V2)?;
+        let quoter_address = Address::from_str(self.chain.contracts.uniswap_v3_quoter_v2)?;
 
         let quote_exact_input_single_fn = Function {
             name: "quoteExactInputSingle".to_string(),
             inputs: vec![Param {
                 name: "params".to_string(),
+                // QuoteExactInputSingleParams: tokenIn, tokenOut, amountIn, fee, sqrtPriceLimitX96
                 kind: ParamType::Tuple(vec![
                     ParamType::Address,
                     ParamType::Address,
-                    ParamType::Uint(24),
                     ParamType::Uint(256),
+                    ParamType::Uint(24),
                     ParamType::Uint(160),
                 ]),
                 internal_type: None,
@@ -657,15 +1293,12 @@ impl SwapProvider {
             state_mutability: StateMutability::View,
         };
 
-        // token_in is already WETH if it was ETH, so we don't need to convert again
-        let actual_token_in = token_in;
-
         let sqrt_price_limit_x96 = U256::zero();
         let params_tokens = vec![
-            Token::Address(actual_token_in),
+            Token::Address(token_in),
             Token::Address(token_out),
-            Token::Uint(U256::from(fee)),
             Token::Uint(amount_in),
+            Token::Uint(U256::from(fee)),
             Token::Uint(sqrt_price_limit_x96),
         ];
 
@@ -673,17 +1306,13 @@ impl SwapProvider {
             .encode_input(&[Token::Tuple(params_tokens)])
             .context("Failed to encode quoteExactInputSingle call")?;
 
-        // Use a dummy address for simulation (eth_call doesn't require real balance)
-        let dummy_from_address = Address::from_str("0xd8dA6BF26964aF9D7eEd9e03E53415D37aA96045")?;
-
         let tx_request = TransactionRequest::new()
             .to(quoter_address)
-            .from(dummy_from_address)
             .data(input_data);
 
         warn!(
             "Calling V3 QuoterV2: token_in={:?}, token_out={:?}, fee={}, amount_in={}",
-            actual_token_in, token_out, fee, amount_in
+            token_in, token_out, fee, amount_in
         );
 
         let result = self
@@ -708,9 +1337,13 @@ impl SwapProvider {
         token_out: Address,
         fee: u32,
         amount_in: U256,
-        _is_eth: bool,
     ) -> Result<U256> {
-        let quoter_address = Address::from_str(UNISWAP_V3_QUOTER)?;
+        let quoter_address = Address::from_str(
+            self.chain
+                .contracts
+                .uniswap_v3_quoter
+                .with_context(|| format!("Old Quoter is not deployed on {}", self.chain.name))?,
+        )?;
 
         // Old Quoter has different interface - returns uint256 directly
         let quote_exact_input_single_fn = Function {
@@ -763,11 +1396,8 @@ impl SwapProvider {
             ])
             .context("Failed to encode quoteExactInputSingle call for old Quoter")?;
 
-        let dummy_from_address = Address::from_str("0xd8dA6BF26964aF9D7eEd9e03E53415D37aA96045")?;
-
         let tx_request = TransactionRequest::new()
             .to(quoter_address)
-            .from(dummy_from_address)
             .data(input_data);
 
         warn!(
@@ -791,50 +1421,223 @@ impl SwapProvider {
         }
     }
 
-    async fn get_token_decimals(&self, token_address: Address) -> Result<u8> {
-        let decimals_fn = Function {
-            name: "decimals".to_string(),
-            inputs: vec![],
-            outputs: vec![Param {
-                name: "".to_string(),
-                kind: ParamType::Uint(8),
-                internal_type: None,
-            }],
-            #[allow(deprecated)]
-            constant: None,
-            state_mutability: StateMutability::View,
+    /// Resolve the tokens and amount of a swap. ETH is replaced by WETH, which the
+    /// routers wrap and unwrap. `amount` is in `to_token` in exact-output mode
+    async fn resolve_pair(
+        &self,
+        from_token: &str,
+        to_token: &str,
+        amount: &str,
+        mode: SwapMode,
+    ) -> Result<SwapPair> {
+        let from_is_eth = self.chain.is_native_token(from_token);
+        let to_is_eth = self.chain.is_native_token(to_token);
+        if from_is_eth && to_is_eth {
+            anyhow::bail!("ETH to ETH swap is not supported");
+        }
+
+        let token_in = Address::from_str(&normalize_token_address(from_token, self.chain)?)?;
+        let token_out = Address::from_str(&normalize_token_address(to_token, self.chain)?)?;
+
+        let (from_decimals, to_decimals) = self
+            .get_pair_decimals(
+                (!from_is_eth).then_some(token_in),
+                (!to_is_eth).then_some(token_out),
+            )
+            .await?;
+
+        let amount = match mode {
+            SwapMode::ExactInput => parse_amount(amount, from_decimals)?,
+            SwapMode::ExactOutput => parse_amount(amount, to_decimals)?,
         };
 
-        let input_data = decimals_fn
-            .encode_input(&[])
-            .context("Failed to encode decimals call")?;
+        Ok(SwapPair {
+            token_in,
+            token_out,
+            from_is_eth,
+            to_is_eth,
+            from_decimals,
+            to_decimals,
+            amount,
+        })
+    }
 
-        let tx_request = TransactionRequest::new().to(token_address).data(input_data);
+    /// Price impact of a simulated swap, where `amount` is the amount given and `quoted`
+    /// the simulated one. `None` if the pools couldn't be read
+    async fn route_price_impact(
+        &self,
+        route: &Route,
+        version: UniswapVersion,
+        mode: SwapMode,
+        amount: U256,
+        quoted: U256,
+    ) -> Option<Decimal> {
+        let (amount_in, amount_out) = match mode {
+            SwapMode::ExactInput => (amount, quoted),
+            SwapMode::ExactOutput => (quoted, amount),
+        };
+        match self
+            .price_impact(route, version, amount_in, amount_out)
+            .await
+        {
+            Ok(impact) => {
+                info!("Price impact of {:?} swap: {}%", version, impact);
+                Some(impact)
+            }
+            Err(e) => {
+                warn!("Failed to calculate price impact: {:#}", e);
+                None
+            }
+        }
+    }
 
-        let result = self
-            .provider
-            .call(&tx_request.into(), None)
+    /// ERC20 allowance of `owner` to the router for `token_in`, with the approvals needed to
+    /// sell `amount`. `None` without an owner (simulations from the placeholder sender), for
+    /// ETH input, or if the allowance couldn't be read.
+    ///
+    /// The V2 and V3 routers pull tokens with `transferFrom`, so only the direct allowance
+    /// matters here, not Permit2
+    async fn sender_allowance(
+        &self,
+        owner: Option<Address>,
+        token_in: Option<Address>,
+        router: Address,
+        decimals: u8,
+        amount: U256,
+    ) -> Option<AllowanceCheck> {
+        let (owner, token_in) = owner.zip(token_in)?;
+        match allowance::check_allowance(
+            &self.provider,
+            self.chain,
+            token_in,
+            owner,
+            router,
+            decimals,
+            Some(amount),
+        )
+        .await
+        {
+            Ok(check) => {
+                if check.sufficient == Some(false) {
+                    info!(
+                        "{:?} must approve the router before swapping: allowance {}, required {:?}",
+                        owner, check.allowance, check.required
+                    );
+                }
+                Some(check)
+            }
+            Err(e) => {
+                warn!("Failed to check the sender's allowance: {:#}", e);
+                None
+            }
+        }
+    }
+
+    /// Warning for a price impact above the configured threshold
+    fn impact_warning(&self, impact: Decimal) -> Option<String> {
+        let threshold = self.price_impact_warning?;
+        (impact > threshold).then(|| {
+            format!(
+                "Price impact of {impact}% exceeds the {threshold}% warning threshold; the pools are too thin for this trade size"
+            )
+        })
+    }
+
+    async fn gas_price(&self) -> Result<U256> {
+        self.provider
+            .get_gas_price()
             .await
-            .context("Failed to call decimals")?;
+            .context("Failed to get gas price")
+    }
 
-        let decoded = decimals_fn
-            .decode_output(&result)
-            .context("Failed to decode decimals result")?;
+    /// Value of 1 WETH in the smallest unit of `token_out`, used to value gas in the token
+    /// quotes are in: the best quote of the V2 pair and the V3 pool of every fee tier, sent
+    /// in one Multicall3 batch. `None` if no pool could quote it
+    async fn weth_value(&self, token_out: Address) -> Result<Option<U256>> {
+        let one_weth = U256::exp10(18);
+        let weth_address = Address::from_str(self.chain.contracts.weth)?;
+        if token_out == weth_address {
+            return Ok(Some(one_weth));
+        }
 
-        match decoded.first() {
-            Some(Token::Uint(val)) => {
-                let d = val.to_string().parse::<u64>()? as u8;
-                Ok(d)
-            }
-            _ => anyhow::bail!("Unexpected decimals result format"),
+        let router_address = Address::from_str(self.chain.contracts.uniswap_v2_router)?;
+        let quoter_address = Address::from_str(self.chain.contracts.uniswap_v3_quoter_v2)?;
+        let quote_exact_input_fn = quote_exact_input_function();
+
+        let mut multicall = Multicall::new(&self.provider, self.chain)?;
+        multicall.add_call(
+            router_address,
+            &get_amounts_out_function(),
+            &[
+                Token::Uint(one_weth),
+                Token::Array(vec![
+                    Token::Address(weth_address),
+                    Token::Address(token_out),
+                ]),
+            ],
+        )?;
+        for fee in V3_FEE_TIERS {
+            let route = Route {
+                tokens: vec![weth_address, token_out],
+                fees: vec![fee],
+            };
+            multicall.add_call(
+                quoter_address,
+                &quote_exact_input_fn,
+                &[Token::Bytes(route.encode_v3_path()), Token::Uint(one_weth)],
+            )?;
+        }
+
+        let mut results = multicall
+            .call()
+            .await
+            .context("Failed to quote WETH in the output token")?
+            .into_iter();
+        let v2_quote = results.next().map(|result| last_amount(&result?));
+        let v3_quotes = results.map(|result| Ok(decode_v3_quote(&result?)?.0));
+
+        let weth_value = v2_quote
+            .into_iter()
+            .chain(v3_quotes)
+            .filter_map(Result::ok)
+            .filter(|amount_out| !amount_out.is_zero())
+            .max();
+        if weth_value.is_none() {
+            warn!(
+                "No pool quotes WETH to {:?}, gas can't be valued in that token",
+                token_out
+            );
         }
+        Ok(weth_value)
+    }
+
+    /// Decimals of the swap's input and output tokens, fetched in a single Multicall3 batch.
+    ///
+    /// `None` stands for the native token, which always has 18 decimals.
+    async fn get_pair_decimals(
+        &self,
+        from_token: Option<Address>,
+        to_token: Option<Address>,
+    ) -> Result<(u8, u8)> {
+        let tokens: Vec<Address> = from_token.iter().chain(to_token.iter()).copied().collect();
+        let mut decimals = erc20::get_decimals(&self.provider, self.chain, &tokens)
+            .await?
+            .into_iter();
+
+        let mut decimals_of = |token: Option<Address>| match token {
+            Some(_) => decimals.next().context("Missing decimals result"),
+            None => Ok(18),
+        };
+        Ok((decimals_of(from_token)?, decimals_of(to_token)?))
     }
 }
 
-fn normalize_token_address(token: &str) -> Result<String> {
+fn normalize_token_address(token: &str, chain: &Chain) -> Result<String> {
     let token_lower = token.to_lowercase();
-    if token_lower == "eth" || token_lower == "ethereum" {
-        Ok(WETH_ADDRESS.to_string())
+    if chain.is_native_token(&token_lower)
+        || (token_lower == "ethereum" && chain.is_native_token("ETH"))
+    {
+        Ok(chain.contracts.weth.to_string())
     } else if token_lower.starts_with("0x") && token_lower.len() == 42 {
         Ok(token_lower)
     } else {
@@ -842,29 +1645,39 @@ fn normalize_token_address(token: &str) -> Result<String> {
     }
 }
 
+fn parse_sender(sender: Option<&str>) -> Result<Address> {
+    Address::from_str(sender.unwrap_or(PLACEHOLDER_SENDER).trim()).context("Invalid sender address")
+}
+
+/// Parse a human-readable amount into raw token units, dropping digits beyond `decimals`.
+/// Parsed in U256, so amounts beyond the `Decimal` range (about 7.9e28) work too
 fn parse_amount(amount_str: &str, decimals: u8) -> Result<U256> {
-    let amount_decimal = Decimal::from_str(amount_str).context("Failed to parse amount")?;
-    let divisor = Decimal::from(10u64.pow(u32::from(decimals)));
-    let amount_units = amount_decimal * divisor;
-
-    // Convert Decimal to string without decimal point
-    // Truncate to get integer part only, then convert to string
-    let amount_units_truncated = amount_units.trunc();
-    let amount_units_str = amount_units_truncated.to_string();
-
-    // Remove any decimal point that might still be present (e.g., "100.0")
-    let amount_units_str = if amount_units_str.contains('.') {
-        amount_units_str
-            .split('.')
-            .next()
-            .unwrap_or(&amount_units_str)
-    } else {
-        &amount_units_str
+    let amount = amount_str.trim();
+    let (whole, fraction) = amount.split_once('.').unwrap_or((amount, ""));
+    let is_digits = |digits: &str| digits.bytes().all(|b| b.is_ascii_digit());
+    if (whole.is_empty() && fraction.is_empty()) || !is_digits(whole) || !is_digits(fraction) {
+        anyhow::bail!("Failed to parse amount: {amount_str}");
+    }
+
+    let decimals = usize::from(decimals);
+    let scale = U256::from(10)
+        .checked_pow(U256::from(decimals))
+        .with_context(|| format!("Unsupported token decimals: {decimals}"))?;
+    let whole = match whole {
+        "" => U256::zero(),
+        whole => U256::from_dec_str(whole).context("Amount is too large")?,
+    };
+    let fraction = &fraction[..fraction.len().min(decimals)];
+    let fraction = match decimals {
+        0 => U256::zero(),
+        _ => U256::from_dec_str(&format!("{fraction:0<decimals$}"))
+            .context("Failed to parse amount")?,
     };
 
-    let amount_u256 =
-        U256::from_dec_str(amount_units_str).context("Failed to convert amount to U256")?;
-    Ok(amount_u256)
+    whole
+        .checked_mul(scale)
+        .and_then(|whole| whole.checked_add(fraction))
+        .context("Amount is too large")
 }
 
 fn parse_slippage(slippage_str: &str) -> Result<Decimal> {
@@ -875,25 +1688,148 @@ fn parse_slippage(slippage_str: &str) -> Result<Decimal> {
     Ok(slippage)
 }
 
+const BPS_PER_UNIT: u64 = 10_000;
+
+/// A slippage percentage in basis points. Slippage finer than a basis point is rounded
+/// down, so the bounds derived from it are never looser than requested
+fn slippage_bps(slippage: Decimal) -> Result<U256> {
+    let bps = u64::try_from((slippage * Decimal::ONE_HUNDRED).trunc())
+        .context("Invalid slippage tolerance")?;
+    if bps > BPS_PER_UNIT {
+        anyhow::bail!("Slippage tolerance must be between 0 and 100");
+    }
+    Ok(U256::from(bps))
+}
+
+/// Minimum output of an exact-input swap: `output` lowered by `slippage` percent,
+/// rounded down
 fn calculate_min_output(output: U256, slippage: Decimal) -> Result<U256> {
-    let slippage_decimal = slippage / Decimal::from(100);
-    let one_minus_slippage = Decimal::from(1) - slippage_decimal;
-    let min_output_decimal = Decimal::from_str(&output.to_string())? * one_minus_slippage;
+    let kept = U256::from(BPS_PER_UNIT) - slippage_bps(slippage)?;
+    let min_output = output
+        .checked_mul(kept)
+        .context("Output amount is too large")?;
+    Ok(min_output / BPS_PER_UNIT)
+}
+
+/// Maximum input of an exact-output swap: `input` raised by `slippage` percent, rounded up
+fn calculate_max_input(input: U256, slippage: Decimal) -> Result<U256> {
+    let raised = U256::from(BPS_PER_UNIT) + slippage_bps(slippage)?;
+    input
+        .checked_mul(raised)
+        .and_then(|max_input| max_input.checked_add(U256::from(BPS_PER_UNIT - 1)))
+        .map(|max_input| max_input / BPS_PER_UNIT)
+        .context("Input amount is too large")
+}
 
-    // Convert Decimal to string without decimal point
-    let min_output_truncated = min_output_decimal.trunc();
-    let min_output_str = min_output_truncated.to_string();
+/// Fill in the amounts of `output` from the simulated swap: `amount` is the output, or
+/// the input in exact-output mode, and `limit` is its slippage bound
+fn set_swap_amounts(
+    output: &mut SwapOutput,
+    amount: U256,
+    limit: U256,
+    from_decimals: u8,
+    to_decimals: u8,
+) -> Result<()> {
+    match output.mode {
+        SwapMode::ExactInput => {
+            output.estimated_output = format_amount(amount, to_decimals)?;
+            output.minimum_output = Some(format_amount(limit, to_decimals)?);
+        }
+        SwapMode::ExactOutput => {
+            output.input_amount = format_amount(amount, from_decimals)?;
+            output.maximum_input = Some(format_amount(limit, from_decimals)?);
+        }
+    }
+    Ok(())
+}
 
-    // Remove any decimal point that might still be present
-    let min_output_str = if min_output_str.contains('.') {
-        min_output_str.split('.').next().unwrap_or(&min_output_str)
-    } else {
-        &min_output_str
+/// V2 router `getAmountsOut(uint256 amountIn, address[] path)`, which returns the amount
+/// out of every hop on the path
+fn get_amounts_out_function() -> Function {
+    Function {
+        name: "getAmountsOut".to_string(),
+        inputs: vec![
+            Param {
+                name: "amountIn".to_string(),
+                kind: ParamType::Uint(256),
+                internal_type: None,
+            },
+            Param {
+                name: "path".to_string(),
+                kind: ParamType::Array(Box::new(ParamType::Address)),
+                internal_type: None,
+            },
+        ],
+        outputs: vec![Param {
+            name: "amounts".to_string(),
+            kind: ParamType::Array(Box::new(ParamType::Uint(256))),
+            internal_type: None,
+        }],
+        #[allow(deprecated)]
+        constant: None,
+        state_mutability: StateMutability::View,
+    }
+}
+
+/// V2 router `getAmountsIn(uint256 amountOut, address[] path)`, which returns the amount
+/// in of every hop on the path
+fn get_amounts_in_function() -> Function {
+    Function {
+        name: "getAmountsIn".to_string(),
+        inputs: vec![
+            Param {
+                name: "amountOut".to_string(),
+                kind: ParamType::Uint(256),
+                internal_type: None,
+            },
+            Param {
+                name: "path".to_string(),
+                kind: ParamType::Array(Box::new(ParamType::Address)),
+                internal_type: None,
+            },
+        ],
+        outputs: vec![Param {
+            name: "amounts".to_string(),
+            kind: ParamType::Array(Box::new(ParamType::Uint(256))),
+            internal_type: None,
+        }],
+        #[allow(deprecated)]
+        constant: None,
+        state_mutability: StateMutability::View,
+    }
+}
+
+/// The amount a V2 quote or swap was not given: the final output of an exact-input swap,
+/// or the first input of an exact-output swap
+fn quoted_amount(decoded: &[Token], mode: SwapMode) -> Result<U256> {
+    match mode {
+        SwapMode::ExactInput => last_amount(decoded),
+        SwapMode::ExactOutput => first_amount(decoded),
+    }
+}
+
+/// Input amount from the decoded `uint256[] amounts` returned by V2 router functions
+fn first_amount(decoded: &[Token]) -> Result<U256> {
+    match decoded.first() {
+        Some(Token::Array(amounts)) => match amounts.first() {
+            Some(Token::Uint(val)) => Ok(*val),
+            _ => anyhow::bail!("Failed to extract input amount"),
+        },
+        _ => anyhow::bail!("Unexpected V2 amounts format"),
+    }
+}
+
+/// Final output amount from the decoded `uint256[] amounts` returned by V2 router functions
+fn last_amount(decoded: &[Token]) -> Result<U256> {
+    let amounts = match decoded.first() {
+        Some(Token::Array(arr)) => arr,
+        _ => anyhow::bail!("Unexpected V2 amounts format"),
     };
 
-    let min_output =
-        U256::from_dec_str(min_output_str).context("Failed to convert min output to U256")?;
-    Ok(min_output)
+    match amounts.last() {
+        Some(Token::Uint(val)) => Ok(*val),
+        _ => anyhow::bail!("Failed to extract output amount"),
+    }
 }
 
 fn prepare_v2_swap_exact_eth_for_tokens(
@@ -901,6 +1837,7 @@ fn prepare_v2_swap_exact_eth_for_tokens(
     amount_in: U256,
     amount_out_min: U256,
     to: Address,
+    deadline: U256,
 ) -> Result<(Function, Bytes, U256)> {
     let function = Function {
         name: "swapExactETHForTokens".to_string(),
@@ -936,7 +1873,6 @@ fn prepare_v2_swap_exact_eth_for_tokens(
         state_mutability: StateMutability::Payable,
     };
 
-    let deadline = U256::from(u64::MAX);
     let path_tokens: Vec<Token> = path.iter().map(|&addr| Token::Address(addr)).collect();
     let data = function
         .encode_input(&[
@@ -955,6 +1891,7 @@ fn prepare_v2_swap_exact_tokens_for_eth(
     amount_in: U256,
     amount_out_min: U256,
     to: Address,
+    deadline: U256,
 ) -> Result<(Function, Bytes, U256)> {
     let function = Function {
         name: "swapExactTokensForETH".to_string(),
@@ -995,7 +1932,6 @@ fn prepare_v2_swap_exact_tokens_for_eth(
         state_mutability: StateMutability::NonPayable,
     };
 
-    let deadline = U256::from(u64::MAX);
     let path_tokens: Vec<Token> = path.iter().map(|&addr| Token::Address(addr)).collect();
     let data = function
         .encode_input(&[
@@ -1015,6 +1951,7 @@ fn prepare_v2_swap_exact_tokens_for_tokens(
     amount_in: U256,
     amount_out_min: U256,
     to: Address,
+    deadline: U256,
 ) -> Result<(Function, Bytes, U256)> {
     let function = Function {
         name: "swapExactTokensForTokens".to_string(),
@@ -1055,7 +1992,6 @@ fn prepare_v2_swap_exact_tokens_for_tokens(
         state_mutability: StateMutability::NonPayable,
     };
 
-    let deadline = U256::from(u64::MAX);
     let path_tokens: Vec<Token> = path.iter().map(|&addr| Token::Address(addr)).collect();
     let data = function
         .encode_input(&[
@@ -1070,44 +2006,237 @@ fn prepare_v2_swap_exact_tokens_for_tokens(
     Ok((function, data.into(), U256::zero()))
 }
 
-fn decode_v2_swap_result(function: &Function, result: &Bytes) -> Result<U256> {
+/// V2 exact-output swap functions, which all return `uint256[] amounts`
+fn v2_swap_for_exact_function(
+    name: &str,
+    inputs: &[(&str, ParamType)],
+    state_mutability: StateMutability,
+) -> Function {
+    Function {
+        name: name.to_string(),
+        inputs: inputs
+            .iter()
+            .map(|(name, kind)| Param {
+                name: name.to_string(),
+                kind: kind.clone(),
+                internal_type: None,
+            })
+            .collect(),
+        outputs: vec![Param {
+            name: "amounts".to_string(),
+            kind: ParamType::Array(Box::new(ParamType::Uint(256))),
+            internal_type: None,
+        }],
+        #[allow(deprecated)]
+        constant: None,
+        state_mutability,
+    }
+}
+
+fn prepare_v2_swap_eth_for_exact_tokens(
+    path: &[Address],
+    amount_out: U256,
+    amount_in_max: U256,
+    to: Address,
+    deadline: U256,
+) -> Result<(Function, Bytes, U256)> {
+    let function = v2_swap_for_exact_function(
+        "swapETHForExactTokens",
+        &[
+            ("amountOut", ParamType::Uint(256)),
+            ("path", ParamType::Array(Box::new(ParamType::Address))),
+            ("to", ParamType::Address),
+            ("deadline", ParamType::Uint(256)),
+        ],
+        StateMutability::Payable,
+    );
+
+    let path_tokens: Vec<Token> = path.iter().map(|&addr| Token::Address(addr)).collect();
+    let data = function
+        .encode_input(&[
+            Token::Uint(amount_out),
+            Token::Array(path_tokens),
+            Token::Address(to),
+            Token::Uint(deadline),
+        ])
+        .context("Failed to encode swapETHForExactTokens")?;
+
+    // The router refunds whatever part of the maximum input it doesn't use
+    Ok((function, data.into(), amount_in_max))
+}
+
+fn prepare_v2_swap_tokens_for_exact_eth(
+    path: &[Address],
+    amount_out: U256,
+    amount_in_max: U256,
+    to: Address,
+    deadline: U256,
+) -> Result<(Function, Bytes, U256)> {
+    prepare_v2_swap_tokens_for_exact(
+        "swapTokensForExactETH",
+        path,
+        amount_out,
+        amount_in_max,
+        to,
+        deadline,
+    )
+}
+
+fn prepare_v2_swap_tokens_for_exact_tokens(
+    path: &[Address],
+    amount_out: U256,
+    amount_in_max: U256,
+    to: Address,
+    deadline: U256,
+) -> Result<(Function, Bytes, U256)> {
+    prepare_v2_swap_tokens_for_exact(
+        "swapTokensForExactTokens",
+        path,
+        amount_out,
+        amount_in_max,
+        to,
+        deadline,
+    )
+}
+
+/// `swapTokensForExactETH` and `swapTokensForExactTokens`, which share their parameters
+fn prepare_v2_swap_tokens_for_exact(
+    name: &str,
+    path: &[Address],
+    amount_out: U256,
+    amount_in_max: U256,
+    to: Address,
+    deadline: U256,
+) -> Result<(Function, Bytes, U256)> {
+    let function = v2_swap_for_exact_function(
+        name,
+        &[
+            ("amountOut", ParamType::Uint(256)),
+            ("amountInMax", ParamType::Uint(256)),
+            ("path", ParamType::Array(Box::new(ParamType::Address))),
+            ("to", ParamType::Address),
+            ("deadline", ParamType::Uint(256)),
+        ],
+        StateMutability::NonPayable,
+    );
+
+    let path_tokens: Vec<Token> = path.iter().map(|&addr| Token::Address(addr)).collect();
+    let data = function
+        .encode_input(&[
+            Token::Uint(amount_out),
+            Token::Uint(amount_in_max),
+            Token::Array(path_tokens),
+            Token::Address(to),
+            Token::Uint(deadline),
+        ])
+        .with_context(|| format!("Failed to encode {name}"))?;
+
+    Ok((function, data.into(), U256::zero()))
+}
+
+/// The output of a simulated V2 swap, or its input in exact-output mode
+fn decode_v2_swap_result(function: &Function, result: &Bytes, mode: SwapMode) -> Result<U256> {
     let decoded = function
         .decode_output(result)
         .context("Failed to decode V2 swap result")?;
 
-    let amounts = match decoded.first() {
-        Some(Token::Array(arr)) => arr,
-        _ => anyhow::bail!("Unexpected V2 swap result format"),
-    };
+    quoted_amount(&decoded, mode).context("Unexpected V2 swap result format")
+}
 
-    match amounts.last() {
-        Some(Token::Uint(val)) => Ok(*val),
-        _ => anyhow::bail!("Failed to extract output amount from V2 swap result"),
+/// QuoterV2 `quoteExactInput(bytes path, uint256 amountIn)`, which quotes a swap along an
+/// encoded multi-pool path
+fn quote_exact_input_function() -> Function {
+    Function {
+        name: "quoteExactInput".to_string(),
+        inputs: vec![
+            Param {
+                name: "path".to_string(),
+                kind: ParamType::Bytes,
+                internal_type: None,
+            },
+            Param {
+                name: "amountIn".to_string(),
+                kind: ParamType::Uint(256),
+                internal_type: None,
+            },
+        ],
+        outputs: vec![
+            Param {
+                name: "amountOut".to_string(),
+                kind: ParamType::Uint(256),
+                internal_type: None,
+            },
+            Param {
+                name: "sqrtPriceX96AfterList".to_string(),
+                kind: ParamType::Array(Box::new(ParamType::Uint(160))),
+                internal_type: None,
+            },
+            Param {
+                name: "initializedTicksCrossedList".to_string(),
+                kind: ParamType::Array(Box::new(ParamType::Uint(32))),
+                internal_type: None,
+            },
+            Param {
+                name: "gasEstimate".to_string(),
+                kind: ParamType::Uint(256),
+                internal_type: None,
+            },
+        ],
+        #[allow(deprecated)]
+        constant: None,
+        state_mutability: StateMutability::NonPayable,
     }
 }
 
+/// QuoterV2 `quoteExactOutput(bytes path, uint256 amountOut)`, which quotes the input of a
+/// swap along an encoded multi-pool path given from the output token to the input token
+fn quote_exact_output_function() -> Function {
+    let mut function = quote_exact_input_function();
+    function.name = "quoteExactOutput".to_string();
+    function.inputs[1].name = "amountOut".to_string();
+    function.outputs[0].name = "amountIn".to_string();
+    function
+}
+
+#[allow(clippy::too_many_arguments)]
 fn prepare_v3_exact_input_single(
+    router_kind: V3RouterKind,
     token_in: Address,
     token_out: Address,
     fee: u32,
     amount_in: U256,
     amount_out_min: U256,
     recipient: Address,
+    deadline: U256,
 ) -> Result<(Function, Bytes, U256)> {
+    // SwapRouter02 dropped the deadline field from ExactInputSingleParams
+    let params_kind = match router_kind {
+        V3RouterKind::SwapRouter => vec![
+            ParamType::Address,
+            ParamType::Address,
+            ParamType::Uint(24),
+            ParamType::Address,
+            ParamType::Uint(256),
+            ParamType::Uint(256),
+            ParamType::Uint(256),
+            ParamType::Uint(160),
+        ],
+        V3RouterKind::SwapRouter02 => vec![
+            ParamType::Address,
+            ParamType::Address,
+            ParamType::Uint(24),
+            ParamType::Address,
+            ParamType::Uint(256),
+            ParamType::Uint(256),
+            ParamType::Uint(160),
+        ],
+    };
+
     let function = Function {
         name: "exactInputSingle".to_string(),
         inputs: vec![Param {
             name: "params".to_string(),
-            kind: ParamType::Tuple(vec![
-                ParamType::Address,
-                ParamType::Address,
-                ParamType::Uint(24),
-                ParamType::Address,
-                ParamType::Uint(256),
-                ParamType::Uint(256),
-                ParamType::Uint(256),
-                ParamType::Uint(160),
-            ]),
+            kind: ParamType::Tuple(params_kind),
             internal_type: None,
         }],
         outputs: vec![Param {
@@ -1120,19 +2249,22 @@ fn prepare_v3_exact_input_single(
         state_mutability: StateMutability::Payable,
     };
 
-    let deadline = U256::from(u64::MAX);
     let sqrt_price_limit_x96 = U256::zero();
 
-    let params_tokens = vec![
+    let mut params_tokens = vec![
         Token::Address(token_in),
         Token::Address(token_out),
         Token::Uint(U256::from(fee)),
         Token::Address(recipient),
-        Token::Uint(deadline),
+    ];
+    if router_kind == V3RouterKind::SwapRouter {
+        params_tokens.push(Token::Uint(deadline));
+    }
+    params_tokens.extend([
         Token::Uint(amount_in),
         Token::Uint(amount_out_min),
         Token::Uint(sqrt_price_limit_x96),
-    ];
+    ]);
 
     let data = function
         .encode_input(&[Token::Tuple(params_tokens)])
@@ -1141,24 +2273,273 @@ fn prepare_v3_exact_input_single(
     Ok((function, data.into(), U256::zero()))
 }
 
+/// Quoted amount and gas estimate of a decoded `quoteExactInput` or `quoteExactOutput`
+/// result: the output, or the input of an exact-output quote
+fn decode_v3_quote(decoded: &[Token]) -> Result<(U256, U256)> {
+    match decoded {
+        [Token::Uint(amount), _, _, Token::Uint(gas_estimate)] => Ok((*amount, *gas_estimate)),
+        _ => anyhow::bail!("Failed to extract output amount from V3 quote"),
+    }
+}
+
+/// Cost of `gas` at `gas_price`, in the smallest unit of a token worth `weth_value` per WETH
+fn gas_cost_in(gas: U256, gas_price: U256, weth_value: U256) -> U256 {
+    gas * gas_price * weth_value / U256::exp10(18)
+}
+
+/// Format `amount` of a token with `decimals` in human-readable form
+fn format_amount(amount: U256, decimals: u8) -> Result<String> {
+    let amount = erc20::to_decimal_units(amount, decimals)?;
+    Ok(format!("{:.prec$}", amount, prec = decimals as usize))
+}
+
+/// Report the best route of one fee tier, with amounts in units of the quoted token:
+/// the output token, or the input token in exact-output mode
+fn fee_tier_quote(
+    fee: u32,
+    best: &QuotedRoute,
+    mode: SwapMode,
+    decimals: u8,
+) -> Result<FeeTierQuote> {
+    let mut tier = FeeTierQuote {
+        fee,
+        route: Vec::new(),
+        estimated_output: None,
+        estimated_input: None,
+        estimated_gas: None,
+        net_output: None,
+        net_input: None,
+        error: None,
+    };
+    match best {
+        Ok((route, quote)) => {
+            let amount = Some(format_amount(quote.amount, decimals)?);
+            let net = quote
+                .net_amount
+                .map(|net| format_amount(net, decimals))
+                .transpose()?;
+            match mode {
+                SwapMode::ExactInput => (tier.estimated_output, tier.net_output) = (amount, net),
+                SwapMode::ExactOutput => (tier.estimated_input, tier.net_input) = (amount, net),
+            }
+            tier.route = route.hops();
+            tier.estimated_gas = quote.gas_estimate.map(|gas| gas.to_string());
+        }
+        Err(e) => tier.error = Some(format!("{e:#}")),
+    }
+    Ok(tier)
+}
+
+fn prepare_v3_exact_input(
+    router_kind: V3RouterKind,
+    path: Vec<u8>,
+    amount_in: U256,
+    amount_out_min: U256,
+    recipient: Address,
+    deadline: U256,
+) -> Result<(Function, Bytes, U256)> {
+    // As with exactInputSingle, SwapRouter02 dropped the deadline from ExactInputParams
+    let params_kind = match router_kind {
+        V3RouterKind::SwapRouter => vec![
+            ParamType::Bytes,
+            ParamType::Address,
+            ParamType::Uint(256),
+            ParamType::Uint(256),
+            ParamType::Uint(256),
+        ],
+        V3RouterKind::SwapRouter02 => vec![
+            ParamType::Bytes,
+            ParamType::Address,
+            ParamType::Uint(256),
+            ParamType::Uint(256),
+        ],
+    };
+
+    let function = Function {
+        name: "exactInput".to_string(),
+        inputs: vec![Param {
+            name: "params".to_string(),
+            kind: ParamType::Tuple(params_kind),
+            internal_type: None,
+        }],
+        outputs: vec![Param {
+            name: "amountOut".to_string(),
+            kind: ParamType::Uint(256),
+            internal_type: None,
+        }],
+        #[allow(deprecated)]
+        constant: None,
+        state_mutability: StateMutability::Payable,
+    };
+
+    let mut params_tokens = vec![Token::Bytes(path), Token::Address(recipient)];
+    if router_kind == V3RouterKind::SwapRouter {
+        params_tokens.push(Token::Uint(deadline));
+    }
+    params_tokens.extend([Token::Uint(amount_in), Token::Uint(amount_out_min)]);
+
+    let data = function
+        .encode_input(&[Token::Tuple(params_tokens)])
+        .context("Failed to encode exactInput")?;
+
+    Ok((function, data.into(), U256::zero()))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn prepare_v3_exact_output_single(
+    router_kind: V3RouterKind,
+    token_in: Address,
+    token_out: Address,
+    fee: u32,
+    amount_out: U256,
+    amount_in_max: U256,
+    recipient: Address,
+    deadline: U256,
+) -> Result<(Function, Bytes, U256)> {
+    // ExactOutputSingleParams mirror ExactInputSingleParams, and SwapRouter02 dropped the
+    // deadline here too
+    let params_kind = match router_kind {
+        V3RouterKind::SwapRouter => vec![
+            ParamType::Address,
+            ParamType::Address,
+            ParamType::Uint(24),
+            ParamType::Address,
+            ParamType::Uint(256),
+            ParamType::Uint(256),
+            ParamType::Uint(256),
+            ParamType::Uint(160),
+        ],
+        V3RouterKind::SwapRouter02 => vec![
+            ParamType::Address,
+            ParamType::Address,
+            ParamType::Uint(24),
+            ParamType::Address,
+            ParamType::Uint(256),
+            ParamType::Uint(256),
+            ParamType::Uint(160),
+        ],
+    };
+
+    let function = Function {
+        name: "exactOutputSingle".to_string(),
+        inputs: vec![Param {
+            name: "params".to_string(),
+            kind: ParamType::Tuple(params_kind),
+            internal_type: None,
+        }],
+        outputs: vec![Param {
+            name: "amountIn".to_string(),
+            kind: ParamType::Uint(256),
+            internal_type: None,
+        }],
+        #[allow(deprecated)]
+        constant: None,
+        state_mutability: StateMutability::Payable,
+    };
+
+    let sqrt_price_limit_x96 = U256::zero();
+
+    let mut params_tokens = vec![
+        Token::Address(token_in),
+        Token::Address(token_out),
+        Token::Uint(U256::from(fee)),
+        Token::Address(recipient),
+    ];
+    if router_kind == V3RouterKind::SwapRouter {
+        params_tokens.push(Token::Uint(deadline));
+    }
+    params_tokens.extend([
+        Token::Uint(amount_out),
+        Token::Uint(amount_in_max),
+        Token::Uint(sqrt_price_limit_x96),
+    ]);
+
+    let data = function
+        .encode_input(&[Token::Tuple(params_tokens)])
+        .context("Failed to encode exactOutputSingle")?;
+
+    Ok((function, data.into(), U256::zero()))
+}
+
+/// `exactOutput` along `path`, which runs from the output token back to the input token
+fn prepare_v3_exact_output(
+    router_kind: V3RouterKind,
+    path: Vec<u8>,
+    amount_out: U256,
+    amount_in_max: U256,
+    recipient: Address,
+    deadline: U256,
+) -> Result<(Function, Bytes, U256)> {
+    let params_kind = match router_kind {
+        V3RouterKind::SwapRouter => vec![
+            ParamType::Bytes,
+            ParamType::Address,
+            ParamType::Uint(256),
+            ParamType::Uint(256),
+            ParamType::Uint(256),
+        ],
+        V3RouterKind::SwapRouter02 => vec![
+            ParamType::Bytes,
+            ParamType::Address,
+            ParamType::Uint(256),
+            ParamType::Uint(256),
+        ],
+    };
+
+    let function = Function {
+        name: "exactOutput".to_string(),
+        inputs: vec![Param {
+            name: "params".to_string(),
+            kind: ParamType::Tuple(params_kind),
+            internal_type: None,
+        }],
+        outputs: vec![Param {
+            name: "amountIn".to_string(),
+            kind: ParamType::Uint(256),
+            internal_type: None,
+        }],
+        #[allow(deprecated)]
+        constant: None,
+        state_mutability: StateMutability::Payable,
+    };
+
+    let mut params_tokens = vec![Token::Bytes(path), Token::Address(recipient)];
+    if router_kind == V3RouterKind::SwapRouter {
+        params_tokens.push(Token::Uint(deadline));
+    }
+    params_tokens.extend([Token::Uint(amount_out), Token::Uint(amount_in_max)]);
+
+    let data = function
+        .encode_input(&[Token::Tuple(params_tokens)])
+        .context("Failed to encode exactOutput")?;
+
+    Ok((function, data.into(), U256::zero()))
+}
+
+#[allow(clippy::too_many_arguments)]
 fn prepare_v3_exact_input_single_native(
+    router_kind: V3RouterKind,
+    weth_address: Address,
     token_out: Address,
     fee: u32,
     amount_in: U256,
     amount_out_min: U256,
     recipient: Address,
+    deadline: U256,
 ) -> Result<(Function, Bytes, U256)> {
-    let weth_address = Address::from_str(WETH_ADDRESS)?;
     prepare_v3_exact_input_single(
+        router_kind,
         weth_address,
         token_out,
         fee,
         amount_in,
         amount_out_min,
         recipient,
+        deadline,
     )
 }
 
+/// The output of a simulated V3 swap, or its input for exactOutput(Single)
 fn decode_v3_swap_result(function: &Function, result: &Bytes) -> Result<U256> {
     let decoded = function
         .decode_output(result)
@@ -1169,3 +2550,88 @@ fn decode_v3_swap_result(function: &Function, result: &Bytes) -> Result<U256> {
         _ => anyhow::bail!("Failed to extract output amount from V3 swap result"),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn slippage(percent: &str) -> Decimal {
+        Decimal::from_str(percent).unwrap()
+    }
+
+    #[test]
+    fn parses_amounts_in_token_units() {
+        assert_eq!(parse_amount("1.5", 6).unwrap(), U256::from(1_500_000));
+        assert_eq!(parse_amount(".25", 2).unwrap(), U256::from(25));
+        assert_eq!(parse_amount("7", 0).unwrap(), U256::from(7));
+        // Digits beyond the token's decimals are dropped
+        assert_eq!(parse_amount("0.1234567", 6).unwrap(), U256::from(123_456));
+    }
+
+    #[test]
+    fn parses_amounts_beyond_the_decimal_range() {
+        let amount = parse_amount("100000000000000000000000000000", 18).unwrap();
+        assert_eq!(amount, U256::exp10(47));
+    }
+
+    #[test]
+    fn rejects_invalid_amounts() {
+        for amount in ["", ".", "-1", "1e18", "1.2.3", "abc"] {
+            assert!(parse_amount(amount, 18).is_err(), "{amount:?}");
+        }
+        assert!(parse_amount("1", 78).is_err());
+        assert!(parse_amount(&"9".repeat(78), 0).is_err());
+    }
+
+    #[test]
+    fn min_output_rounds_down() {
+        let output = U256::from(1_000_001);
+        assert_eq!(
+            calculate_min_output(output, slippage("0.5")).unwrap(),
+            U256::from(995_000)
+        );
+        assert_eq!(calculate_min_output(output, slippage("0")).unwrap(), output);
+        assert_eq!(
+            calculate_min_output(output, slippage("100")).unwrap(),
+            U256::zero()
+        );
+    }
+
+    #[test]
+    fn max_input_rounds_up() {
+        assert_eq!(
+            calculate_max_input(U256::from(1_000_001), slippage("0.5")).unwrap(),
+            U256::from(1_005_002)
+        );
+        assert_eq!(
+            calculate_max_input(U256::from(1000), slippage("0")).unwrap(),
+            U256::from(1000)
+        );
+    }
+
+    #[test]
+    fn slippage_bounds_work_beyond_the_decimal_range() {
+        let amount = U256::exp10(40);
+        assert_eq!(
+            calculate_min_output(amount, slippage("1")).unwrap(),
+            U256::exp10(38) * 99
+        );
+        assert_eq!(
+            calculate_max_input(amount, slippage("1")).unwrap(),
+            U256::exp10(38) * 101
+        );
+    }
+
+    #[test]
+    fn slippage_below_a_basis_point_is_rounded_down() {
+        let output = U256::from(1_000_000);
+        assert_eq!(
+            calculate_min_output(output, slippage("0.019")).unwrap(),
+            U256::from(999_900)
+        );
+        assert_eq!(
+            calculate_max_input(output, slippage("0.019")).unwrap(),
+            U256::from(1_000_100)
+        );
+    }
+}
@@ -0,0 +1,196 @@
+// Copyright 2025 chenjjiaa
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::{Context, Result};
+use ethabi::{Function, ParamType, Token};
+use ethers::types::{Address, U256};
+use rust_decimal::Decimal;
+use std::str::FromStr;
+use tracing::debug;
+
+use super::routing::Route;
+use super::{SwapProvider, UniswapVersion};
+use crate::abi;
+use crate::multicall::Multicall;
+
+impl SwapProvider {
+    /// Price impact of swapping `amount_in` for `amount_out` along `route`, as a percentage:
+    /// how far the execution price falls short of the route's mid price with the pool fees
+    /// taken out, so only the price moved by the trade counts.
+    ///
+    /// The mid price of each hop comes from the V2 pair reserves (`getReserves`) or the V3
+    /// pool's `slot0.sqrtPriceX96`. Pools are looked up on the factory, then read, in one
+    /// Multicall3 batch each.
+    pub(super) async fn price_impact(
+        &self,
+        route: &Route,
+        version: UniswapVersion,
+        amount_in: U256,
+        amount_out: U256,
+    ) -> Result<Decimal> {
+        let mid_price = match version {
+            UniswapVersion::V2 => self.v2_mid_price(route).await?,
+            UniswapVersion::V3 => self.v3_mid_price(route).await?,
+        };
+        if mid_price <= 0.0 || !mid_price.is_finite() {
+            anyhow::bail!("Route {route} has no mid price");
+        }
+
+        let execution_price = to_f64(amount_out)? / to_f64(amount_in)?;
+        let impact = (1.0 - execution_price / mid_price) * 100.0;
+        debug!(
+            "Route {} mid price {} (net of fees), execution price {}, impact {:.4}%",
+            route, mid_price, execution_price, impact
+        );
+
+        // A trade can't beat the mid price; a slightly negative impact is rounding
+        Decimal::try_from(impact.max(0.0))
+            .map(|impact| impact.round_dp(4))
+            .with_context(|| format!("Price impact {impact} is out of range"))
+    }
+
+    /// Product over the route of each pair's `reserve_out / reserve_in` in raw units, less
+    /// the 0.3% V2 fee per hop
+    async fn v2_mid_price(&self, route: &Route) -> Result<f64> {
+        let router = Address::from_str(self.chain.contracts.uniswap_v2_router)?;
+        let factory = abi::get_factory(&self.provider, router).await?;
+
+        let get_pair_fn = abi::get_pair_function();
+        let pairs = self
+            .hop_pools(factory, &get_pair_fn, route, |_| Vec::new())
+            .await?;
+
+        let get_reserves_fn = abi::get_reserves_function();
+        let mut multicall = Multicall::new(&self.provider, self.chain)?;
+        for pair in &pairs {
+            multicall.add_call(*pair, &get_reserves_fn, &[])?;
+        }
+
+        let mut price = 1.0;
+        for (result, hop) in multicall
+            .call()
+            .await
+            .context("Failed to read V2 pair reserves")?
+            .into_iter()
+            .zip(route.tokens.windows(2))
+        {
+            let (reserve0, reserve1) = match result?.as_slice() {
+                [Token::Uint(reserve0), Token::Uint(reserve1), _] => (*reserve0, *reserve1),
+                _ => anyhow::bail!("Unexpected getReserves result format"),
+            };
+            // Pair tokens are sorted by address
+            let (reserve_in, reserve_out) = if hop[0] < hop[1] {
+                (reserve0, reserve1)
+            } else {
+                (reserve1, reserve0)
+            };
+            price *= to_f64(reserve_out)? / to_f64(reserve_in)? * 0.997;
+        }
+        Ok(price)
+    }
+
+    /// Product over the route of each pool's `slot0` price in raw units, less the pool fee
+    async fn v3_mid_price(&self, route: &Route) -> Result<f64> {
+        let quoter = Address::from_str(self.chain.contracts.uniswap_v3_quoter_v2)?;
+        let factory = abi::get_factory(&self.provider, quoter).await?;
+
+        let get_pool_fn = abi::get_pool_function();
+        let pools = self
+            .hop_pools(factory, &get_pool_fn, route, |i| {
+                vec![Token::Uint(U256::from(route.fees[i]))]
+            })
+            .await?;
+
+        let slot0_fn = abi::view_function(
+            "slot0",
+            vec![],
+            vec![
+                ParamType::Uint(160),
+                ParamType::Int(24),
+                ParamType::Uint(16),
+                ParamType::Uint(16),
+                ParamType::Uint(16),
+                ParamType::Uint(8),
+                ParamType::Bool,
+            ],
+        );
+        let mut multicall = Multicall::new(&self.provider, self.chain)?;
+        for pool in &pools {
+            multicall.add_call(*pool, &slot0_fn, &[])?;
+        }
+
+        let mut price = 1.0;
+        for ((result, hop), fee) in multicall
+            .call()
+            .await
+            .context("Failed to read V3 pool slot0")?
+            .into_iter()
+            .zip(route.tokens.windows(2))
+            .zip(&route.fees)
+        {
+            let sqrt_price_x96 = match result?.first() {
+                Some(Token::Uint(sqrt_price_x96)) => *sqrt_price_x96,
+                _ => anyhow::bail!("Unexpected slot0 result format"),
+            };
+            // sqrtPriceX96 is the square root of token1 per token0 in raw units, as Q64.96
+            let price1 = (to_f64(sqrt_price_x96)? / 2f64.powi(96)).powi(2);
+            let hop_price = if hop[0] < hop[1] {
+                price1
+            } else {
+                1.0 / price1
+            };
+            price *= hop_price * (1.0 - f64::from(*fee) / 1e6);
+        }
+        Ok(price)
+    }
+
+    /// Pool of every hop of `route`, looked up with `get_pool_fn(token_in, token_out, ..)`
+    /// on `factory`, where `extra_args(i)` gives the arguments after the tokens of hop `i`
+    async fn hop_pools(
+        &self,
+        factory: Address,
+        get_pool_fn: &Function,
+        route: &Route,
+        extra_args: impl Fn(usize) -> Vec<Token>,
+    ) -> Result<Vec<Address>> {
+        let mut multicall = Multicall::new(&self.provider, self.chain)?;
+        for (i, hop) in route.tokens.windows(2).enumerate() {
+            let mut args = vec![Token::Address(hop[0]), Token::Address(hop[1])];
+            args.extend(extra_args(i));
+            multicall.add_call(factory, get_pool_fn, &args)?;
+        }
+
+        multicall
+            .call()
+            .await
+            .with_context(|| format!("Failed to call {}", get_pool_fn.name))?
+            .into_iter()
+            .zip(route.tokens.windows(2))
+            .map(|(result, hop)| match result?.first() {
+                Some(Token::Address(pool)) if !pool.is_zero() => Ok(*pool),
+                Some(Token::Address(_)) => {
+                    anyhow::bail!("No pool for {:?}/{:?}", hop[0], hop[1])
+                }
+                _ => anyhow::bail!("Unexpected {} result format", get_pool_fn.name),
+            })
+            .collect()
+    }
+}
+
+fn to_f64(amount: U256) -> Result<f64> {
+    amount
+        .to_string()
+        .parse()
+        .context("Failed to convert amount to f64")
+}
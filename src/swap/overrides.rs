@@ -0,0 +1,217 @@
+// Copyright 2025 chenjjiaa
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::{Context, Result};
+use ethabi::{Function, Token};
+use ethers::{
+    prelude::*,
+    providers::call_raw::{spoof, RawCall},
+    types::{transaction::eip2718::TypedTransaction, Address, Bytes, H256, U256},
+    utils::keccak256,
+};
+use tracing::{debug, warn};
+
+use super::SwapProvider;
+use crate::erc20;
+
+/// Mapping slots tried when the node can't list the storage a token reads
+/// (`eth_createAccessList`). Covers the usual layouts of OpenZeppelin and Solmate tokens
+const FALLBACK_MAPPING_SLOTS: u64 = 10;
+
+/// Outcome of simulating a swap transaction
+pub(super) struct Simulation {
+    /// Return data of the swap call, or why it failed
+    pub output: Result<Bytes>,
+    /// Gas estimate of the transaction, `None` if it couldn't be estimated
+    pub gas_estimate: Option<U256>,
+}
+
+impl SwapProvider {
+    /// Simulate `tx` from `sender` with `eth_call`, with state overrides granting the sender
+    /// `amount_in` of `token_in` and an allowance for `spender`, or the ETH the transaction
+    /// sends when `token_in` is `None`. Gas is estimated with the same overrides where the
+    /// node accepts them on `eth_estimateGas`, and without them otherwise.
+    ///
+    /// Overrides that can't be built are skipped with a warning, so the simulation may still
+    /// revert on balance or allowance checks.
+    pub(super) async fn simulate_swap(
+        &self,
+        tx: TypedTransaction,
+        sender: Address,
+        spender: Address,
+        token_in: Option<Address>,
+        amount_in: U256,
+    ) -> Simulation {
+        let mut state = spoof::state();
+        match token_in {
+            // Some ETH on top of the value, in case the node charges gas in eth_call
+            None => {
+                let value = tx.value().copied().unwrap_or_default();
+                state
+                    .account(sender)
+                    .balance(value.saturating_add(U256::exp10(18)));
+            }
+            Some(token) => {
+                if let Err(e) = self
+                    .grant_token(&mut state, token, sender, spender, amount_in)
+                    .await
+                {
+                    warn!(
+                        "Failed to override {:?} state, simulating without: {:#}",
+                        token, e
+                    );
+                }
+            }
+        }
+
+        let output = self
+            .provider
+            .call_raw(&tx)
+            .state(&state)
+            .await
+            .map_err(|e| anyhow::anyhow!("Swap simulation reverted: {e}"));
+
+        let gas_estimate = match self
+            .provider
+            .request::<_, U256>("eth_estimateGas", (&tx, BlockNumber::Latest, &state))
+            .await
+        {
+            Ok(gas) => Some(gas),
+            Err(e) => {
+                debug!("eth_estimateGas with state overrides failed: {}", e);
+                match self.provider.estimate_gas(&tx, None).await {
+                    Ok(gas) => Some(gas),
+                    Err(e) => {
+                        warn!("Failed to estimate gas for swap: {}", e);
+                        None
+                    }
+                }
+            }
+        };
+
+        Simulation {
+            output,
+            gas_estimate,
+        }
+    }
+
+    /// Add overrides to `state` setting `owner`'s balance of `token`, and its allowance for
+    /// `spender`, to `amount`
+    async fn grant_token(
+        &self,
+        state: &mut spoof::State,
+        token: Address,
+        owner: Address,
+        spender: Address,
+        amount: U256,
+    ) -> Result<()> {
+        let balance_of = erc20::balance_of_function();
+        let allowance = erc20::allowance_function();
+        let balance_args = [Token::Address(owner)];
+        let allowance_args = [Token::Address(owner), Token::Address(spender)];
+
+        let (balance_slot, allowance_slot) = futures::try_join!(
+            self.find_slot(token, &balance_of, &balance_args, || fallback_slots(&[
+                owner
+            ])),
+            self.find_slot(token, &allowance, &allowance_args, || {
+                fallback_slots(&[owner, spender])
+            }),
+        )?;
+
+        let value = H256::from_uint(&amount);
+        state.account(token).store(balance_slot, value);
+        state.account(token).store(allowance_slot, value);
+        debug!(
+            "Granting {} of {:?} to {:?} in slots {:?} and {:?}",
+            amount, token, owner, balance_slot, allowance_slot
+        );
+        Ok(())
+    }
+
+    /// The storage slot of `token` that `function(args)` returns, found by setting each
+    /// candidate slot to an arbitrary value and checking the call returns it. Candidates are
+    /// the slots the call reads according to `eth_createAccessList`, or `fallback()` if the
+    /// node doesn't support it
+    async fn find_slot(
+        &self,
+        token: Address,
+        function: &Function,
+        args: &[Token],
+        fallback: impl FnOnce() -> Vec<H256>,
+    ) -> Result<H256> {
+        let data = function.encode_input(args)?;
+        let tx: TypedTransaction = TransactionRequest::new().to(token).data(data).into();
+
+        let candidates = match self.provider.create_access_list(&tx, None).await {
+            Ok(access_list) => access_list
+                .access_list
+                .0
+                .into_iter()
+                .filter(|item| item.address == token)
+                .flat_map(|item| item.storage_keys)
+                .collect(),
+            Err(e) => {
+                debug!("eth_createAccessList failed, trying common slots: {}", e);
+                fallback()
+            }
+        };
+
+        // A value no real balance or allowance holds, so only the right slot returns it
+        let probe = U256::from_big_endian(&keccak256(b"eth-mcp storage slot probe"));
+        let found = futures::future::join_all(candidates.iter().map(|&slot| {
+            let tx = &tx;
+            async move {
+                let mut state = spoof::state();
+                state.account(token).store(slot, H256::from_uint(&probe));
+                let result = self.provider.call_raw(tx).state(&state).await.ok()?;
+                let value = erc20::decode_uint(function.decode_output(&result).ok()?).ok()?;
+                (value == probe).then_some(slot)
+            }
+        }))
+        .await;
+
+        found.into_iter().flatten().next().with_context(|| {
+            format!(
+                "No storage slot of {:?} holds its {} among {} candidates",
+                token,
+                function.name,
+                candidates.len()
+            )
+        })
+    }
+}
+
+/// Slots of `mapping[keys[0]][keys[1]]..` for the first [`FALLBACK_MAPPING_SLOTS`]
+/// mapping slots, in both the Solidity (`keccak(key . slot)`) and Vyper
+/// (`keccak(slot . key)`) layouts
+fn fallback_slots(keys: &[Address]) -> Vec<H256> {
+    let mut slots = Vec::new();
+    for slot in 0..FALLBACK_MAPPING_SLOTS {
+        for solidity in [true, false] {
+            let mut location = H256::from_low_u64_be(slot);
+            for key in keys {
+                let key = H256::from(*key);
+                let preimage = if solidity {
+                    [key.as_bytes(), location.as_bytes()].concat()
+                } else {
+                    [location.as_bytes(), key.as_bytes()].concat()
+                };
+                location = H256::from(keccak256(preimage));
+            }
+            slots.push(location);
+        }
+    }
+    slots
+}
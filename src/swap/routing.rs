@@ -0,0 +1,325 @@
+// Copyright 2025 chenjjiaa
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::{Context, Result};
+use ethers::types::{Address, U256};
+use std::fmt;
+use std::str::FromStr;
+use tracing::debug;
+
+use super::{RouteHop, SwapMode};
+use crate::chains::Chain;
+
+/// A candidate swap route: the tokens swapped through, from input to output
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Route {
+    pub tokens: Vec<Address>,
+    /// Fee tier of the pool used for each hop. Empty for V2 routes
+    pub fees: Vec<u32>,
+}
+
+impl Route {
+    /// Whether the route swaps through a single pool
+    pub fn is_direct(&self) -> bool {
+        self.tokens.len() == 2
+    }
+
+    /// The route packed as V3 `exactInput` and `quoteExactInput` expect it: each token
+    /// address (20 bytes) followed by the fee (3 bytes) of the pool to the next token
+    pub fn encode_v3_path(&self) -> Vec<u8> {
+        let mut path = Vec::with_capacity(self.tokens.len() * 23);
+        for (i, token) in self.tokens.iter().enumerate() {
+            path.extend_from_slice(token.as_bytes());
+            if let Some(fee) = self.fees.get(i) {
+                path.extend_from_slice(&fee.to_be_bytes()[1..]);
+            }
+        }
+        path
+    }
+
+    /// The same pools traversed from the output token to the input token, the order
+    /// V3 `exactOutput` and `quoteExactOutput` expect their path in
+    pub fn reversed(&self) -> Route {
+        Route {
+            tokens: self.tokens.iter().rev().copied().collect(),
+            fees: self.fees.iter().rev().copied().collect(),
+        }
+    }
+
+    pub fn hops(&self) -> Vec<RouteHop> {
+        self.tokens
+            .windows(2)
+            .enumerate()
+            .map(|(i, pair)| RouteHop {
+                token_in: format!("{:?}", pair[0]),
+                token_out: format!("{:?}", pair[1]),
+                fee: self.fees.get(i).copied(),
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for Route {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, token) in self.tokens.iter().enumerate() {
+            if i > 0 {
+                match self.fees.get(i - 1) {
+                    Some(fee) => write!(f, " -({fee})-> ")?,
+                    None => write!(f, " -> ")?,
+                }
+            }
+            write!(f, "{token:?}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Tokens tried between the input and output token: the wrapped native token and the
+/// chain's main stablecoins
+pub fn intermediate_tokens(chain: &Chain) -> Result<Vec<Address>> {
+    let contracts = &chain.contracts;
+    [
+        Some(contracts.weth),
+        Some(contracts.usdc),
+        contracts.usdt,
+        contracts.dai,
+    ]
+    .into_iter()
+    .flatten()
+    .map(|address| {
+        Address::from_str(address)
+            .with_context(|| format!("Invalid intermediate token address {address}"))
+    })
+    .collect()
+}
+
+/// The direct route, then one route through each intermediate token that isn't
+/// already an end of the swap
+pub fn v2_candidates(
+    token_in: Address,
+    token_out: Address,
+    intermediates: &[Address],
+) -> Vec<Route> {
+    let direct = Route {
+        tokens: vec![token_in, token_out],
+        fees: Vec::new(),
+    };
+    let via = intermediates
+        .iter()
+        .filter(|&&token| token != token_in && token != token_out)
+        .map(|&token| Route {
+            tokens: vec![token_in, token, token_out],
+            fees: Vec::new(),
+        });
+    std::iter::once(direct).chain(via).collect()
+}
+
+/// The routes of [`v2_candidates`], each with every combination of `fees` for its hops
+pub fn v3_candidates(
+    token_in: Address,
+    token_out: Address,
+    intermediates: &[Address],
+    fees: &[u32],
+) -> Vec<Route> {
+    let mut routes = Vec::new();
+    for route in v2_candidates(token_in, token_out, intermediates) {
+        let mut fee_paths: Vec<Vec<u32>> = vec![Vec::new()];
+        for _ in 1..route.tokens.len() {
+            fee_paths = fee_paths
+                .into_iter()
+                .flat_map(|path| {
+                    fees.iter().map(move |&fee| {
+                        let mut path = path.clone();
+                        path.push(fee);
+                        path
+                    })
+                })
+                .collect();
+        }
+        routes.extend(fee_paths.into_iter().map(|fees| Route {
+            tokens: route.tokens.clone(),
+            fees,
+        }));
+    }
+    routes
+}
+
+/// Quote of one candidate route
+#[derive(Debug, Clone, Copy)]
+pub struct Quote {
+    /// Quoted amount: the output of an exact-input swap, or the input of an exact-output
+    /// swap, in the smallest unit of that token
+    pub amount: U256,
+    /// Gas used by the swap as estimated by the quoter (V3 only)
+    pub gas_estimate: Option<U256>,
+    /// `amount` net of the cost of `gas_estimate` valued in the same token: the output
+    /// minus the gas cost, or the input plus it. `None` if the gas cost couldn't be valued
+    pub net_amount: Option<U256>,
+}
+
+impl Quote {
+    pub fn new(amount: U256) -> Self {
+        Self {
+            amount,
+            gas_estimate: None,
+            net_amount: None,
+        }
+    }
+
+    /// Amount routes are ranked by: the net amount where known, otherwise the amount
+    pub fn score(&self) -> U256 {
+        self.net_amount.unwrap_or(self.amount)
+    }
+
+    /// Whether this quote beats `other`: more output, or less input for exact-output swaps
+    pub fn is_better_than(&self, other: &Quote, mode: SwapMode) -> bool {
+        match mode {
+            SwapMode::ExactInput => self.score() > other.score(),
+            SwapMode::ExactOutput => self.score() < other.score(),
+        }
+    }
+}
+
+/// The best route among some candidates and its quote, or why none could be quoted
+pub type QuotedRoute = Result<(Route, Quote)>;
+
+/// The candidate with the best quote, given one quote per candidate in order. Routes are
+/// ranked by their amount net of gas where it is known. Fails if no route could be quoted
+pub fn best_route(candidates: &[Route], quotes: &[Result<Quote>], mode: SwapMode) -> QuotedRoute {
+    let mut best: Option<(&Route, Quote)> = None;
+    let mut direct_error = None;
+
+    for (route, quote) in candidates.iter().zip(quotes) {
+        match quote {
+            Ok(quote) if !quote.amount.is_zero() => {
+                debug!(
+                    "Route {} quotes {} (net {:?})",
+                    route, quote.amount, quote.net_amount
+                );
+                if best
+                    .as_ref()
+                    .is_none_or(|(_, best_quote)| quote.is_better_than(best_quote, mode))
+                {
+                    best = Some((route, *quote));
+                }
+            }
+            Ok(_) => {
+                debug!("Route {} quotes zero", route);
+                if route.is_direct() && direct_error.is_none() {
+                    direct_error = Some("zero amount".to_string());
+                }
+            }
+            Err(e) => {
+                debug!("Route {} failed: {:#}", route, e);
+                if route.is_direct() && direct_error.is_none() {
+                    direct_error = Some(format!("{e:#}"));
+                }
+            }
+        }
+    }
+
+    let (route, quote) = best.with_context(|| {
+        format!(
+            "No pool route found among {} candidates (direct route: {})",
+            candidates.len(),
+            direct_error.as_deref().unwrap_or("not tried")
+        )
+    })?;
+    debug!("Best of {} routes: {}", candidates.len(), route);
+    Ok((route.clone(), quote))
+}
+
+/// For each fee tier in `fees`, the best of the candidates whose pools all use that tier
+pub fn best_route_per_fee_tier(
+    candidates: &[Route],
+    quotes: &[Result<Quote>],
+    fees: &[u32],
+    mode: SwapMode,
+) -> Vec<(u32, QuotedRoute)> {
+    fees.iter()
+        .map(|&fee| {
+            let (tier_candidates, tier_quotes): (Vec<Route>, Vec<Result<Quote>>) = candidates
+                .iter()
+                .zip(quotes)
+                .filter(|(route, _)| route.fees.iter().all(|&route_fee| route_fee == fee))
+                .map(|(route, quote)| {
+                    let quote = match quote {
+                        Ok(quote) => Ok(*quote),
+                        Err(e) => Err(anyhow::anyhow!("{e:#}")),
+                    };
+                    (route.clone(), quote)
+                })
+                .unzip();
+            (fee, best_route(&tier_candidates, &tier_quotes, mode))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const USDC: &str = "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48";
+    const WETH: &str = "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2";
+    const DAI: &str = "0x6B175474E89094C44Da98b954EedeAC495271d0F";
+
+    fn address(address: &str) -> Address {
+        Address::from_str(address).unwrap()
+    }
+
+    /// USDC -(500)-> WETH -(3000)-> DAI
+    fn route() -> Route {
+        Route {
+            tokens: vec![address(USDC), address(WETH), address(DAI)],
+            fees: vec![500, 3000],
+        }
+    }
+
+    fn path(parts: &[&str]) -> Vec<u8> {
+        hex::decode(parts.concat().replace("0x", "")).unwrap()
+    }
+
+    #[test]
+    fn encodes_v3_paths() {
+        assert_eq!(
+            route().encode_v3_path(),
+            path(&[USDC, "0001f4", WETH, "000bb8", DAI])
+        );
+    }
+
+    #[test]
+    fn exact_output_paths_start_at_the_output_token() {
+        let reversed = route().reversed();
+        assert_eq!(
+            reversed.tokens,
+            [address(DAI), address(WETH), address(USDC)]
+        );
+        // Each fee still sits between the two tokens of its pool
+        assert_eq!(
+            reversed.encode_v3_path(),
+            path(&[DAI, "000bb8", WETH, "0001f4", USDC])
+        );
+        assert_eq!(reversed.reversed(), route());
+    }
+
+    #[test]
+    fn direct_v3_path_is_one_pool() {
+        let route = Route {
+            tokens: vec![address(USDC), address(WETH)],
+            fees: vec![100],
+        };
+        assert_eq!(route.encode_v3_path(), path(&[USDC, "000064", WETH]));
+        assert_eq!(route.encode_v3_path().len(), 43);
+    }
+}
@@ -0,0 +1,281 @@
+// Copyright 2025 chenjjiaa
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::{Context, Result};
+use ethabi::{Function, Param, ParamType, StateMutability, Token};
+use ethers::{
+    prelude::*,
+    types::{Address, Bytes, U256, U64},
+};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
+
+use super::{
+    format_amount, parse_sender, BuildSwapTransactionInput, SimulationStatus, SwapInput,
+    SwapParties, SwapProvider, SwapTransactionOutput, UnsignedTransaction, NO_DEADLINE,
+};
+use crate::chains::V3RouterKind;
+
+/// Validity of swap transactions without an explicit deadline, as in the Uniswap app
+const DEFAULT_DEADLINE_SECS: u64 = 30 * 60;
+
+/// Margin added to the simulated gas, as state can change before the transaction is mined
+const GAS_LIMIT_BUFFER_PERCENT: u64 = 20;
+
+/// Gas limit of swaps whose gas couldn't be estimated
+const DEFAULT_SWAP_GAS_LIMIT: u64 = 300_000;
+
+impl SwapProvider {
+    /// Build the unsigned EIP-1559 transaction of a swap from `input.sender`, paying out to
+    /// `input.recipient`. The swap is routed and simulated as in [`SwapProvider::estimate_swap`];
+    /// the gas limit is the simulated gas plus [`GAS_LIMIT_BUFFER_PERCENT`], fees come from
+    /// `eth_feeHistory` and the nonce from the sender's pending transaction count, after any
+    /// approvals the swap still needs
+    pub async fn build_swap_transaction(
+        &self,
+        input: BuildSwapTransactionInput,
+    ) -> Result<SwapTransactionOutput> {
+        let sender = parse_sender(Some(&input.sender))?;
+        let recipient = match &input.recipient {
+            Some(recipient) => parse_sender(Some(recipient)).context("Invalid recipient")?,
+            None => sender,
+        };
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .context("System clock is before the Unix epoch")?
+            .as_secs();
+        let deadline = now + input.deadline_secs.unwrap_or(DEFAULT_DEADLINE_SECS);
+
+        let swap_input = SwapInput {
+            from_token: input.from_token,
+            to_token: input.to_token,
+            amount: input.amount,
+            slippage_tolerance: input.slippage_tolerance,
+            version: input.version,
+            mode: input.mode,
+            pool_fee: input.pool_fee,
+            chain: input.chain,
+            chain_id: input.chain_id,
+            sender: Some(input.sender),
+        };
+        let parties = SwapParties {
+            sender,
+            recipient,
+            deadline: U256::from(deadline),
+        };
+
+        let (prepared, fees, pending_nonce) = futures::try_join!(
+            self.prepare_swap(swap_input, parties),
+            async {
+                self.provider
+                    .estimate_eip1559_fees(None)
+                    .await
+                    .context("Failed to estimate EIP-1559 fees")
+            },
+            async {
+                self.provider
+                    .get_transaction_count(sender, Some(BlockNumber::Pending.into()))
+                    .await
+                    .context("Failed to get the sender's nonce")
+            },
+        )?;
+        let (max_fee_per_gas, max_priority_fee_per_gas) = fees;
+        let swap = prepared.output;
+
+        let mut warnings = Vec::new();
+        let gas_limit = match prepared.gas_estimate {
+            Some(gas) => gas + gas * GAS_LIMIT_BUFFER_PERCENT / 100,
+            None => {
+                warnings.push(format!(
+                    "Gas couldn't be estimated, so the gas limit is a default of {DEFAULT_SWAP_GAS_LIMIT}"
+                ));
+                U256::from(DEFAULT_SWAP_GAS_LIMIT)
+            }
+        };
+        if swap.simulation == SimulationStatus::QuoteOnly {
+            warnings.push(format!(
+                "The swap failed in simulation and may revert: {}",
+                swap.simulation_error.as_deref().unwrap_or("unknown error")
+            ));
+        }
+        if let Some(warning) = &swap.price_impact_warning {
+            warnings.push(warning.clone());
+        }
+
+        // Approvals are sent first, each taking a nonce
+        let approvals = swap
+            .allowance
+            .as_ref()
+            .map_or(0, |allowance| allowance.approvals.len());
+        if approvals > 0 {
+            warn!(
+                "{:?} needs {} approval(s) before the swap",
+                sender, approvals
+            );
+            warnings.push(format!(
+                "Send the {approvals} approval transaction(s) under `swap.allowance.approvals` first, with nonces from {pending_nonce}; the swap transaction uses the nonce after them"
+            ));
+        }
+        let nonce = pending_nonce + approvals;
+
+        let transaction = UnsignedTransaction {
+            tx_type: U64::from(2),
+            chain_id: U64::from(self.chain.id),
+            from: sender,
+            to: match prepared.tx.to {
+                Some(NameOrAddress::Address(to)) => to,
+                _ => anyhow::bail!("Swap transaction has no router address"),
+            },
+            nonce,
+            gas: gas_limit,
+            max_fee_per_gas,
+            max_priority_fee_per_gas,
+            value: prepared.tx.value.unwrap_or_default(),
+            data: prepared.tx.data.unwrap_or_default(),
+        };
+        info!(
+            "Built swap transaction from {:?}: nonce {}, gas limit {}, max fee {} wei",
+            sender, nonce, gas_limit, max_fee_per_gas
+        );
+
+        Ok(SwapTransactionOutput {
+            transaction,
+            sender: format!("{sender:?}"),
+            recipient: format!("{recipient:?}"),
+            deadline,
+            gas_limit: gas_limit.to_string(),
+            max_fee_per_gas_gwei: format_amount(max_fee_per_gas, 9)?,
+            max_priority_fee_per_gas_gwei: format_amount(max_priority_fee_per_gas, 9)?,
+            max_gas_cost_eth: format_amount(gas_limit * max_fee_per_gas, 18)?,
+            nonce: nonce.as_u64(),
+            chain_id: self.chain.id,
+            warnings,
+            swap,
+            ens_names: Vec::new(),
+        })
+    }
+}
+
+/// Calldata running `calls` on a V3 router, and whether it is a `multicall`. A single call
+/// is sent as-is unless it has a deadline the router can't check otherwise: SwapRouter02
+/// swap parameters have no deadline, so it goes through `multicall(uint256 deadline, ..)`
+pub(super) fn v3_router_calldata(
+    router_kind: V3RouterKind,
+    mut calls: Vec<Bytes>,
+    deadline: U256,
+) -> Result<(Bytes, bool)> {
+    let needs_deadline =
+        router_kind == V3RouterKind::SwapRouter02 && deadline != U256::from(NO_DEADLINE);
+    if calls.len() == 1 && !needs_deadline {
+        return Ok((calls.remove(0), false));
+    }
+
+    let data = Token::Array(
+        calls
+            .into_iter()
+            .map(|call| Token::Bytes(call.to_vec()))
+            .collect(),
+    );
+    let (inputs, args) = if needs_deadline {
+        (
+            vec![
+                param("deadline", ParamType::Uint(256)),
+                param("data", ParamType::Array(Box::new(ParamType::Bytes))),
+            ],
+            vec![Token::Uint(deadline), data],
+        )
+    } else {
+        (
+            vec![param("data", ParamType::Array(Box::new(ParamType::Bytes)))],
+            vec![data],
+        )
+    };
+    let function = router_function("multicall", inputs, multicall_outputs());
+    let calldata = function
+        .encode_input(&args)
+        .context("Failed to encode multicall")?;
+    Ok((calldata.into(), true))
+}
+
+/// Return data of the first call in the result of [`v3_router_calldata`]
+pub(super) fn first_v3_router_result(result: Bytes, is_multicall: bool) -> Result<Bytes> {
+    if !is_multicall {
+        return Ok(result);
+    }
+
+    let function = router_function("multicall", Vec::new(), multicall_outputs());
+    match function
+        .decode_output(&result)
+        .context("Failed to decode multicall result")?
+        .first()
+    {
+        Some(Token::Array(results)) => match results.first() {
+            Some(Token::Bytes(first)) => Ok(first.clone().into()),
+            _ => anyhow::bail!("Multicall returned no results"),
+        },
+        _ => anyhow::bail!("Unexpected multicall result format"),
+    }
+}
+
+/// `unwrapWETH9(uint256 amountMinimum, address recipient)`, which sends the router's WETH
+/// to `recipient` as ETH
+pub(super) fn prepare_v3_unwrap_weth9(amount_minimum: U256, recipient: Address) -> Result<Bytes> {
+    let function = router_function(
+        "unwrapWETH9",
+        vec![
+            param("amountMinimum", ParamType::Uint(256)),
+            param("recipient", ParamType::Address),
+        ],
+        Vec::new(),
+    );
+    let data = function
+        .encode_input(&[Token::Uint(amount_minimum), Token::Address(recipient)])
+        .context("Failed to encode unwrapWETH9")?;
+    Ok(data.into())
+}
+
+/// `refundETH()`, which returns the router's ETH balance to the caller
+pub(super) fn prepare_v3_refund_eth() -> Result<Bytes> {
+    let function = router_function("refundETH", Vec::new(), Vec::new());
+    let data = function
+        .encode_input(&[])
+        .context("Failed to encode refundETH")?;
+    Ok(data.into())
+}
+
+fn router_function(name: &str, inputs: Vec<Param>, outputs: Vec<Param>) -> Function {
+    Function {
+        name: name.to_string(),
+        inputs,
+        outputs,
+        #[allow(deprecated)]
+        constant: None,
+        state_mutability: StateMutability::Payable,
+    }
+}
+
+fn multicall_outputs() -> Vec<Param> {
+    vec![param(
+        "results",
+        ParamType::Array(Box::new(ParamType::Bytes)),
+    )]
+}
+
+fn param(name: &str, kind: ParamType) -> Param {
+    Param {
+        name: name.to_string(),
+        kind,
+        internal_type: None,
+    }
+}
@@ -0,0 +1,152 @@
+// Copyright 2025 chenjjiaa
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::{Context, Result};
+use ethers::types::Address;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::str::FromStr;
+use tracing::info;
+
+use crate::chains::Chain;
+use crate::config::TokenRegistryConfig;
+
+/// Token list bundled with the server
+const BUNDLED_TOKEN_LIST: &str = include_str!("tokens/default.tokenlist.json");
+
+/// A token list in the Uniswap token list format (https://tokenlists.org). Fields the
+/// registry doesn't use are ignored
+#[derive(Debug, Deserialize)]
+struct TokenList {
+    name: String,
+    tokens: Vec<TokenListEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TokenListEntry {
+    chain_id: u64,
+    address: String,
+    symbol: String,
+    name: String,
+    decimals: u8,
+}
+
+/// A token from one of the registry's token lists
+#[derive(Debug, Clone)]
+pub struct RegisteredToken {
+    pub address: Address,
+    pub symbol: String,
+    pub name: String,
+    pub decimals: u8,
+    /// Name of the token list the token was first found in
+    pub list: String,
+}
+
+/// Tokens from the bundled and configured token lists, used to resolve symbols to
+/// addresses and decimals per chain
+pub struct TokenRegistry {
+    /// Tokens keyed by chain ID and lowercase symbol. More than one token under a key
+    /// makes the symbol ambiguous on that chain
+    by_symbol: HashMap<(u64, String), Vec<RegisteredToken>>,
+}
+
+impl TokenRegistry {
+    pub fn from_config(config: &TokenRegistryConfig) -> Result<Self> {
+        let mut registry = Self {
+            by_symbol: HashMap::new(),
+        };
+
+        if config.bundled {
+            registry
+                .add_list(BUNDLED_TOKEN_LIST)
+                .context("Invalid bundled token list")?;
+        }
+        for path in &config.files {
+            let contents = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read token list {}", path.display()))?;
+            registry
+                .add_list(&contents)
+                .with_context(|| format!("Invalid token list {}", path.display()))?;
+        }
+
+        info!(
+            "Token registry: {} tokens from {} lists",
+            registry.by_symbol.values().map(Vec::len).sum::<usize>(),
+            usize::from(config.bundled) + config.files.len()
+        );
+        Ok(registry)
+    }
+
+    fn add_list(&mut self, json: &str) -> Result<()> {
+        let list: TokenList = serde_json::from_str(json).context("Failed to parse token list")?;
+
+        for entry in list.tokens {
+            let address = Address::from_str(&entry.address).with_context(|| {
+                format!(
+                    "Invalid address {} for token {}",
+                    entry.address, entry.symbol
+                )
+            })?;
+
+            let tokens = self
+                .by_symbol
+                .entry((entry.chain_id, entry.symbol.to_lowercase()))
+                .or_default();
+            // A token listed in several lists is registered once
+            if tokens.iter().any(|token| token.address == address) {
+                continue;
+            }
+            tokens.push(RegisteredToken {
+                address,
+                symbol: entry.symbol,
+                name: entry.name,
+                decimals: entry.decimals,
+                list: list.name.clone(),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Look up `symbol` (case-insensitive) on `chain`. Returns `None` if no list has it,
+    /// and an error naming every candidate if several tokens share the symbol. On chains
+    /// whose gas token isn't ether, "ETH" means bridged WETH
+    pub fn resolve_symbol(&self, chain: &Chain, symbol: &str) -> Result<Option<&RegisteredToken>> {
+        let mut symbol_lower = symbol.trim().to_lowercase();
+        if symbol_lower == "eth" && !chain.is_native_token("ETH") {
+            symbol_lower = "weth".to_string();
+        }
+        let key = (chain.id, symbol_lower);
+        match self.by_symbol.get(&key).map(Vec::as_slice) {
+            None | Some([]) => Ok(None),
+            Some([token]) => Ok(Some(token)),
+            Some(tokens) => {
+                let candidates: Vec<String> = tokens
+                    .iter()
+                    .map(|token| {
+                        format!("{:?} ({}, from {})", token.address, token.name, token.list)
+                    })
+                    .collect();
+                anyhow::bail!(
+                    "Symbol {} is ambiguous on {}, it matches {} tokens: {}. Pass the token address instead",
+                    symbol.trim(),
+                    chain.name,
+                    tokens.len(),
+                    candidates.join("; ")
+                )
+            }
+        }
+    }
+}
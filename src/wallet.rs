@@ -0,0 +1,588 @@
+// Copyright 2025 chenjjiaa
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::{Context, Result};
+use ethers::{
+    prelude::*,
+    signers::{coins_bip39::English, LocalWallet, MnemonicBuilder, Signer},
+    types::{
+        transaction::eip2718::TypedTransaction, Address, Bytes, Eip1559TransactionRequest, H256,
+        U256, U64,
+    },
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::env;
+use thiserror::Error;
+use tracing::{info, warn};
+
+use crate::config::WalletConfig;
+use crate::ens::ResolvedName;
+use crate::rpc::RpcClientError;
+use crate::swap::{BuildSwapTransactionInput, SwapTransactionOutput, UnsignedTransaction};
+
+/// Password of the keystores in `wallet.keystores`
+const KEYSTORE_PASSWORD_ENV: &str = "WALLET_KEYSTORE_PASSWORD";
+/// BIP-39 mnemonic the accounts at `wallet.derivation_paths` are derived from
+const MNEMONIC_ENV: &str = "WALLET_MNEMONIC";
+/// Optional BIP-39 passphrase of the mnemonic
+const MNEMONIC_PASSPHRASE_ENV: &str = "WALLET_MNEMONIC_PASSPHRASE";
+
+/// Margin added to the node's gas estimate of transactions sent without a gas limit
+const GAS_LIMIT_BUFFER_PERCENT: u64 = 20;
+
+/// Gas limit of a swap approval that can't be estimated because it depends on an earlier
+/// one to the same contract (setting an allowance after resetting it to zero)
+const DEPENDENT_APPROVAL_GAS_LIMIT: u64 = 100_000;
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct SendTransactionInput {
+    /// Wallet account to send from. Defaults to the wallet's first account
+    #[serde(default)]
+    pub from: Option<String>,
+    /// Recipient address or ENS name
+    pub to: String,
+    /// ETH to send (in human-readable format, e.g., "0.1"). Defaults to 0
+    #[serde(default)]
+    pub value: Option<String>,
+    /// Hex-encoded calldata, e.g. an approval returned by `get_allowance`
+    #[serde(default)]
+    pub data: Option<String>,
+    /// Gas limit. Defaults to the node's estimate plus 20%
+    #[serde(default)]
+    pub gas_limit: Option<u64>,
+    /// Chain name (e.g. "mainnet", "arbitrum", "base"). Defaults to the server's default chain
+    #[serde(default)]
+    pub chain: Option<String>,
+    /// Chain ID (e.g. 1, 42161, 8453). Alternative to `chain`
+    #[serde(default)]
+    pub chain_id: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SentTransactionOutput {
+    /// Hash of the broadcast transaction
+    pub transaction_hash: String,
+    /// The transaction as signed
+    pub transaction: UnsignedTransaction,
+    /// Chain the transaction was sent on
+    pub chain: String,
+    /// ENS names in the input and the addresses they resolved to
+    pub ens_names: Vec<ResolvedName>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ExecuteSwapInput {
+    /// The swap, as for `build_swap_transaction`. The sender must be a wallet account
+    #[serde(flatten)]
+    pub swap: BuildSwapTransactionInput,
+    /// Send the approvals the swap needs before it. Without this, a swap that needs
+    /// approvals is rejected
+    #[serde(default)]
+    pub approve: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExecuteSwapOutput {
+    /// Hashes of the approvals sent before the swap, in order
+    pub approval_hashes: Vec<String>,
+    /// Hash of the swap transaction
+    pub transaction_hash: String,
+    /// The swap transaction as signed, with the estimate it was built from
+    #[serde(flatten)]
+    pub built: SwapTransactionOutput,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PartialSwapOutput {
+    /// Why sending stopped
+    pub error: String,
+    /// Hashes of the approvals sent before the failure, in order
+    pub approval_hashes: Vec<String>,
+    /// Nonce of the approval or swap that failed to send. Unless the error says it may have
+    /// been broadcast, this nonce and the ones after it are still unused
+    pub failed_nonce: u64,
+    /// Chain the transactions were sent on
+    pub chain: String,
+}
+
+/// A transaction that failed to send, and whether it may have reached the network anyway
+#[derive(Debug, Error)]
+pub enum SendError {
+    /// Signing failed or the node rejected the transaction, so it wasn't broadcast
+    #[error("{0:#}")]
+    NotSent(anyhow::Error),
+    /// The broadcast failed without the node rejecting the transaction, which may still be
+    /// mined
+    #[error("{error:#}; transaction {hash:?} may still have been broadcast")]
+    Unknown { hash: H256, error: anyhow::Error },
+}
+
+impl SendError {
+    /// Whether the transaction may have been broadcast
+    pub fn may_be_broadcast(&self) -> bool {
+        matches!(self, Self::Unknown { .. })
+    }
+}
+
+/// A swap that failed to execute, with the approvals that went out before the failure
+#[derive(Debug, Error)]
+#[error("{error:#}{}", sent_approvals(approval_hashes))]
+pub struct SwapSendError {
+    /// Number of transactions, approvals first and then the swap, that were or may have
+    /// been broadcast
+    pub sent: usize,
+    /// Hashes of the approvals that were sent, in order
+    pub approval_hashes: Vec<String>,
+    /// Nonce of the approval or swap that failed to send, `None` if sending never started
+    pub failed_nonce: Option<u64>,
+    pub error: anyhow::Error,
+}
+
+impl From<anyhow::Error> for SwapSendError {
+    fn from(error: anyhow::Error) -> Self {
+        Self {
+            sent: 0,
+            approval_hashes: Vec::new(),
+            failed_nonce: None,
+            error,
+        }
+    }
+}
+
+fn sent_approvals(hashes: &[String]) -> String {
+    if hashes.is_empty() {
+        String::new()
+    } else {
+        format!(" (approvals already sent: {})", hashes.join(", "))
+    }
+}
+
+/// Local signing accounts, loaded from encrypted keystores and a BIP-39 mnemonic
+pub struct Wallet {
+    accounts: Vec<LocalWallet>,
+}
+
+impl Wallet {
+    /// Load the accounts of the wallet, or `None` if it isn't enabled. Secrets are only read
+    /// from the environment: the keystore password from `WALLET_KEYSTORE_PASSWORD`, the
+    /// mnemonic from `WALLET_MNEMONIC` and its passphrase from `WALLET_MNEMONIC_PASSPHRASE`
+    pub fn from_config(config: &WalletConfig) -> Result<Option<Self>> {
+        if !config.enabled {
+            return Ok(None);
+        }
+
+        let mut accounts = Vec::new();
+        if !config.keystores.is_empty() {
+            let password = env::var(KEYSTORE_PASSWORD_ENV).with_context(|| {
+                format!("{KEYSTORE_PASSWORD_ENV} must be set to decrypt wallet.keystores")
+            })?;
+            for keystore in &config.keystores {
+                let account =
+                    LocalWallet::decrypt_keystore(keystore, &password).with_context(|| {
+                        format!("Failed to decrypt keystore {}", keystore.display())
+                    })?;
+                info!(
+                    "Loaded wallet account {:?} from {}",
+                    account.address(),
+                    keystore.display()
+                );
+                accounts.push(account);
+            }
+        }
+
+        if let Ok(mnemonic) = env::var(MNEMONIC_ENV) {
+            let passphrase = env::var(MNEMONIC_PASSPHRASE_ENV).ok();
+            for path in &config.derivation_paths {
+                let mut builder = MnemonicBuilder::<English>::default()
+                    .phrase(mnemonic.trim())
+                    .derivation_path(path)
+                    .with_context(|| format!("Invalid derivation path {path}"))?;
+                if let Some(passphrase) = &passphrase {
+                    builder = builder.password(passphrase);
+                }
+                let account = builder
+                    .build()
+                    .with_context(|| format!("Failed to derive the account at {path}"))?;
+                info!(
+                    "Loaded wallet account {:?} from the mnemonic at {}",
+                    account.address(),
+                    path
+                );
+                accounts.push(account);
+            }
+        }
+
+        if accounts.is_empty() {
+            anyhow::bail!(
+                "The wallet is enabled but has no accounts: set wallet.keystores or {MNEMONIC_ENV}"
+            );
+        }
+        Ok(Some(Self { accounts }))
+    }
+
+    /// Addresses of the wallet's accounts, the default sender first
+    pub fn addresses(&self) -> Vec<Address> {
+        self.accounts.iter().map(Signer::address).collect()
+    }
+
+    /// The account for `from`, or the first account
+    pub fn account(&self, from: Option<Address>) -> Result<Address> {
+        match from {
+            Some(from) => self
+                .signer(from)
+                .map(Signer::address)
+                .with_context(|| format!("{from:?} is not a wallet account")),
+            None => Ok(self.accounts[0].address()),
+        }
+    }
+
+    /// Sign `tx` with the account of its `from` address and broadcast it with
+    /// `eth_sendRawTransaction`. Unless the node answers with an error, a failed broadcast
+    /// may still have gone out, which the error reports with the transaction's hash
+    pub async fn send_transaction<M>(
+        &self,
+        provider: &M,
+        tx: &UnsignedTransaction,
+    ) -> Result<H256, SendError>
+    where
+        M: Middleware,
+        M::Error: 'static,
+    {
+        let signer = self.signer(tx.from).ok_or_else(|| {
+            SendError::NotSent(anyhow::anyhow!("{:?} is not a wallet account", tx.from))
+        })?;
+
+        let typed = tx.to_typed_transaction();
+        let signature = signer
+            .sign_transaction(&typed)
+            .await
+            .map_err(|e| SendError::NotSent(anyhow::anyhow!("Failed to sign transaction: {e}")))?;
+        let raw: Bytes = typed.rlp_signed(&signature);
+        let hash = typed.hash(&signature);
+
+        if let Err(e) = provider.send_raw_transaction(raw).await {
+            let error = anyhow::anyhow!("Failed to broadcast transaction: {e}");
+            return Err(if rejected_by_node(&e) {
+                SendError::NotSent(error)
+            } else {
+                SendError::Unknown { hash, error }
+            });
+        }
+        info!(
+            "Sent transaction {:?} from {:?} with nonce {}",
+            hash, tx.from, tx.nonce
+        );
+        Ok(hash)
+    }
+
+    fn signer(&self, address: Address) -> Option<&LocalWallet> {
+        self.accounts
+            .iter()
+            .find(|account| account.address() == address)
+    }
+}
+
+/// Whether a broadcast failed because the node answered with an error, so the transaction
+/// wasn't accepted. Errors saying the transaction is already known mean an earlier attempt,
+/// e.g. one that timed out before failing over, reached a node
+fn rejected_by_node(error: &(dyn std::error::Error + 'static)) -> bool {
+    let Some(ProviderError::JsonRpcClientError(error)) = error.downcast_ref() else {
+        return false;
+    };
+    match error.downcast_ref() {
+        Some(RpcClientError::Client(HttpClientError::JsonRpcError(e))) => {
+            let message = e.message.to_lowercase();
+            !["already known", "known transaction", "nonce too low"]
+                .iter()
+                .any(|known| message.contains(known))
+        }
+        _ => false,
+    }
+}
+
+/// An EIP-1559 transaction from `from`, with the sender's pending nonce, fees from
+/// `eth_feeHistory`, and the node's gas estimate plus [`GAS_LIMIT_BUFFER_PERCENT`] unless
+/// `gas_limit` is given
+pub async fn fill_transaction<M: Middleware>(
+    provider: &M,
+    chain_id: u64,
+    from: Address,
+    to: Address,
+    value: U256,
+    data: Bytes,
+    gas_limit: Option<u64>,
+) -> Result<UnsignedTransaction> {
+    let request = Eip1559TransactionRequest::new()
+        .from(from)
+        .to(to)
+        .value(value)
+        .data(data.clone())
+        .chain_id(chain_id);
+
+    let gas = async {
+        match gas_limit {
+            Some(gas_limit) => Ok(U256::from(gas_limit)),
+            None => provider
+                .estimate_gas(&request.clone().into(), None)
+                .await
+                .map(|gas| gas + gas * GAS_LIMIT_BUFFER_PERCENT / 100)
+                .map_err(|e| anyhow::anyhow!("Failed to estimate gas: {e}")),
+        }
+    };
+    let fees = async {
+        provider
+            .estimate_eip1559_fees(None)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to estimate EIP-1559 fees: {e}"))
+    };
+    let nonce = async {
+        provider
+            .get_transaction_count(from, Some(BlockNumber::Pending.into()))
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to get the sender's nonce: {e}"))
+    };
+    let (gas, (max_fee_per_gas, max_priority_fee_per_gas), nonce) =
+        futures::try_join!(gas, fees, nonce)?;
+
+    Ok(UnsignedTransaction {
+        tx_type: U64::from(2),
+        chain_id: U64::from(chain_id),
+        from,
+        to,
+        nonce,
+        gas,
+        max_fee_per_gas,
+        max_priority_fee_per_gas,
+        value,
+        data,
+    })
+}
+
+/// The approvals `(contract, calldata)` a swap needs and the swap itself, in the order they
+/// are sent, with one range of nonces reserved at send time: the approvals take the
+/// sender's pending nonce onward and the swap the nonce after them. Approval gas is the
+/// node's estimate plus [`GAS_LIMIT_BUFFER_PERCENT`], and approvals pay the swap's fees
+pub async fn fill_swap_transactions<M: Middleware>(
+    provider: &M,
+    swap: &UnsignedTransaction,
+    approvals: &[(Address, Bytes)],
+) -> Result<Vec<UnsignedTransaction>> {
+    let first_nonce = provider
+        .get_transaction_count(swap.from, Some(BlockNumber::Pending.into()))
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to get the sender's nonce: {e}"))?;
+
+    let mut transactions = Vec::with_capacity(approvals.len() + 1);
+    for (i, (to, data)) in approvals.iter().enumerate() {
+        let request = Eip1559TransactionRequest::new()
+            .from(swap.from)
+            .to(*to)
+            .data(data.clone())
+            .chain_id(swap.chain_id.as_u64());
+        let gas = match provider.estimate_gas(&request.into(), None).await {
+            Ok(gas) => gas + gas * GAS_LIMIT_BUFFER_PERCENT / 100,
+            // Estimated against the current state, which the earlier approval changes
+            Err(e) if approvals[..i].iter().any(|(earlier, _)| earlier == to) => {
+                warn!(
+                    "Approval {} couldn't be estimated after an earlier one to {:?} ({e}), using a gas limit of {DEPENDENT_APPROVAL_GAS_LIMIT}",
+                    i + 1,
+                    to
+                );
+                U256::from(DEPENDENT_APPROVAL_GAS_LIMIT)
+            }
+            Err(e) => anyhow::bail!("Failed to estimate the gas of approval {}: {e}", i + 1),
+        };
+
+        transactions.push(UnsignedTransaction {
+            tx_type: swap.tx_type,
+            chain_id: swap.chain_id,
+            from: swap.from,
+            to: *to,
+            nonce: first_nonce + i,
+            gas,
+            max_fee_per_gas: swap.max_fee_per_gas,
+            max_priority_fee_per_gas: swap.max_priority_fee_per_gas,
+            value: U256::zero(),
+            data: data.clone(),
+        });
+    }
+
+    transactions.push(UnsignedTransaction {
+        nonce: first_nonce + approvals.len(),
+        ..swap.clone()
+    });
+    Ok(transactions)
+}
+
+impl UnsignedTransaction {
+    /// The transaction in the form ethers signs
+    pub fn to_typed_transaction(&self) -> TypedTransaction {
+        Eip1559TransactionRequest::new()
+            .from(self.from)
+            .to(self.to)
+            .nonce(self.nonce)
+            .gas(self.gas)
+            .max_fee_per_gas(self.max_fee_per_gas)
+            .max_priority_fee_per_gas(self.max_priority_fee_per_gas)
+            .value(self.value)
+            .data(self.data.clone())
+            .chain_id(self.chain_id)
+            .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SENDER: &str = "0x70997970C51812dc3A010C7d01b50e0d17dc79C8";
+    const TOKEN: &str = "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48";
+    const PERMIT2: &str = "0x000000000022D473030F116dDEE9F6B43aC78BA3";
+    const ROUTER: &str = "0x3fC91A3afd70395Cd496C647d5a6CC9D4B2b7FAD";
+
+    fn address(address: &str) -> Address {
+        address.parse().unwrap()
+    }
+
+    fn swap() -> UnsignedTransaction {
+        UnsignedTransaction {
+            tx_type: U64::from(2),
+            chain_id: U64::from(1),
+            from: address(SENDER),
+            to: address(ROUTER),
+            // Built before any approvals were sent, and stale by send time
+            nonce: U256::from(3),
+            gas: U256::from(250_000),
+            max_fee_per_gas: U256::from(20_000_000_000u64),
+            max_priority_fee_per_gas: U256::from(1_000_000_000),
+            value: U256::zero(),
+            data: Bytes::from(vec![0x35, 0x93, 0x56, 0x4c]),
+        }
+    }
+
+    fn approve(to: &str) -> (Address, Bytes) {
+        (address(to), Bytes::from(vec![0x09, 0x5e, 0xa7, 0xb3]))
+    }
+
+    #[tokio::test]
+    async fn approvals_and_swap_take_consecutive_nonces() {
+        let (provider, mock) = Provider::mocked();
+        // Responses are popped from the back, so they are pushed in reverse order
+        mock.push(U256::from(50_000)).unwrap();
+        mock.push(U256::from(40_000)).unwrap();
+        mock.push(U256::from(7)).unwrap();
+
+        let swap = swap();
+        let transactions =
+            fill_swap_transactions(&provider, &swap, &[approve(TOKEN), approve(PERMIT2)])
+                .await
+                .unwrap();
+
+        let nonces: Vec<u64> = transactions.iter().map(|tx| tx.nonce.as_u64()).collect();
+        let gas: Vec<u64> = transactions.iter().map(|tx| tx.gas.as_u64()).collect();
+        assert_eq!(nonces, [7, 8, 9]);
+        assert_eq!(gas, [48_000, 60_000, 250_000]);
+        assert_eq!(transactions[0].to, address(TOKEN));
+        assert_eq!(transactions[1].to, address(PERMIT2));
+        assert_eq!(transactions[2].data, swap.data);
+        assert!(transactions
+            .iter()
+            .all(|tx| tx.max_fee_per_gas == swap.max_fee_per_gas
+                && tx.max_priority_fee_per_gas == swap.max_priority_fee_per_gas));
+
+        mock.assert_request(
+            "eth_getTransactionCount",
+            (address(SENDER), BlockNumber::Pending),
+        )
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn swap_without_approvals_takes_the_pending_nonce() {
+        let (provider, mock) = Provider::mocked();
+        mock.push(U256::from(12)).unwrap();
+
+        let transactions = fill_swap_transactions(&provider, &swap(), &[])
+            .await
+            .unwrap();
+
+        assert_eq!(transactions.len(), 1);
+        assert_eq!(transactions[0].nonce, U256::from(12));
+        assert_eq!(transactions[0].gas, U256::from(250_000));
+    }
+
+    #[tokio::test]
+    async fn dependent_approval_falls_back_to_a_fixed_gas_limit() {
+        let (provider, mock) = Provider::mocked();
+        // Setting the allowance reverts in estimation until the reset to zero is mined
+        mock.push(false).unwrap();
+        mock.push(U256::from(30_000)).unwrap();
+        mock.push(U256::from(7)).unwrap();
+
+        let transactions =
+            fill_swap_transactions(&provider, &swap(), &[approve(TOKEN), approve(TOKEN)])
+                .await
+                .unwrap();
+
+        let gas: Vec<u64> = transactions.iter().map(|tx| tx.gas.as_u64()).collect();
+        assert_eq!(gas, [36_000, DEPENDENT_APPROVAL_GAS_LIMIT, 250_000]);
+    }
+
+    #[tokio::test]
+    async fn independent_approval_that_fails_estimation_is_an_error() {
+        let (provider, mock) = Provider::mocked();
+        mock.push(false).unwrap();
+        mock.push(U256::from(7)).unwrap();
+
+        let result = fill_swap_transactions(&provider, &swap(), &[approve(TOKEN)]).await;
+        assert!(result.is_err());
+    }
+
+    /// Anvil's second test account, `SENDER`
+    fn wallet() -> Wallet {
+        let key = "59c6995e998f97a5a0044966f0945389dc9e86dae88c7a8412f4603b6b78690d";
+        Wallet {
+            accounts: vec![key.parse::<LocalWallet>().unwrap()],
+        }
+    }
+
+    #[tokio::test]
+    async fn failed_broadcast_may_have_gone_out() {
+        let (provider, mock) = Provider::mocked();
+        // No answer from the node
+        mock.push(false).unwrap();
+
+        let error = wallet()
+            .send_transaction(&provider, &swap())
+            .await
+            .unwrap_err();
+        let SendError::Unknown { hash, .. } = error else {
+            panic!("Unexpected error: {error}");
+        };
+        mock.push(hash).unwrap();
+        let sent = wallet().send_transaction(&provider, &swap()).await.unwrap();
+        assert_eq!(sent, hash);
+    }
+
+    #[tokio::test]
+    async fn unknown_sender_is_not_sent() {
+        let (provider, _mock) = Provider::mocked();
+        let mut tx = swap();
+        tx.from = address(ROUTER);
+
+        let error = wallet().send_transaction(&provider, &tx).await.unwrap_err();
+        assert!(!error.may_be_broadcast());
+    }
+}